@@ -0,0 +1,93 @@
+// Times the level generators in src/dungeon.rs against the database's, on maps of growing size,
+// first the layout alone and then with the room and its tiles inserted. Run with DATABASE_URL
+// pointing at a migrated database:
+//   DATABASE_URL=postgres://postgres@localhost/mpd cargo bench
+// Everything is inserted in a transaction that is rolled back, so nothing is left behind.
+
+use std::time::{Duration, Instant};
+
+use mpdungeon2::dungeon::{self, LevelType, Options};
+use sqlx::PgPool;
+
+const SIZES: [(i32, i32); 4] = [(40, 20), (80, 40), (120, 60), (160, 80)];
+const RUNS: i64 = 5;
+
+// The mean time of RUNS calls, with a different seed each time
+async fn mean<F: Future<Output = ()>>(mut run: impl FnMut(i64) -> F) -> Duration {
+    let start = Instant::now();
+    for seed in 1..=RUNS {
+        run(seed).await;
+    }
+    start.elapsed() / RUNS as u32
+}
+
+#[tokio::main]
+async fn main() {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is needed to time the database");
+    let db = PgPool::connect(&url).await.unwrap();
+    let options = Options::default();
+
+    println!(
+        "{:<5} {:>8} {:>12} {:>12} {:>12} {:>12}",
+        "type", "size", "rust layout", "sql layout", "rust insert", "sql insert"
+    );
+    for level_type in [LevelType::Bsp, LevelType::Cave, LevelType::Maze] {
+        for (width, height) in SIZES {
+            let rust_layout = mean(|seed| {
+                let options = &options;
+                async move {
+                    dungeon::generate(level_type, width, height, seed, options, &[]).unwrap();
+                }
+            })
+            .await;
+            let sql_layout = mean(|seed| {
+                let db = &db;
+                async move {
+                    sqlx::query("SELECT template FROM level_layout($1, $2, $3, $4, '{}')")
+                        .bind(level_type.name())
+                        .bind(width)
+                        .bind(height)
+                        .bind(seed)
+                        .execute(db)
+                        .await
+                        .unwrap();
+                }
+            })
+            .await;
+
+            let mut tx = db.begin().await.unwrap();
+            let start = Instant::now();
+            for seed in 1..=RUNS {
+                let grid =
+                    dungeon::generate(level_type, width, height, seed, &options, &[]).unwrap();
+                dungeon::insert(&mut *tx, level_type, seed, &options, &grid)
+                    .await
+                    .unwrap();
+            }
+            let rust_insert = start.elapsed() / RUNS as u32;
+            let start = Instant::now();
+            for seed in 1..=RUNS {
+                sqlx::query("SELECT room_id FROM generate_level($1, $2, $3, $4)")
+                    .bind(level_type.name())
+                    .bind(width)
+                    .bind(height)
+                    .bind(seed)
+                    .execute(&mut *tx)
+                    .await
+                    .unwrap();
+            }
+            let sql_insert = start.elapsed() / RUNS as u32;
+            tx.rollback().await.unwrap();
+
+            println!(
+                "{:<5} {:>8} {:>12.1?} {:>12.1?} {:>12.1?} {:>12.1?}",
+                level_type.name(),
+                format!("{}x{}", width, height),
+                rust_layout,
+                sql_layout,
+                rust_insert,
+                sql_insert
+            );
+        }
+    }
+}
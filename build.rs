@@ -0,0 +1,4 @@
+// The tests embed the migrations, rebuild them whenever one is added or changed
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,11 @@
+-- Only the names up gave them, not ones they had before
+DELETE FROM names n
+USING llms l, species s
+WHERE n.entity_id=l.entity_id AND s.entity_id=l.entity_id AND n.name=initcap(s.species);
+
+DROP INDEX IF EXISTS messages_room;
+DROP INDEX IF EXISTS messages_recipient;
+DROP INDEX IF EXISTS messages_speaker;
+
+ALTER TABLE messages DROP COLUMN room_id;
+ALTER TABLE messages DROP COLUMN channel;
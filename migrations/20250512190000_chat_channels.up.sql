@@ -0,0 +1,13 @@
+ALTER TABLE messages ADD COLUMN channel TEXT NOT NULL DEFAULT 'direct' CHECK (channel IN ('direct', 'room', 'world'));
+ALTER TABLE messages ADD COLUMN room_id INTEGER;
+
+CREATE INDEX messages_speaker ON messages(speaker);
+CREATE INDEX messages_recipient ON messages(recipient);
+CREATE INDEX messages_room ON messages(room_id) WHERE channel = 'room';
+
+-- Tells are addressed by name, so anything players can talk to needs one
+INSERT INTO names (entity_id, name)
+SELECT l.entity_id, initcap(s.species)
+FROM llms l
+INNER JOIN species s ON s.entity_id=l.entity_id
+WHERE NOT EXISTS (SELECT 1 FROM names n WHERE n.entity_id=l.entity_id);
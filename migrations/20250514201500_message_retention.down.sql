@@ -0,0 +1,5 @@
+SELECT cron.unschedule('prune_messages');
+
+DROP FUNCTION IF EXISTS prune_messages;
+
+DROP INDEX IF EXISTS messages_sent_at;
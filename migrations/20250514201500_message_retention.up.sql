@@ -0,0 +1,16 @@
+CREATE INDEX messages_sent_at ON messages(sent_at);
+
+-- Chat older than `keep` is dropped, including anything the responder never got around to
+CREATE OR REPLACE FUNCTION prune_messages(keep INTERVAL DEFAULT '30 days')
+RETURNS INTEGER AS $$
+DECLARE
+  pruned INTEGER;
+BEGIN
+  DELETE FROM messages
+  WHERE sent_at < NOW() - keep;
+  GET DIAGNOSTICS pruned = ROW_COUNT;
+  RETURN pruned;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT cron.schedule('prune_messages', '0 4 * * *', 'SELECT prune_messages()');
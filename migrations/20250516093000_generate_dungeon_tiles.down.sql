@@ -0,0 +1,2 @@
+DROP FUNCTION IF EXISTS generate_dungeon(INT, INT, INT, INT, INT);
+ALTER FUNCTION generate_dungeon_layout(INT, INT, INT, INT, INT) RENAME TO generate_dungeon;
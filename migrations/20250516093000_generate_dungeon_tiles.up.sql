@@ -0,0 +1,19 @@
+-- generate_dungeon never made any tiles: the CTE calling create_room_template isn't referenced by
+-- the final SELECT, so Postgres never ran it. Keep the layout and apply its template afterwards.
+ALTER FUNCTION generate_dungeon(INT, INT, INT, INT, INT) RENAME TO generate_dungeon_layout;
+
+CREATE FUNCTION generate_dungeon(
+    width INT,
+    height INT,
+    room_count INT DEFAULT NULL,
+    min_room_size INT DEFAULT 3,
+    max_room_size INT DEFAULT 20
+) RETURNS TABLE (
+    room_id INT,
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.room_id, l.debug_output, l.template
+FROM generate_dungeon_layout(width, height, room_count, min_room_size, max_room_size) l
+CROSS JOIN LATERAL create_room_template(l.room_id, l.template);
+$$ LANGUAGE SQL;
@@ -0,0 +1,5 @@
+DROP FUNCTION IF EXISTS run_due_jobs;
+DROP FUNCTION IF EXISTS unschedule_job;
+DROP FUNCTION IF EXISTS schedule_job;
+
+DROP TABLE IF EXISTS jobs;
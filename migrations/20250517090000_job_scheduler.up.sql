@@ -0,0 +1,63 @@
+-- Recurring jobs for databases without pg_cron, run by `mpdungeon2 jobs` calling run_due_jobs()
+CREATE TABLE jobs (
+  job_name TEXT PRIMARY KEY,
+  every INTERVAL NOT NULL,
+  command TEXT NOT NULL,
+  last_run TIMESTAMPTZ
+);
+
+-- Schedules with pg_cron when it is installed, going by `cron_schedule`, and on the jobs table
+-- otherwise, going by `every`
+CREATE OR REPLACE FUNCTION schedule_job(job_name TEXT, cron_schedule TEXT, every INTERVAL, command TEXT)
+RETURNS VOID AS $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.schedule(job_name, cron_schedule, command);
+  ELSE
+    INSERT INTO jobs (job_name, every, command)
+    VALUES (job_name, every, command)
+    ON CONFLICT ON CONSTRAINT jobs_pkey DO UPDATE
+    SET every=EXCLUDED.every, command=EXCLUDED.command;
+  END IF;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION unschedule_job(name TEXT)
+RETURNS VOID AS $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.unschedule(name) FROM cron.job WHERE jobname = name;
+  END IF;
+  DELETE FROM jobs WHERE job_name = name;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Runs every job that is due and returns how many ran. Only one caller runs jobs at a time, the
+-- others get 0, and a failing job is logged rather than failing the caller.
+CREATE OR REPLACE FUNCTION run_due_jobs()
+RETURNS INTEGER AS $$
+DECLARE
+  job RECORD;
+  ran INTEGER := 0;
+BEGIN
+  IF NOT pg_try_advisory_xact_lock(hashtext('run_due_jobs')) THEN
+    RETURN 0;
+  END IF;
+  FOR job IN
+    SELECT * FROM jobs
+    WHERE last_run IS NULL OR last_run + every <= NOW()
+    FOR UPDATE SKIP LOCKED
+  LOOP
+    UPDATE jobs SET last_run = NOW() WHERE jobs.job_name = job.job_name;
+    BEGIN
+      EXECUTE job.command;
+    EXCEPTION WHEN OTHERS THEN
+      RAISE WARNING 'job % failed: %', job.job_name, SQLERRM;
+    END;
+    ran := ran + 1;
+  END LOOP;
+  RETURN ran;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT schedule_job('prune_messages', '0 4 * * *', '1 day', 'SELECT prune_messages()');
@@ -0,0 +1,10 @@
+SELECT unschedule_job('respond_messages');
+
+-- Back to how init left it, which is also where the function comes from
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.schedule('respond_messages', '1 seconds', 'SELECT respond_messages()');
+  END IF;
+END;
+$$;
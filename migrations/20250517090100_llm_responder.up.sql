@@ -0,0 +1,20 @@
+-- The innkeeper answers tells through an LLM, using the openai functions from
+-- postgres/scripts/03_openai.sql. init created respond_messages() everywhere, this only changes
+-- how it is scheduled, and only where those are installed.
+
+-- init scheduled the responder with pg_cron wherever it ran, openai functions or not
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.unschedule(jobname) FROM cron.job WHERE jobname = 'respond_messages';
+  END IF;
+END;
+$$;
+
+DO $$
+BEGIN
+  IF to_regprocedure('openai.prompt(text, text, text)') IS NOT NULL THEN
+    PERFORM schedule_job('respond_messages', '1 seconds', '1 second', 'SELECT respond_messages()');
+  END IF;
+END;
+$$;
@@ -0,0 +1,6 @@
+SELECT unschedule_job('end_stale_sessions');
+
+DROP FUNCTION IF EXISTS end_stale_sessions;
+DROP FUNCTION IF EXISTS session_timeout;
+
+DROP TABLE IF EXISTS sessions;
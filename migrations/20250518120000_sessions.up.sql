@@ -0,0 +1,27 @@
+-- At most one client plays each character. The client heartbeats while it is connected, and a
+-- session that stops heartbeating is abandoned and may be replaced without a takeover.
+CREATE TABLE sessions (
+  entity_id INTEGER PRIMARY KEY,
+  token TEXT NOT NULL,
+  started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_seen TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE OR REPLACE FUNCTION session_timeout()
+RETURNS INTERVAL AS $$
+  SELECT INTERVAL '30 seconds';
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION end_stale_sessions()
+RETURNS INTEGER AS $$
+DECLARE
+  ended INTEGER;
+BEGIN
+  DELETE FROM sessions
+  WHERE last_seen < NOW() - session_timeout();
+  GET DIAGNOSTICS ended = ROW_COUNT;
+  RETURN ended;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT schedule_job('end_stale_sessions', '* * * * *', '1 minute', 'SELECT end_stale_sessions()');
@@ -0,0 +1,28 @@
+-- The roles are left behind, other databases in the cluster may still be using them
+DROP FUNCTION schema_migrations();
+DROP FUNCTION chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT);
+DROP FUNCTION world_view(TEXT);
+DROP FUNCTION tell(TEXT, TEXT, TEXT);
+DROP FUNCTION shout(TEXT, TEXT);
+DROP FUNCTION say_room(TEXT, TEXT);
+DROP FUNCTION submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER);
+DROP FUNCTION heartbeat(TEXT);
+DROP FUNCTION login(TEXT, TEXT, BOOLEAN);
+
+ALTER FUNCTION room_tick() SECURITY INVOKER RESET search_path;
+GRANT EXECUTE ON FUNCTION room_tick(), run_due_jobs() TO PUBLIC;
+
+DROP POLICY messages_sent ON messages;
+DROP POLICY messages_seen ON messages;
+ALTER TABLE messages DISABLE ROW LEVEL SECURITY;
+DROP POLICY positions_registered ON positions;
+DROP POLICY positions_seen ON positions;
+ALTER TABLE positions DISABLE ROW LEVEL SECURITY;
+
+DROP FUNCTION current_room();
+DROP FUNCTION current_player();
+
+REVOKE USAGE ON SEQUENCE entities_idx FROM mpd_game;
+REVOKE ALL ON names, positions, species, commands, players, rooms, hps, portals, weights, messages, sessions FROM mpd_game;
+REVOKE USAGE ON SCHEMA public FROM mpd_player, mpd_game;
+GRANT CREATE ON SCHEMA public TO PUBLIC;
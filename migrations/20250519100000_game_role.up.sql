@@ -0,0 +1,311 @@
+-- Clients connect as mpd_player, which can do nothing but call the functions below. They run as
+-- mpd_game, which can reach the tables, but only through row level security scoped to the
+-- player whose session token was passed in.
+--
+-- Roles belong to the whole cluster rather than this database, so they may already be there.
+-- mpd_player can only log in once it has a password, taken from the mpd.player_password setting
+-- when the migration runs (`mpdungeon2 migrate up --player-password`) or given by an admin.
+DO $$
+DECLARE
+  player_password TEXT := current_setting('mpd.player_password', true);
+BEGIN
+  BEGIN
+    CREATE ROLE mpd_player NOLOGIN;
+  EXCEPTION WHEN duplicate_object THEN NULL;
+  END;
+  IF player_password <> '' THEN
+    EXECUTE format('ALTER ROLE mpd_player LOGIN PASSWORD %L', player_password);
+  END IF;
+END;
+$$;
+
+DO $$
+BEGIN
+  CREATE ROLE mpd_game NOLOGIN;
+EXCEPTION WHEN duplicate_object THEN NULL;
+END;
+$$;
+
+REVOKE CREATE ON SCHEMA public FROM PUBLIC;
+GRANT USAGE ON SCHEMA public TO mpd_player, mpd_game;
+
+GRANT SELECT ON names, positions, species, commands, players, rooms, hps, portals, weights, messages, sessions TO mpd_game;
+GRANT INSERT ON names, positions, species, commands, players, hps, messages, sessions TO mpd_game;
+GRANT UPDATE ON commands, sessions TO mpd_game;
+GRANT USAGE ON SEQUENCE entities_idx TO mpd_game;
+
+-- The player the current transaction is acting for, from the token the API function was given
+CREATE OR REPLACE FUNCTION current_player()
+RETURNS INTEGER AS $$
+  SELECT entity_id FROM sessions WHERE token = current_setting('mpd.session', true);
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Reads positions past its own row level security, the policies on it depend on this
+CREATE OR REPLACE FUNCTION current_room()
+RETURNS INTEGER AS $$
+  SELECT room_id FROM positions WHERE entity_id = current_player();
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER TABLE positions ENABLE ROW LEVEL SECURITY;
+CREATE POLICY positions_seen ON positions FOR SELECT TO mpd_game
+USING (entity_id = current_player() OR room_id = current_player() OR room_id = current_room());
+CREATE POLICY positions_registered ON positions FOR INSERT TO mpd_game
+WITH CHECK (entity_id = current_player());
+
+ALTER TABLE messages ENABLE ROW LEVEL SECURITY;
+CREATE POLICY messages_seen ON messages FOR SELECT TO mpd_game
+USING (
+  speaker = current_player() OR
+  recipient = current_player() OR
+  (channel = 'room' AND room_id = current_room()) OR
+  channel = 'world'
+);
+CREATE POLICY messages_sent ON messages FOR INSERT TO mpd_game
+WITH CHECK (speaker = current_player());
+
+-- Logs in, registering the name if it is new. No row if the password is wrong or the name isn't
+-- a player's, and a NULL token if someone is playing the character and takeover is false.
+CREATE OR REPLACE FUNCTION login(username TEXT, password TEXT, takeover BOOLEAN)
+RETURNS TABLE (entity_id INTEGER, token TEXT) AS $$
+#variable_conflict use_column
+DECLARE
+  player_id INTEGER;
+  authenticated BOOLEAN;
+  registering BOOLEAN := false;
+  new_token TEXT;
+BEGIN
+  SELECT n.entity_id, p.entity_id IS NOT NULL
+  INTO player_id, authenticated
+  FROM names n
+  LEFT JOIN players p ON p.entity_id=n.entity_id AND p.password=login.password
+  WHERE n.name=username
+  LIMIT 1;
+
+  IF player_id IS NULL THEN
+    registering := true;
+    INSERT INTO names (name) VALUES (username) RETURNING names.entity_id INTO player_id;
+    INSERT INTO players (entity_id, password) VALUES (player_id, login.password);
+    INSERT INTO hps (entity_id, hp, maxhp) VALUES (player_id, 10, 10);
+    INSERT INTO species (entity_id, species) VALUES (player_id, 'human');
+  ELSIF NOT authenticated THEN
+    RETURN;
+  END IF;
+
+  INSERT INTO sessions (entity_id, token)
+  VALUES (player_id, gen_random_uuid()::text)
+  ON CONFLICT (entity_id) DO UPDATE
+  SET
+    token=EXCLUDED.token,
+    started_at=NOW(),
+    last_seen=NOW()
+  WHERE takeover OR sessions.last_seen < NOW() - session_timeout()
+  RETURNING sessions.token INTO new_token;
+
+  IF registering THEN
+    PERFORM set_config('mpd.session', new_token, true);
+    INSERT INTO positions (entity_id, x, y, room_id)
+    SELECT player_id, 1, 1, r.entity_id
+    FROM rooms r
+    WHERE r.landing_zone IS TRUE;
+  END IF;
+
+  RETURN QUERY SELECT player_id, new_token;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False once the session has been taken over
+CREATE OR REPLACE FUNCTION heartbeat(session TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  UPDATE sessions SET last_seen=NOW() WHERE token=session;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False if the session is no longer the character's
+CREATE OR REPLACE FUNCTION submit_command(session TEXT, command_type TEXT, x SMALLINT, y SMALLINT, target INTEGER)
+RETURNS BOOLEAN AS $$
+#variable_conflict use_column
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO commands (entity_id, command_type, x, y, target)
+  SELECT current_player(), submit_command.command_type, submit_command.x, submit_command.y, submit_command.target
+  WHERE current_player() IS NOT NULL
+  ON CONFLICT(entity_id) DO UPDATE
+  SET
+    command_type=EXCLUDED.command_type,
+    x=EXCLUDED.x,
+    y=EXCLUDED.y,
+    target=EXCLUDED.target;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Commands set off room_tick as the player's mpd_game, but a tick moves everything in the room
+-- (and travellers out of it), so it has to see past the player's row level security
+ALTER FUNCTION room_tick() SECURITY DEFINER SET search_path = public, pg_temp;
+
+CREATE OR REPLACE FUNCTION say_room(session TEXT, message TEXT)
+RETURNS VOID AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO messages (speaker, message, channel, room_id)
+  SELECT current_player(), say_room.message, 'room', current_room()
+  WHERE current_player() IS NOT NULL;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+CREATE OR REPLACE FUNCTION shout(session TEXT, message TEXT)
+RETURNS VOID AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO messages (speaker, message, channel)
+  SELECT current_player(), shout.message, 'world'
+  WHERE current_player() IS NOT NULL;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False if there is no one by that name to tell
+CREATE OR REPLACE FUNCTION tell(session TEXT, recipient TEXT, message TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO messages (speaker, recipient, message, channel)
+  SELECT DISTINCT ON (n.entity_id) current_player(), n.entity_id, tell.message, 'direct'
+  FROM names n
+  -- Rooms have names too, but can't be spoken to
+  WHERE
+    lower(n.name)=lower(tell.recipient) AND
+    NOT EXISTS (SELECT 1 FROM rooms r WHERE r.entity_id=n.entity_id) AND
+    current_player() IS NOT NULL;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- The player's room and everything they carry
+CREATE OR REPLACE FUNCTION world_view(session TEXT)
+RETURNS TABLE (
+  entity_id INTEGER,
+  x SMALLINT,
+  y SMALLINT,
+  room_id INTEGER,
+  species TEXT,
+  name TEXT,
+  command_type TEXT,
+  command_x SMALLINT,
+  command_y SMALLINT,
+  hp INTEGER,
+  maxhp INTEGER,
+  ends INTEGER[],
+  weight INTEGER
+) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT
+    p.entity_id,
+    p.x,
+    p.y,
+    p.room_id,
+    s.species,
+    n.name,
+    c.command_type,
+    c.x,
+    c.y,
+    h.hp,
+    h.maxhp,
+    portals.ends,
+    w.weight
+  FROM positions st
+  INNER JOIN positions p ON (p.room_id=st.room_id OR p.room_id=st.entity_id)
+  LEFT JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN names n ON n.entity_id=p.entity_id
+  LEFT JOIN commands c ON c.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  LEFT JOIN weights w ON w.entity_id=p.entity_id
+  CROSS JOIN LATERAL (SELECT ARRAY_AGG(end_entity_id) ends FROM portals WHERE start_entity_id=p.entity_id) portals
+  WHERE st.entity_id=current_player()
+  ORDER BY p.entity_id ASC;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- A page of the chat the player can see, newest first, before and/or after a point in time
+CREATE OR REPLACE FUNCTION chat_view(session TEXT, before TIMESTAMPTZ, after TIMESTAMPTZ, page_size BIGINT)
+RETURNS TABLE (
+  sender TEXT,
+  receiver TEXT,
+  channel TEXT,
+  message TEXT,
+  sent_at TIMESTAMPTZ
+) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  -- The policy on messages picks out what this player may read
+  RETURN QUERY
+  SELECT
+    COALESCE(sn.name, ss.species),
+    COALESCE(rn.name, rs.species),
+    m.channel,
+    m.message,
+    m.sent_at
+  FROM messages m
+  LEFT JOIN names sn ON sn.entity_id=m.speaker
+  LEFT JOIN species ss ON ss.entity_id=m.speaker
+  LEFT JOIN names rn ON rn.entity_id=m.recipient
+  LEFT JOIN species rs ON rs.entity_id=m.recipient
+  WHERE
+    current_player() IS NOT NULL AND
+    (before IS NULL OR m.sent_at < before) AND
+    (after IS NULL OR m.sent_at > after)
+  ORDER BY m.sent_at DESC
+  LIMIT page_size;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Lets clients check the schema without being able to read sqlx's table themselves
+CREATE OR REPLACE FUNCTION schema_migrations()
+RETURNS TABLE (version BIGINT, checksum BYTEA) AS $$
+  SELECT version, checksum FROM _sqlx_migrations WHERE success ORDER BY version;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- The player-facing functions act as mpd_game so the row level security above applies to them.
+-- The rest stay with the migration's owner, which sees everything.
+ALTER FUNCTION login(TEXT, TEXT, BOOLEAN) OWNER TO mpd_game;
+ALTER FUNCTION heartbeat(TEXT) OWNER TO mpd_game;
+ALTER FUNCTION submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER) OWNER TO mpd_game;
+ALTER FUNCTION say_room(TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION shout(TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION tell(TEXT, TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION world_view(TEXT) OWNER TO mpd_game;
+ALTER FUNCTION chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT) OWNER TO mpd_game;
+
+-- Functions can be called by anyone unless revoked, which matters for the ones that run as
+-- someone else
+REVOKE EXECUTE ON FUNCTION
+  current_player(),
+  current_room(),
+  login(TEXT, TEXT, BOOLEAN),
+  heartbeat(TEXT),
+  submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER),
+  room_tick(),
+  say_room(TEXT, TEXT),
+  shout(TEXT, TEXT),
+  tell(TEXT, TEXT, TEXT),
+  world_view(TEXT),
+  chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT),
+  schema_migrations(),
+  run_due_jobs()
+FROM PUBLIC;
+
+GRANT EXECUTE ON FUNCTION current_player(), current_room() TO mpd_game;
+GRANT EXECUTE ON FUNCTION
+  login(TEXT, TEXT, BOOLEAN),
+  heartbeat(TEXT),
+  submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER),
+  say_room(TEXT, TEXT),
+  shout(TEXT, TEXT),
+  tell(TEXT, TEXT, TEXT),
+  world_view(TEXT),
+  chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT),
+  schema_migrations()
+TO mpd_player;
@@ -0,0 +1,240 @@
+DROP FUNCTION api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT);
+DROP FUNCTION api.snapshot(TEXT);
+DROP FUNCTION api.say(TEXT, TEXT, TEXT, TEXT);
+DROP FUNCTION api.submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER);
+DROP FUNCTION api.heartbeat(TEXT);
+DROP FUNCTION api.register(TEXT, TEXT);
+DROP FUNCTION api.login(TEXT, TEXT, BOOLEAN);
+DROP FUNCTION start_session(INTEGER, BOOLEAN);
+DROP TYPE api.chat_line;
+DROP TYPE api.entity;
+DROP TYPE api.session;
+DROP FUNCTION api.version();
+DROP SCHEMA api;
+
+-- The functions the game_role migration gave clients
+-- Logs in, registering the name if it is new. No row if the password is wrong or the name isn't
+-- a player's, and a NULL token if someone is playing the character and takeover is false.
+CREATE OR REPLACE FUNCTION login(username TEXT, password TEXT, takeover BOOLEAN)
+RETURNS TABLE (entity_id INTEGER, token TEXT) AS $$
+#variable_conflict use_column
+DECLARE
+  player_id INTEGER;
+  authenticated BOOLEAN;
+  registering BOOLEAN := false;
+  new_token TEXT;
+BEGIN
+  SELECT n.entity_id, p.entity_id IS NOT NULL
+  INTO player_id, authenticated
+  FROM names n
+  LEFT JOIN players p ON p.entity_id=n.entity_id AND p.password=login.password
+  WHERE n.name=username
+  LIMIT 1;
+
+  IF player_id IS NULL THEN
+    registering := true;
+    INSERT INTO names (name) VALUES (username) RETURNING names.entity_id INTO player_id;
+    INSERT INTO players (entity_id, password) VALUES (player_id, login.password);
+    INSERT INTO hps (entity_id, hp, maxhp) VALUES (player_id, 10, 10);
+    INSERT INTO species (entity_id, species) VALUES (player_id, 'human');
+  ELSIF NOT authenticated THEN
+    RETURN;
+  END IF;
+
+  INSERT INTO sessions (entity_id, token)
+  VALUES (player_id, gen_random_uuid()::text)
+  ON CONFLICT (entity_id) DO UPDATE
+  SET
+    token=EXCLUDED.token,
+    started_at=NOW(),
+    last_seen=NOW()
+  WHERE takeover OR sessions.last_seen < NOW() - session_timeout()
+  RETURNING sessions.token INTO new_token;
+
+  IF registering THEN
+    PERFORM set_config('mpd.session', new_token, true);
+    INSERT INTO positions (entity_id, x, y, room_id)
+    SELECT player_id, 1, 1, r.entity_id
+    FROM rooms r
+    WHERE r.landing_zone IS TRUE;
+  END IF;
+
+  RETURN QUERY SELECT player_id, new_token;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False once the session has been taken over
+CREATE OR REPLACE FUNCTION heartbeat(session TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  UPDATE sessions SET last_seen=NOW() WHERE token=session;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False if the session is no longer the character's
+CREATE OR REPLACE FUNCTION submit_command(session TEXT, command_type TEXT, x SMALLINT, y SMALLINT, target INTEGER)
+RETURNS BOOLEAN AS $$
+#variable_conflict use_column
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO commands (entity_id, command_type, x, y, target)
+  SELECT current_player(), submit_command.command_type, submit_command.x, submit_command.y, submit_command.target
+  WHERE current_player() IS NOT NULL
+  ON CONFLICT(entity_id) DO UPDATE
+  SET
+    command_type=EXCLUDED.command_type,
+    x=EXCLUDED.x,
+    y=EXCLUDED.y,
+    target=EXCLUDED.target;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+CREATE OR REPLACE FUNCTION say_room(session TEXT, message TEXT)
+RETURNS VOID AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO messages (speaker, message, channel, room_id)
+  SELECT current_player(), say_room.message, 'room', current_room()
+  WHERE current_player() IS NOT NULL;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+CREATE OR REPLACE FUNCTION shout(session TEXT, message TEXT)
+RETURNS VOID AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO messages (speaker, message, channel)
+  SELECT current_player(), shout.message, 'world'
+  WHERE current_player() IS NOT NULL;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False if there is no one by that name to tell
+CREATE OR REPLACE FUNCTION tell(session TEXT, recipient TEXT, message TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO messages (speaker, recipient, message, channel)
+  SELECT DISTINCT ON (n.entity_id) current_player(), n.entity_id, tell.message, 'direct'
+  FROM names n
+  -- Rooms have names too, but can't be spoken to
+  WHERE
+    lower(n.name)=lower(tell.recipient) AND
+    NOT EXISTS (SELECT 1 FROM rooms r WHERE r.entity_id=n.entity_id) AND
+    current_player() IS NOT NULL;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- The player's room and everything they carry
+CREATE OR REPLACE FUNCTION world_view(session TEXT)
+RETURNS TABLE (
+  entity_id INTEGER,
+  x SMALLINT,
+  y SMALLINT,
+  room_id INTEGER,
+  species TEXT,
+  name TEXT,
+  command_type TEXT,
+  command_x SMALLINT,
+  command_y SMALLINT,
+  hp INTEGER,
+  maxhp INTEGER,
+  ends INTEGER[],
+  weight INTEGER
+) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT
+    p.entity_id,
+    p.x,
+    p.y,
+    p.room_id,
+    s.species,
+    n.name,
+    c.command_type,
+    c.x,
+    c.y,
+    h.hp,
+    h.maxhp,
+    portals.ends,
+    w.weight
+  FROM positions st
+  INNER JOIN positions p ON (p.room_id=st.room_id OR p.room_id=st.entity_id)
+  LEFT JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN names n ON n.entity_id=p.entity_id
+  LEFT JOIN commands c ON c.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  LEFT JOIN weights w ON w.entity_id=p.entity_id
+  CROSS JOIN LATERAL (SELECT ARRAY_AGG(end_entity_id) ends FROM portals WHERE start_entity_id=p.entity_id) portals
+  WHERE st.entity_id=current_player()
+  ORDER BY p.entity_id ASC;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- A page of the chat the player can see, newest first, before and/or after a point in time
+CREATE OR REPLACE FUNCTION chat_view(session TEXT, before TIMESTAMPTZ, after TIMESTAMPTZ, page_size BIGINT)
+RETURNS TABLE (
+  sender TEXT,
+  receiver TEXT,
+  channel TEXT,
+  message TEXT,
+  sent_at TIMESTAMPTZ
+) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  -- The policy on messages picks out what this player may read
+  RETURN QUERY
+  SELECT
+    COALESCE(sn.name, ss.species),
+    COALESCE(rn.name, rs.species),
+    m.channel,
+    m.message,
+    m.sent_at
+  FROM messages m
+  LEFT JOIN names sn ON sn.entity_id=m.speaker
+  LEFT JOIN species ss ON ss.entity_id=m.speaker
+  LEFT JOIN names rn ON rn.entity_id=m.recipient
+  LEFT JOIN species rs ON rs.entity_id=m.recipient
+  WHERE
+    current_player() IS NOT NULL AND
+    (before IS NULL OR m.sent_at < before) AND
+    (after IS NULL OR m.sent_at > after)
+  ORDER BY m.sent_at DESC
+  LIMIT page_size;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION login(TEXT, TEXT, BOOLEAN) OWNER TO mpd_game;
+ALTER FUNCTION heartbeat(TEXT) OWNER TO mpd_game;
+ALTER FUNCTION submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER) OWNER TO mpd_game;
+ALTER FUNCTION say_room(TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION shout(TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION tell(TEXT, TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION world_view(TEXT) OWNER TO mpd_game;
+ALTER FUNCTION chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT) OWNER TO mpd_game;
+
+REVOKE EXECUTE ON FUNCTION
+  login(TEXT, TEXT, BOOLEAN),
+  heartbeat(TEXT),
+  submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER),
+  say_room(TEXT, TEXT),
+  shout(TEXT, TEXT),
+  tell(TEXT, TEXT, TEXT),
+  world_view(TEXT),
+  chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT)
+FROM PUBLIC;
+
+GRANT EXECUTE ON FUNCTION
+  login(TEXT, TEXT, BOOLEAN),
+  heartbeat(TEXT),
+  submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER),
+  say_room(TEXT, TEXT),
+  shout(TEXT, TEXT),
+  tell(TEXT, TEXT, TEXT),
+  world_view(TEXT),
+  chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT)
+TO mpd_player;
@@ -0,0 +1,256 @@
+-- Everything a client does goes through the api schema. The tables behind it can change from one
+-- migration to the next, but these functions and the types they return only change along with
+-- api.version(), so a client built against an older schema keeps working until it is bumped.
+CREATE SCHEMA api;
+GRANT USAGE ON SCHEMA api TO mpd_player, mpd_game;
+
+DROP FUNCTION login(TEXT, TEXT, BOOLEAN);
+DROP FUNCTION heartbeat(TEXT);
+DROP FUNCTION submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER);
+DROP FUNCTION say_room(TEXT, TEXT);
+DROP FUNCTION shout(TEXT, TEXT);
+DROP FUNCTION tell(TEXT, TEXT, TEXT);
+DROP FUNCTION world_view(TEXT);
+DROP FUNCTION chat_view(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT);
+
+CREATE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 1;
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- A NULL token means someone else is playing the character
+CREATE TYPE api.session AS (
+  entity_id INTEGER,
+  token TEXT
+);
+
+CREATE TYPE api.entity AS (
+  entity_id INTEGER,
+  x SMALLINT,
+  y SMALLINT,
+  room_id INTEGER,
+  species TEXT,
+  name TEXT,
+  command_type TEXT,
+  command_x SMALLINT,
+  command_y SMALLINT,
+  hp INTEGER,
+  maxhp INTEGER,
+  ends INTEGER[],
+  weight INTEGER
+);
+
+CREATE TYPE api.chat_line AS (
+  sender TEXT,
+  receiver TEXT,
+  channel TEXT,
+  message TEXT,
+  sent_at TIMESTAMPTZ
+);
+
+-- Starts a session for the player, taking it from whoever has it if takeover is true or their
+-- client has gone quiet
+CREATE FUNCTION start_session(player_id INTEGER, takeover BOOLEAN)
+RETURNS TEXT AS $$
+  INSERT INTO sessions (entity_id, token)
+  VALUES (player_id, gen_random_uuid()::text)
+  ON CONFLICT (entity_id) DO UPDATE
+  SET
+    token=EXCLUDED.token,
+    started_at=NOW(),
+    last_seen=NOW()
+  WHERE takeover OR sessions.last_seen < NOW() - session_timeout()
+  RETURNING token;
+$$ LANGUAGE SQL;
+
+-- No row if the name isn't a player's or the password is wrong
+CREATE FUNCTION api.login(username TEXT, password TEXT, takeover BOOLEAN)
+RETURNS SETOF api.session AS $$
+DECLARE
+  player_id INTEGER;
+BEGIN
+  SELECT p.entity_id INTO player_id
+  FROM names n
+  INNER JOIN players p ON p.entity_id=n.entity_id
+  WHERE n.name=username AND p.password=login.password
+  LIMIT 1;
+  IF player_id IS NULL THEN
+    RETURN;
+  END IF;
+  RETURN NEXT ROW(player_id, start_session(player_id, takeover))::api.session;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Creates a player at the landing zone and logs them in. No row if the name is taken.
+CREATE FUNCTION api.register(username TEXT, password TEXT)
+RETURNS SETOF api.session AS $$
+DECLARE
+  player_id INTEGER;
+  token TEXT;
+BEGIN
+  IF EXISTS (SELECT 1 FROM names WHERE name=username) THEN
+    RETURN;
+  END IF;
+  INSERT INTO names (name) VALUES (username) RETURNING entity_id INTO player_id;
+  INSERT INTO players (entity_id, password) VALUES (player_id, register.password);
+  INSERT INTO hps (entity_id, hp, maxhp) VALUES (player_id, 10, 10);
+  INSERT INTO species (entity_id, species) VALUES (player_id, 'human');
+  token := start_session(player_id, false);
+  -- Positions can only be written for the session's own player
+  PERFORM set_config('mpd.session', token, true);
+  INSERT INTO positions (entity_id, x, y, room_id)
+  SELECT player_id, 1, 1, r.entity_id
+  FROM rooms r
+  WHERE r.landing_zone IS TRUE;
+  RETURN NEXT ROW(player_id, token)::api.session;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False once the session has been taken over
+CREATE FUNCTION api.heartbeat(session TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  UPDATE sessions SET last_seen=NOW() WHERE token=session;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- False if the session is no longer the character's
+CREATE FUNCTION api.submit_command(session TEXT, command_type TEXT, x SMALLINT, y SMALLINT, target INTEGER)
+RETURNS BOOLEAN AS $$
+#variable_conflict use_column
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  INSERT INTO commands (entity_id, command_type, x, y, target)
+  SELECT current_player(), submit_command.command_type, submit_command.x, submit_command.y, submit_command.target
+  WHERE current_player() IS NOT NULL
+  ON CONFLICT(entity_id) DO UPDATE
+  SET
+    command_type=EXCLUDED.command_type,
+    x=EXCLUDED.x,
+    y=EXCLUDED.y,
+    target=EXCLUDED.target;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Says something on the 'room', 'world' or 'direct' channel, the last to the recipient named.
+-- False if nothing was said, because there is no one by that name or the session has ended.
+CREATE FUNCTION api.say(session TEXT, channel TEXT, recipient TEXT, message TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  IF current_player() IS NULL THEN
+    RETURN false;
+  END IF;
+  CASE channel
+    WHEN 'room' THEN
+      INSERT INTO messages (speaker, message, channel, room_id)
+      VALUES (current_player(), say.message, 'room', current_room());
+    WHEN 'world' THEN
+      INSERT INTO messages (speaker, message, channel)
+      VALUES (current_player(), say.message, 'world');
+    WHEN 'direct' THEN
+      INSERT INTO messages (speaker, recipient, message, channel)
+      SELECT DISTINCT ON (n.entity_id) current_player(), n.entity_id, say.message, 'direct'
+      FROM names n
+      -- Rooms have names too, but can't be spoken to
+      WHERE
+        lower(n.name)=lower(say.recipient) AND
+        NOT EXISTS (SELECT 1 FROM rooms r WHERE r.entity_id=n.entity_id);
+    ELSE
+      RAISE EXCEPTION 'unknown channel %', channel;
+  END CASE;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- The player's room and everything they carry
+CREATE FUNCTION api.snapshot(session TEXT)
+RETURNS SETOF api.entity AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT
+    p.entity_id,
+    p.x,
+    p.y,
+    p.room_id,
+    s.species,
+    n.name,
+    c.command_type,
+    c.x,
+    c.y,
+    h.hp,
+    h.maxhp,
+    portals.ends,
+    w.weight
+  FROM positions st
+  INNER JOIN positions p ON (p.room_id=st.room_id OR p.room_id=st.entity_id)
+  LEFT JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN names n ON n.entity_id=p.entity_id
+  LEFT JOIN commands c ON c.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  LEFT JOIN weights w ON w.entity_id=p.entity_id
+  CROSS JOIN LATERAL (SELECT ARRAY_AGG(end_entity_id) ends FROM portals WHERE start_entity_id=p.entity_id) portals
+  WHERE st.entity_id=current_player()
+  ORDER BY p.entity_id ASC;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- A page of the chat the player can see, newest first, before and/or after a point in time
+CREATE FUNCTION api.chat(session TEXT, before TIMESTAMPTZ, after TIMESTAMPTZ, page_size BIGINT)
+RETURNS SETOF api.chat_line AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  -- The policy on messages picks out what this player may read
+  RETURN QUERY
+  SELECT
+    COALESCE(sn.name, ss.species),
+    COALESCE(rn.name, rs.species),
+    m.channel,
+    m.message,
+    m.sent_at
+  FROM messages m
+  LEFT JOIN names sn ON sn.entity_id=m.speaker
+  LEFT JOIN species ss ON ss.entity_id=m.speaker
+  LEFT JOIN names rn ON rn.entity_id=m.recipient
+  LEFT JOIN species rs ON rs.entity_id=m.recipient
+  WHERE
+    current_player() IS NOT NULL AND
+    (before IS NULL OR m.sent_at < before) AND
+    (after IS NULL OR m.sent_at > after)
+  ORDER BY m.sent_at DESC
+  LIMIT page_size;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION start_session(INTEGER, BOOLEAN) OWNER TO mpd_game;
+ALTER FUNCTION api.login(TEXT, TEXT, BOOLEAN) OWNER TO mpd_game;
+ALTER FUNCTION api.register(TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION api.heartbeat(TEXT) OWNER TO mpd_game;
+ALTER FUNCTION api.submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER) OWNER TO mpd_game;
+ALTER FUNCTION api.say(TEXT, TEXT, TEXT, TEXT) OWNER TO mpd_game;
+ALTER FUNCTION api.snapshot(TEXT) OWNER TO mpd_game;
+ALTER FUNCTION api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT) OWNER TO mpd_game;
+
+REVOKE EXECUTE ON FUNCTION
+  start_session(INTEGER, BOOLEAN),
+  api.login(TEXT, TEXT, BOOLEAN),
+  api.register(TEXT, TEXT),
+  api.heartbeat(TEXT),
+  api.submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER),
+  api.say(TEXT, TEXT, TEXT, TEXT),
+  api.snapshot(TEXT),
+  api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT)
+FROM PUBLIC;
+
+GRANT EXECUTE ON FUNCTION
+  api.login(TEXT, TEXT, BOOLEAN),
+  api.register(TEXT, TEXT),
+  api.heartbeat(TEXT),
+  api.submit_command(TEXT, TEXT, SMALLINT, SMALLINT, INTEGER),
+  api.say(TEXT, TEXT, TEXT, TEXT),
+  api.snapshot(TEXT),
+  api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT)
+TO mpd_player;
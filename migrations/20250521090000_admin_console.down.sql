@@ -0,0 +1,2 @@
+-- mpd_admin is left behind, other databases in the cluster may still be using it
+DROP SCHEMA admin CASCADE;
@@ -0,0 +1,183 @@
+-- What game masters can do to the world, through `mpdungeon2 gm`. Every change is written to the
+-- audit log along with the database user who made it. Grant mpd_admin to whoever should be
+-- allowed; the functions run as their owner, so no one needs rights on the tables themselves.
+DO $$
+BEGIN
+  CREATE ROLE mpd_admin NOLOGIN;
+EXCEPTION WHEN duplicate_object THEN NULL;
+END;
+$$;
+
+CREATE SCHEMA admin;
+GRANT USAGE ON SCHEMA admin TO mpd_admin;
+
+CREATE TABLE admin.audit_log (
+  id BIGSERIAL PRIMARY KEY,
+  at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  operator TEXT NOT NULL DEFAULT session_user,
+  action TEXT NOT NULL,
+  details JSONB NOT NULL
+);
+
+-- What can be spawned, and what it is made of
+CREATE TABLE admin.spawn_templates (
+  template TEXT PRIMARY KEY,
+  species TEXT NOT NULL,
+  -- NULL for things that can't be hurt
+  hp INTEGER,
+  weight INTEGER,
+  monster BOOLEAN NOT NULL DEFAULT false
+);
+
+INSERT INTO admin.spawn_templates (template, species, hp, weight, monster) VALUES
+  ('snake', 'snake', 5, 1, true),
+  ('gold', 'gold', NULL, 1, false);
+
+CREATE FUNCTION admin.audit(action TEXT, details JSONB)
+RETURNS VOID AS $$
+  INSERT INTO admin.audit_log (action, details) VALUES (action, details);
+$$ LANGUAGE SQL;
+
+CREATE FUNCTION admin.rooms()
+RETURNS TABLE (room_id INTEGER, name TEXT, players BIGINT, entities BIGINT, min_commands INTEGER, landing_zone BOOLEAN) AS $$
+  SELECT
+    r.entity_id,
+    n.name,
+    COUNT(pl.entity_id),
+    COUNT(p.entity_id),
+    r.min_commands,
+    r.landing_zone
+  FROM rooms r
+  LEFT JOIN names n ON n.entity_id=r.entity_id
+  LEFT JOIN positions p ON p.room_id=r.entity_id
+  LEFT JOIN players pl ON pl.entity_id=p.entity_id
+  GROUP BY r.entity_id, n.name
+  ORDER BY r.entity_id;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+CREATE FUNCTION admin.players()
+RETURNS TABLE (entity_id INTEGER, name TEXT, room_id INTEGER, x SMALLINT, y SMALLINT, hp INTEGER, maxhp INTEGER, last_seen TIMESTAMPTZ) AS $$
+  SELECT pl.entity_id, n.name, p.room_id, p.x, p.y, h.hp, h.maxhp, s.last_seen
+  FROM players pl
+  LEFT JOIN names n ON n.entity_id=pl.entity_id
+  LEFT JOIN positions p ON p.entity_id=pl.entity_id
+  LEFT JOIN hps h ON h.entity_id=pl.entity_id
+  LEFT JOIN sessions s ON s.entity_id=pl.entity_id
+  ORDER BY pl.entity_id;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Commands waiting on their room to tick
+CREATE FUNCTION admin.commands()
+RETURNS TABLE (entity_id INTEGER, name TEXT, room_id INTEGER, command_type TEXT, x SMALLINT, y SMALLINT, target INTEGER) AS $$
+  SELECT c.entity_id, COALESCE(n.name, s.species), p.room_id, c.command_type, c.x, c.y, c.target
+  FROM commands c
+  LEFT JOIN names n ON n.entity_id=c.entity_id
+  LEFT JOIN species s ON s.entity_id=c.entity_id
+  LEFT JOIN positions p ON p.entity_id=c.entity_id
+  ORDER BY p.room_id, c.entity_id;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+CREATE FUNCTION admin.teleport(entity INTEGER, room INTEGER, x SMALLINT, y SMALLINT)
+RETURNS VOID AS $$
+DECLARE
+  was positions;
+BEGIN
+  IF NOT EXISTS (SELECT 1 FROM rooms WHERE entity_id=room) THEN
+    RAISE EXCEPTION 'There is no room %', room;
+  END IF;
+  SELECT * INTO was FROM positions WHERE entity_id=entity;
+  IF NOT FOUND THEN
+    RAISE EXCEPTION 'Entity % is nowhere', entity;
+  END IF;
+  UPDATE positions SET room_id=room, x=teleport.x, y=teleport.y WHERE entity_id=entity;
+  -- Whatever it was about to do made sense where it was
+  DELETE FROM commands WHERE entity_id=entity;
+  PERFORM admin.audit('teleport', jsonb_build_object(
+    'entity_id', entity,
+    'from', jsonb_build_object('room_id', was.room_id, 'x', was.x, 'y', was.y),
+    'to', jsonb_build_object('room_id', room, 'x', x, 'y', y)
+  ));
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Returns the new entity
+CREATE FUNCTION admin.spawn(template_name TEXT, room INTEGER, x SMALLINT, y SMALLINT)
+RETURNS INTEGER AS $$
+DECLARE
+  t admin.spawn_templates;
+  spawned INTEGER;
+BEGIN
+  SELECT * INTO t FROM admin.spawn_templates WHERE template=template_name;
+  IF NOT FOUND THEN
+    RAISE EXCEPTION 'There is no template called %', template_name;
+  END IF;
+  IF NOT EXISTS (SELECT 1 FROM rooms WHERE entity_id=room) THEN
+    RAISE EXCEPTION 'There is no room %', room;
+  END IF;
+  INSERT INTO species (species) VALUES (t.species) RETURNING entity_id INTO spawned;
+  INSERT INTO positions (entity_id, x, y, room_id) VALUES (spawned, spawn.x, spawn.y, room);
+  IF t.hp IS NOT NULL THEN
+    INSERT INTO hps (entity_id, hp, maxhp) VALUES (spawned, t.hp, t.hp);
+  END IF;
+  IF t.weight IS NOT NULL THEN
+    INSERT INTO weights (entity_id, weight) VALUES (spawned, t.weight);
+  END IF;
+  IF t.monster THEN
+    INSERT INTO monsters (entity_id) VALUES (spawned);
+  END IF;
+  PERFORM admin.audit('spawn', jsonb_build_object(
+    'entity_id', spawned,
+    'template', template_name,
+    'room_id', room,
+    'x', x,
+    'y', y
+  ));
+  RETURN spawned;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Leaves maxhp as it was if not given
+CREATE FUNCTION admin.set_hp(entity INTEGER, new_hp INTEGER, new_maxhp INTEGER DEFAULT NULL)
+RETURNS VOID AS $$
+DECLARE
+  was hps;
+BEGIN
+  SELECT * INTO was FROM hps WHERE entity_id=entity;
+  IF NOT FOUND THEN
+    RAISE EXCEPTION 'Entity % has no hp', entity;
+  END IF;
+  UPDATE hps SET hp=new_hp, maxhp=COALESCE(new_maxhp, maxhp) WHERE entity_id=entity;
+  PERFORM admin.audit('set_hp', jsonb_build_object(
+    'entity_id', entity,
+    'from', jsonb_build_object('hp', was.hp, 'maxhp', was.maxhp),
+    'to', jsonb_build_object('hp', new_hp, 'maxhp', COALESCE(new_maxhp, was.maxhp))
+  ));
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Ends the player's session, their client finds out on its next heartbeat. False if they weren't
+-- logged in.
+CREATE FUNCTION admin.kick(entity INTEGER)
+RETURNS BOOLEAN AS $$
+BEGIN
+  DELETE FROM sessions WHERE entity_id=entity;
+  IF NOT FOUND THEN
+    RETURN false;
+  END IF;
+  DELETE FROM commands WHERE entity_id=entity;
+  PERFORM admin.audit('kick', jsonb_build_object('entity_id', entity));
+  RETURN true;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON ALL FUNCTIONS IN SCHEMA admin FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION
+  admin.rooms(),
+  admin.players(),
+  admin.commands(),
+  admin.teleport(INTEGER, INTEGER, SMALLINT, SMALLINT),
+  admin.spawn(TEXT, INTEGER, SMALLINT, SMALLINT),
+  admin.set_hp(INTEGER, INTEGER, INTEGER),
+  admin.kick(INTEGER)
+TO mpd_admin;
+GRANT SELECT ON admin.audit_log, admin.spawn_templates TO mpd_admin;
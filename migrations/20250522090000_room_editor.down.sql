@@ -0,0 +1,3 @@
+DROP FUNCTION admin.save_room(TEXT, TEXT);
+DROP FUNCTION admin.room_tiles(TEXT);
+DROP FUNCTION paint_room_template(INTEGER, TEXT);
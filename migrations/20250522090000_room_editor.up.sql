@@ -0,0 +1,111 @@
+-- create_room_template with everything the room editor can paint:
+--   # wall, + floor, D door, < upstair, > downstair, S spawn point (a snake, from admin.spawn_templates)
+-- Doors, stairs and spawn points stand on a floor tile of their own.
+CREATE FUNCTION paint_room_template(room_entity_id INTEGER, template TEXT)
+RETURNS VOID AS $$
+DECLARE
+  tile RECORD;
+  feature INTEGER;
+  snake admin.spawn_templates;
+BEGIN
+  SELECT * INTO snake FROM admin.spawn_templates t WHERE t.template='snake';
+  FOR tile IN
+    WITH lines AS (
+      SELECT row_number() OVER () - 1 AS y, line
+      FROM regexp_split_to_table(template, '\n') AS line
+      WHERE length(trim(line)) > 0
+    )
+    SELECT x::SMALLINT AS x, y::SMALLINT AS y, substring(line FROM x+1 FOR 1) AS symbol
+    FROM lines, LATERAL generate_series(0, length(line)-1) AS x
+    WHERE substring(line FROM x+1 FOR 1) != ' '
+  LOOP
+    IF tile.symbol NOT IN ('#', '+', 'D', '<', '>', 'S') THEN
+      RAISE EXCEPTION 'Unknown symbol % at %, %', tile.symbol, tile.x, tile.y;
+    END IF;
+    INSERT INTO species (species)
+    VALUES (CASE WHEN tile.symbol='#' THEN 'wall' ELSE 'floor' END)
+    RETURNING entity_id INTO feature;
+    INSERT INTO positions (entity_id, x, y, room_id) VALUES (feature, tile.x, tile.y, room_entity_id);
+    IF tile.symbol='#' THEN
+      INSERT INTO impassibles (entity_id) VALUES (feature);
+    ELSIF tile.symbol IN ('D', '<', '>') THEN
+      INSERT INTO species (species)
+      VALUES (CASE tile.symbol WHEN 'D' THEN 'door' WHEN '<' THEN 'upstair' ELSE 'downstair' END)
+      RETURNING entity_id INTO feature;
+      INSERT INTO positions (entity_id, x, y, room_id) VALUES (feature, tile.x, tile.y, room_entity_id);
+    ELSIF tile.symbol='S' THEN
+      INSERT INTO species (species) VALUES (snake.species) RETURNING entity_id INTO feature;
+      INSERT INTO positions (entity_id, x, y, room_id) VALUES (feature, tile.x, tile.y, room_entity_id);
+      INSERT INTO hps (entity_id, hp, maxhp) VALUES (feature, snake.hp, snake.hp);
+      INSERT INTO weights (entity_id, weight) VALUES (feature, snake.weight);
+      INSERT INTO monsters (entity_id) VALUES (feature);
+    END IF;
+  END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+REVOKE EXECUTE ON FUNCTION paint_room_template(INTEGER, TEXT) FROM PUBLIC;
+
+-- What the room editor needs to show a room as a template again. Living monsters are shown as
+-- the spawn points they came from.
+CREATE FUNCTION admin.room_tiles(room_name TEXT)
+RETURNS TABLE (x SMALLINT, y SMALLINT, species TEXT, monster BOOLEAN) AS $$
+  SELECT p.x, p.y, s.species, m.entity_id IS NOT NULL
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  INNER JOIN positions p ON p.room_id=r.entity_id
+  INNER JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN monsters m ON m.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  WHERE n.name=room_name AND (m.entity_id IS NULL OR h.hp > 0)
+  ORDER BY p.y, p.x;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Creates the room if there is none by that name, otherwise replaces its tiles and puts its
+-- monsters back on their spawn points. Players and anything lying around are left where they are.
+-- Portals to or from replaced doors and stairs go with them.
+CREATE FUNCTION admin.save_room(room_name TEXT, template TEXT)
+RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+  replaced INTEGER[];
+BEGIN
+  SELECT r.entity_id INTO room
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  WHERE n.name=room_name;
+
+  IF room IS NULL THEN
+    INSERT INTO rooms (min_commands, landing_zone) VALUES (1, false) RETURNING entity_id INTO room;
+    INSERT INTO names (entity_id, name) VALUES (room, room_name);
+  ELSE
+    SELECT ARRAY_AGG(p.entity_id) INTO replaced
+    FROM positions p
+    LEFT JOIN species s ON s.entity_id=p.entity_id
+    LEFT JOIN monsters m ON m.entity_id=p.entity_id
+    WHERE
+      p.room_id=room AND
+      (s.species IN ('wall', 'floor', 'door', 'upstair', 'downstair') OR m.entity_id IS NOT NULL);
+
+    DELETE FROM portals WHERE start_entity_id = ANY(replaced) OR end_entity_id = ANY(replaced);
+    DELETE FROM impassibles WHERE entity_id = ANY(replaced);
+    DELETE FROM monsters WHERE entity_id = ANY(replaced);
+    DELETE FROM hps WHERE entity_id = ANY(replaced);
+    DELETE FROM weights WHERE entity_id = ANY(replaced);
+    DELETE FROM commands WHERE entity_id = ANY(replaced);
+    DELETE FROM species WHERE entity_id = ANY(replaced);
+    DELETE FROM positions WHERE entity_id = ANY(replaced);
+  END IF;
+
+  PERFORM paint_room_template(room, template);
+  PERFORM admin.audit('save_room', jsonb_build_object(
+    'room_id', room,
+    'name', room_name,
+    'template', template
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.room_tiles(TEXT), admin.save_room(TEXT, TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.room_tiles(TEXT), admin.save_room(TEXT, TEXT) TO mpd_admin;
@@ -0,0 +1,201 @@
+-- Portals declared in templates by label. Entities with the same label in different rooms lead
+-- to each other, whichever of the rooms was made first.
+CREATE TABLE portal_labels (
+  entity_id INTEGER PRIMARY KEY,
+  label TEXT NOT NULL
+);
+
+CREATE INDEX portal_labels_label ON portal_labels (label);
+
+-- One entity of a legend entry as JSON: a species followed by any of impassible, monster, hp=N,
+-- weight=N, portal=<label> and spawn=<template> for the species, hp, weight and monster of a
+-- template in admin.spawn_templates. Anything written out wins over the spawn template.
+CREATE FUNCTION template_layer(layer TEXT)
+RETURNS JSONB AS $$
+DECLARE
+  word TEXT;
+  result JSONB := '{}';
+  spawn admin.spawn_templates;
+BEGIN
+  FOREACH word IN ARRAY regexp_split_to_array(trim(layer), '\s+') LOOP
+    IF word IN ('impassible', 'monster') THEN
+      result := result || jsonb_build_object(word, true);
+    ELSIF word ~ '^(hp|weight)=\d+$' THEN
+      result := result || jsonb_build_object(split_part(word, '=', 1), split_part(word, '=', 2)::INTEGER);
+    ELSIF word ~ '^portal=.' THEN
+      result := result || jsonb_build_object('portal', substring(word FROM 8));
+    ELSIF word ~ '^spawn=.' THEN
+      SELECT * INTO spawn FROM admin.spawn_templates t WHERE t.template=substring(word FROM 7);
+      IF NOT FOUND THEN
+        RAISE EXCEPTION 'Unknown spawn template % in %', substring(word FROM 7), layer;
+      END IF;
+      result := jsonb_strip_nulls(jsonb_build_object(
+        'species', spawn.species,
+        'hp', spawn.hp,
+        'weight', spawn.weight,
+        'monster', spawn.monster
+      )) || result;
+    ELSIF word ~ '=' THEN
+      RAISE EXCEPTION 'Unknown component % in %', word, layer;
+    ELSIF result ? 'species' THEN
+      RAISE EXCEPTION 'More than one species in %', layer;
+    ELSE
+      result := result || jsonb_build_object('species', word);
+    END IF;
+  END LOOP;
+  IF NOT result ? 'species' THEN
+    RAISE EXCEPTION 'No species in %', layer;
+  END IF;
+  RETURN result;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Templates can start with a legend of what each symbol puts down, ended by a line of ---
+--   D = floor, door portal=tavern
+--   s = floor, spawn=snake
+--   $ = floor, gold weight=1
+--   ---
+--   ##D##
+--   #s+$#
+--   #####
+-- Every comma separated part of an entry is an entity of its own on the tile, see template_layer.
+-- Without a legend, or where it doesn't say otherwise, # is a wall and + a floor. Any other symbol
+-- is an error rather than an entity without a species.
+CREATE OR REPLACE FUNCTION create_room_template(room_entity_id INT, template TEXT)
+RETURNS VOID AS $$
+DECLARE
+  lines TEXT[] := regexp_split_to_array(template, '\n');
+  legend_end INTEGER;
+  legend JSONB := jsonb_build_object(
+    '#', jsonb_build_array(template_layer('wall impassible')),
+    '+', jsonb_build_array(template_layer('floor'))
+  );
+  entry TEXT[];
+  map TEXT := template;
+  tile RECORD;
+  layer RECORD;
+  entity INTEGER;
+BEGIN
+  SELECT i INTO legend_end FROM generate_subscripts(lines, 1) AS i WHERE trim(lines[i])='---' LIMIT 1;
+  IF legend_end IS NOT NULL THEN
+    FOR i IN 1..legend_end-1 LOOP
+      CONTINUE WHEN length(trim(lines[i])) = 0;
+      entry := regexp_match(lines[i], '^\s*(\S)\s*=(.*)$');
+      IF entry IS NULL THEN
+        RAISE EXCEPTION 'Legend entries look like "D = floor, door", not %', lines[i];
+      END IF;
+      legend := legend || jsonb_build_object(entry[1], (
+        SELECT jsonb_agg(template_layer(part) ORDER BY n)
+        FROM unnest(string_to_array(entry[2], ',')) WITH ORDINALITY AS parts(part, n)
+      ));
+    END LOOP;
+    map := array_to_string(lines[legend_end+1:], E'\n');
+  END IF;
+
+  FOR tile IN
+    WITH map_lines AS (
+      SELECT row_number() OVER () - 1 AS y, line
+      FROM regexp_split_to_table(map, '\n') AS line
+      WHERE length(trim(line)) > 0
+    )
+    SELECT x::SMALLINT AS x, y::SMALLINT AS y, substring(line FROM x+1 FOR 1) AS symbol
+    FROM map_lines, LATERAL generate_series(0, length(line)-1) AS x
+    WHERE substring(line FROM x+1 FOR 1) != ' '
+  LOOP
+    IF NOT legend ? tile.symbol THEN
+      RAISE EXCEPTION 'Unknown symbol % at %, %', tile.symbol, tile.x, tile.y;
+    END IF;
+    FOR layer IN
+      SELECT * FROM jsonb_to_recordset(legend -> tile.symbol) AS l(
+        species TEXT, impassible BOOLEAN, monster BOOLEAN, hp INTEGER, weight INTEGER, portal TEXT
+      )
+    LOOP
+      INSERT INTO species (species) VALUES (layer.species) RETURNING entity_id INTO entity;
+      INSERT INTO positions (entity_id, x, y, room_id) VALUES (entity, tile.x, tile.y, room_entity_id);
+      IF layer.impassible THEN
+        INSERT INTO impassibles (entity_id) VALUES (entity);
+      END IF;
+      IF layer.monster THEN
+        INSERT INTO monsters (entity_id) VALUES (entity);
+      END IF;
+      IF layer.hp IS NOT NULL THEN
+        INSERT INTO hps (entity_id, hp, maxhp) VALUES (entity, layer.hp, layer.hp);
+      END IF;
+      IF layer.weight IS NOT NULL THEN
+        INSERT INTO weights (entity_id, weight) VALUES (entity, layer.weight);
+      END IF;
+      IF layer.portal IS NOT NULL THEN
+        WITH other_ends AS (
+          SELECT l.entity_id
+          FROM portal_labels l
+          INNER JOIN positions p ON p.entity_id=l.entity_id
+          WHERE l.label=layer.portal AND p.room_id != room_entity_id
+        )
+        INSERT INTO portals (start_entity_id, end_entity_id)
+        SELECT entity, entity_id FROM other_ends
+        UNION ALL
+        SELECT entity_id, entity FROM other_ends;
+        INSERT INTO portal_labels (entity_id, label) VALUES (entity, layer.portal);
+      END IF;
+    END LOOP;
+  END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+-- The room editor's symbols are a legend too. A template with a legend of its own can add to them.
+CREATE OR REPLACE FUNCTION paint_room_template(room_entity_id INTEGER, template TEXT)
+RETURNS VOID AS $$
+  SELECT create_room_template(
+    room_entity_id,
+    E'D = floor, door\n< = floor, upstair\n> = floor, downstair\nS = floor, spawn=snake\n'
+    || CASE WHEN template ~ '(^|\n)\s*---\s*(\n|$)' THEN '' ELSE E'---\n' END
+    || template
+  );
+$$ LANGUAGE SQL;
+
+-- Labels go with the doors and stairs they were on
+CREATE OR REPLACE FUNCTION admin.save_room(room_name TEXT, template TEXT)
+RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+  replaced INTEGER[];
+BEGIN
+  SELECT r.entity_id INTO room
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  WHERE n.name=room_name;
+
+  IF room IS NULL THEN
+    INSERT INTO rooms (min_commands, landing_zone) VALUES (1, false) RETURNING entity_id INTO room;
+    INSERT INTO names (entity_id, name) VALUES (room, room_name);
+  ELSE
+    SELECT ARRAY_AGG(p.entity_id) INTO replaced
+    FROM positions p
+    LEFT JOIN species s ON s.entity_id=p.entity_id
+    LEFT JOIN monsters m ON m.entity_id=p.entity_id
+    WHERE
+      p.room_id=room AND
+      (s.species IN ('wall', 'floor', 'door', 'upstair', 'downstair') OR m.entity_id IS NOT NULL);
+
+    DELETE FROM portals WHERE start_entity_id = ANY(replaced) OR end_entity_id = ANY(replaced);
+    DELETE FROM portal_labels WHERE entity_id = ANY(replaced);
+    DELETE FROM impassibles WHERE entity_id = ANY(replaced);
+    DELETE FROM monsters WHERE entity_id = ANY(replaced);
+    DELETE FROM hps WHERE entity_id = ANY(replaced);
+    DELETE FROM weights WHERE entity_id = ANY(replaced);
+    DELETE FROM commands WHERE entity_id = ANY(replaced);
+    DELETE FROM species WHERE entity_id = ANY(replaced);
+    DELETE FROM positions WHERE entity_id = ANY(replaced);
+  END IF;
+
+  PERFORM paint_room_template(room, template);
+  PERFORM admin.audit('save_room', jsonb_build_object(
+    'room_id', room,
+    'name', room_name,
+    'template', template
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION template_layer(TEXT) FROM PUBLIC;
@@ -0,0 +1,438 @@
+-- Doors that open and close, blocking the way while closed the same as a wall. Locked doors only
+-- open for someone carrying a key with the same lock.
+CREATE TABLE doors (
+  entity_id INTEGER PRIMARY KEY,
+  open BOOLEAN NOT NULL DEFAULT true,
+  locked BOOLEAN NOT NULL DEFAULT false,
+  lock TEXT
+);
+
+CREATE TABLE keys (
+  entity_id INTEGER PRIMARY KEY,
+  lock TEXT NOT NULL
+);
+
+GRANT SELECT ON doors TO mpd_game;
+
+-- The doors there already are stand open as they always have
+INSERT INTO doors (entity_id)
+SELECT s.entity_id FROM species s WHERE s.species='door';
+
+ALTER TYPE api.entity ADD ATTRIBUTE open BOOLEAN;
+
+-- Whether each door is open, NULL for everything else
+CREATE OR REPLACE FUNCTION api.snapshot(session TEXT)
+RETURNS SETOF api.entity AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT
+    p.entity_id,
+    p.x,
+    p.y,
+    p.room_id,
+    s.species,
+    n.name,
+    c.command_type,
+    c.x,
+    c.y,
+    h.hp,
+    h.maxhp,
+    portals.ends,
+    w.weight,
+    d.open
+  FROM positions st
+  INNER JOIN positions p ON (p.room_id=st.room_id OR p.room_id=st.entity_id)
+  LEFT JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN names n ON n.entity_id=p.entity_id
+  LEFT JOIN commands c ON c.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  LEFT JOIN weights w ON w.entity_id=p.entity_id
+  LEFT JOIN doors d ON d.entity_id=p.entity_id
+  CROSS JOIN LATERAL (SELECT ARRAY_AGG(end_entity_id) ends FROM portals WHERE start_entity_id=p.entity_id) portals
+  WHERE st.entity_id=current_player()
+  ORDER BY p.entity_id ASC;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+
+-- open and close, for doors next to the player
+CREATE OR REPLACE FUNCTION room_tick()
+RETURNS TRIGGER AS $$
+BEGIN
+  WITH triggered_rooms AS (
+    SELECT 
+      p.room_id
+    FROM 
+      positions p
+    LEFT JOIN positions po ON 
+      po.room_id=p.room_id
+    INNER JOIN players pl ON 
+      pl.entity_id=po.entity_id
+    LEFT JOIN commands c ON 
+      c.entity_id=pl.entity_id
+    LEFT JOIN rooms r ON r.entity_id=p.room_id
+    WHERE p.entity_id=NEW.entity_id
+    GROUP BY p.room_id, r.min_commands
+    HAVING (COUNT(po.*) = COUNT(c.*)) OR (COUNT(c.*) >= r.min_commands)
+  ),
+  monster_attack_commands AS (
+    SELECT p.entity_id::int, 'attack'::text, null::smallint, null::smallint, com.targ_entity_id::int
+    FROM 
+      triggered_rooms t,
+      positions p,
+      monsters m,
+      hps h,
+      LATERAL (
+        SELECT hs.entity_id "targ_entity_id"
+        FROM hps h 
+        INNER JOIN positions hs ON hs.entity_id=h.entity_id AND hs.room_id=p.room_id
+        WHERE h.entity_id != m.entity_id AND abs(hs.x - p.x) <= 1 AND abs(hs.y - p.y) <= 1
+        LIMIT 1
+      ) com
+      WHERE p.room_id=t.room_id AND p.entity_id=m.entity_id AND h.entity_id=p.entity_id AND h.hp > 0
+  ),
+  monster_move_commands AS (
+    SELECT p.entity_id::int, 'move'::text, gx, gy, null::int
+    FROM 
+      triggered_rooms t,
+      positions p,
+      monsters m,
+      hps h,
+      LATERAL (
+        SELECT x,y
+        FROM positions pt
+        INNER JOIN hps ht ON ht.entity_id=pt.entity_id
+        WHERE pt.room_id=p.room_id AND pt.entity_id != p.entity_id AND ht.hp > 0
+        ORDER BY ABS(pt.x-p.x) + ABS(pt.y - p.y) ASC
+        LIMIT 1
+      ) targ,
+      LATERAL (
+        SELECT gx ,gy 
+        FROM generate_series(-1,1) gx
+        CROSS JOIN generate_series(-1,1) gy
+        WHERE NOT EXISTS (
+          SELECT 1
+          FROM positions tp 
+          INNER JOIN impassibles i ON i.entity_id=tp.entity_id
+          WHERE tp.room_id=p.room_id AND tp.x=p.x+gx AND tp.y=p.y+gy
+        )
+        ORDER BY ABS(p.x+gx-targ.x) + ABS(p.y+gy-targ.y) ASC
+        LIMIT 1
+      ) com
+    WHERE 
+      p.room_id=t.room_id AND 
+      p.entity_id=m.entity_id AND 
+      h.entity_id=p.entity_id AND 
+      h.hp > 0 AND 
+      NOT EXISTS (SELECT mac.entity_id FROM monster_attack_commands mac WHERE mac.entity_id=p.entity_id)
+  ),
+  removed_commands AS (
+    DELETE FROM commands
+    USING positions p 
+    WHERE 
+      p.room_id IN (SELECT room_id FROM triggered_rooms) AND 
+      commands.entity_id=p.entity_id
+    RETURNING commands.*
+  ),
+  actioned_commands AS (
+    SELECT rm.* 
+    FROM removed_commands rm
+    INNER JOIN hps ON hps.hp > 0 AND hps.entity_id=rm.entity_id
+    UNION ALL
+    SELECT *
+    FROM monster_attack_commands
+    UNION ALL
+    SELECT *
+    FROM monster_move_commands
+  ),
+  travels AS (
+    UPDATE positions SET 
+      x=targ_p.x,
+      y=targ_p.y,
+      room_id=targ_p.room_id
+    FROM actioned_commands c
+    INNER JOIN portals p ON p.start_entity_id=c.target
+    INNER JOIN positions targ_p ON targ_p.entity_id=p.end_entity_id
+    WHERE c.command_type='travel' AND positions.entity_id=c.entity_id
+  ),
+  picked_up AS (
+    UPDATE positions SET
+      x = 0,
+      y = 0,
+      room_id = c.entity_id
+    FROM commands c
+    WHERE positions.entity_id=c.target AND c.command_type='pickup'
+  ),
+  dropped AS (
+    UPDATE positions SET
+      x = player_pos.x,
+      y = player_pos.y,
+      room_id = player_pos.room_id
+    FROM actioned_commands c
+    INNER JOIN positions player_pos ON player_pos.entity_id = c.entity_id
+    WHERE positions.entity_id = c.target AND c.command_type = 'drop'
+  ),
+  -- Doors next to whoever opens or closes them, the first command for each if there are several.
+  -- Locked doors only open for someone carrying a key to them, and nothing can close on someone.
+  door_commands AS (
+    SELECT DISTINCT ON (d.entity_id) c.command_type, d.entity_id, k.has_key
+    FROM actioned_commands c
+    INNER JOIN doors d ON d.entity_id=c.target
+    INNER JOIN positions dp ON dp.entity_id=d.entity_id
+    INNER JOIN positions pp ON pp.entity_id=c.entity_id
+    CROSS JOIN LATERAL (
+      SELECT EXISTS (
+        SELECT 1 FROM keys
+        INNER JOIN positions kp ON kp.entity_id=keys.entity_id
+        WHERE kp.room_id=c.entity_id AND keys.lock=d.lock
+      ) has_key
+    ) k
+    WHERE
+      c.command_type IN ('open', 'close') AND
+      dp.room_id=pp.room_id AND abs(dp.x - pp.x) <= 1 AND abs(dp.y - pp.y) <= 1 AND
+      (c.command_type='close' OR NOT d.locked OR k.has_key) AND
+      (c.command_type='open' OR NOT EXISTS (
+        SELECT 1 FROM positions op
+        INNER JOIN hps ON hps.entity_id=op.entity_id
+        WHERE op.room_id=dp.room_id AND op.x=dp.x AND op.y=dp.y
+      ))
+    ORDER BY d.entity_id, c.entity_id
+  ),
+  -- Closing a door with its key on you locks it again
+  doors_changed AS (
+    UPDATE doors SET
+      open = dc.command_type='open',
+      locked = dc.command_type='close' AND dc.has_key
+    FROM door_commands dc
+    WHERE doors.entity_id=dc.entity_id
+  ),
+  doors_opened AS (
+    DELETE FROM impassibles
+    USING door_commands dc
+    WHERE impassibles.entity_id=dc.entity_id AND dc.command_type='open'
+  ),
+  doors_closed AS (
+    INSERT INTO impassibles (entity_id)
+    SELECT entity_id FROM door_commands WHERE command_type='close'
+    ON CONFLICT DO NOTHING
+  ),
+  new_pos AS (
+    UPDATE positions SET
+      x = positions.x + c.x,
+      y = positions.y + c.y
+    FROM actioned_commands c
+    WHERE 
+      positions.entity_id=c.entity_id AND 
+      c.command_type='move' AND 
+      positions.room_id IN (SELECT room_id FROM triggered_rooms)
+      AND NOT EXISTS (SELECT * FROM impassibles i INNER JOIN positions p ON p.x=positions.x+c.x AND p.y=positions.y+c.y AND p.entity_id=i.entity_id AND p.room_id=positions.room_id)
+      RETURNING *
+    )
+  UPDATE hps 
+  SET hp=hp-1
+  FROM actioned_commands c
+  WHERE c.target=hps.entity_id AND c.command_type='attack';
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+
+-- Legends can place doors too: open, closed, or locked with lock=<name>, and keys with key=<name>
+CREATE OR REPLACE FUNCTION template_layer(layer TEXT)
+RETURNS JSONB AS $$
+DECLARE
+  word TEXT;
+  result JSONB := '{}';
+  spawn admin.spawn_templates;
+BEGIN
+  FOREACH word IN ARRAY regexp_split_to_array(trim(layer), '\s+') LOOP
+    IF word IN ('impassible', 'monster') THEN
+      result := result || jsonb_build_object(word, true);
+    ELSIF word ~ '^(hp|weight)=\d+$' THEN
+      result := result || jsonb_build_object(split_part(word, '=', 1), split_part(word, '=', 2)::INTEGER);
+    ELSIF word IN ('open', 'closed', 'locked') THEN
+      result := result || jsonb_build_object('door', word);
+    ELSIF word ~ '^(lock|key)=.' THEN
+      result := result || jsonb_build_object(split_part(word, '=', 1), substring(word FROM position('=' IN word)+1));
+    ELSIF word ~ '^portal=.' THEN
+      result := result || jsonb_build_object('portal', substring(word FROM 8));
+    ELSIF word ~ '^spawn=.' THEN
+      SELECT * INTO spawn FROM admin.spawn_templates t WHERE t.template=substring(word FROM 7);
+      IF NOT FOUND THEN
+        RAISE EXCEPTION 'Unknown spawn template % in %', substring(word FROM 7), layer;
+      END IF;
+      result := jsonb_strip_nulls(jsonb_build_object(
+        'species', spawn.species,
+        'hp', spawn.hp,
+        'weight', spawn.weight,
+        'monster', spawn.monster
+      )) || result;
+    ELSIF word ~ '=' THEN
+      RAISE EXCEPTION 'Unknown component % in %', word, layer;
+    ELSIF result ? 'species' THEN
+      RAISE EXCEPTION 'More than one species in %', layer;
+    ELSE
+      result := result || jsonb_build_object('species', word);
+    END IF;
+  END LOOP;
+  -- A door with a lock starts locked unless it says otherwise
+  IF result ? 'lock' AND NOT result ? 'door' THEN
+    result := result || jsonb_build_object('door', 'locked');
+  END IF;
+  IF NOT result ? 'species' THEN
+    RAISE EXCEPTION 'No species in %', layer;
+  END IF;
+  RETURN result;
+END;
+$$ LANGUAGE plpgsql;
+
+
+CREATE OR REPLACE FUNCTION create_room_template(room_entity_id INT, template TEXT)
+RETURNS VOID AS $$
+DECLARE
+  lines TEXT[] := regexp_split_to_array(template, '\n');
+  legend_end INTEGER;
+  legend JSONB := jsonb_build_object(
+    '#', jsonb_build_array(template_layer('wall impassible')),
+    '+', jsonb_build_array(template_layer('floor'))
+  );
+  entry TEXT[];
+  map TEXT := template;
+  tile RECORD;
+  layer RECORD;
+  entity INTEGER;
+BEGIN
+  SELECT i INTO legend_end FROM generate_subscripts(lines, 1) AS i WHERE trim(lines[i])='---' LIMIT 1;
+  IF legend_end IS NOT NULL THEN
+    FOR i IN 1..legend_end-1 LOOP
+      CONTINUE WHEN length(trim(lines[i])) = 0;
+      entry := regexp_match(lines[i], '^\s*(\S)\s*=(.*)$');
+      IF entry IS NULL THEN
+        RAISE EXCEPTION 'Legend entries look like "D = floor, door", not %', lines[i];
+      END IF;
+      legend := legend || jsonb_build_object(entry[1], (
+        SELECT jsonb_agg(template_layer(part) ORDER BY n)
+        FROM unnest(string_to_array(entry[2], ',')) WITH ORDINALITY AS parts(part, n)
+      ));
+    END LOOP;
+    map := array_to_string(lines[legend_end+1:], E'\n');
+  END IF;
+
+  FOR tile IN
+    WITH map_lines AS (
+      SELECT row_number() OVER () - 1 AS y, line
+      FROM regexp_split_to_table(map, '\n') AS line
+      WHERE length(trim(line)) > 0
+    )
+    SELECT x::SMALLINT AS x, y::SMALLINT AS y, substring(line FROM x+1 FOR 1) AS symbol
+    FROM map_lines, LATERAL generate_series(0, length(line)-1) AS x
+    WHERE substring(line FROM x+1 FOR 1) != ' '
+  LOOP
+    IF NOT legend ? tile.symbol THEN
+      RAISE EXCEPTION 'Unknown symbol % at %, %', tile.symbol, tile.x, tile.y;
+    END IF;
+    FOR layer IN
+      SELECT * FROM jsonb_to_recordset(legend -> tile.symbol) AS l(
+        species TEXT, impassible BOOLEAN, monster BOOLEAN, hp INTEGER, weight INTEGER, portal TEXT,
+        door TEXT, lock TEXT, key TEXT
+      )
+    LOOP
+      INSERT INTO species (species) VALUES (layer.species) RETURNING entity_id INTO entity;
+      INSERT INTO positions (entity_id, x, y, room_id) VALUES (entity, tile.x, tile.y, room_entity_id);
+      IF layer.impassible OR layer.door IN ('closed', 'locked') THEN
+        INSERT INTO impassibles (entity_id) VALUES (entity);
+      END IF;
+      IF layer.door IS NOT NULL THEN
+        INSERT INTO doors (entity_id, open, locked, lock)
+        VALUES (entity, layer.door='open', layer.door='locked', layer.lock);
+      END IF;
+      IF layer.key IS NOT NULL THEN
+        INSERT INTO keys (entity_id, lock) VALUES (entity, layer.key);
+      END IF;
+      IF layer.monster THEN
+        INSERT INTO monsters (entity_id) VALUES (entity);
+      END IF;
+      IF layer.hp IS NOT NULL THEN
+        INSERT INTO hps (entity_id, hp, maxhp) VALUES (entity, layer.hp, layer.hp);
+      END IF;
+      IF layer.weight IS NOT NULL THEN
+        INSERT INTO weights (entity_id, weight) VALUES (entity, layer.weight);
+      END IF;
+      IF layer.portal IS NOT NULL THEN
+        WITH other_ends AS (
+          SELECT l.entity_id
+          FROM portal_labels l
+          INNER JOIN positions p ON p.entity_id=l.entity_id
+          WHERE l.label=layer.portal AND p.room_id != room_entity_id
+        )
+        INSERT INTO portals (start_entity_id, end_entity_id)
+        SELECT entity, entity_id FROM other_ends
+        UNION ALL
+        SELECT entity_id, entity FROM other_ends;
+        INSERT INTO portal_labels (entity_id, label) VALUES (entity, layer.portal);
+      END IF;
+    END LOOP;
+  END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+
+-- Doors painted in the editor start closed
+CREATE OR REPLACE FUNCTION paint_room_template(room_entity_id INTEGER, template TEXT)
+RETURNS VOID AS $$
+  SELECT create_room_template(
+    room_entity_id,
+    E'D = floor, door closed\n< = floor, upstair\n> = floor, downstair\nS = floor, spawn=snake\n'
+    || CASE WHEN template ~ '(^|\n)\s*---\s*(\n|$)' THEN '' ELSE E'---\n' END
+    || template
+  );
+$$ LANGUAGE SQL;
+
+
+-- Door state goes with the door
+CREATE OR REPLACE FUNCTION admin.save_room(room_name TEXT, template TEXT)
+RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+  replaced INTEGER[];
+BEGIN
+  SELECT r.entity_id INTO room
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  WHERE n.name=room_name;
+
+  IF room IS NULL THEN
+    INSERT INTO rooms (min_commands, landing_zone) VALUES (1, false) RETURNING entity_id INTO room;
+    INSERT INTO names (entity_id, name) VALUES (room, room_name);
+  ELSE
+    SELECT ARRAY_AGG(p.entity_id) INTO replaced
+    FROM positions p
+    LEFT JOIN species s ON s.entity_id=p.entity_id
+    LEFT JOIN monsters m ON m.entity_id=p.entity_id
+    WHERE
+      p.room_id=room AND
+      (s.species IN ('wall', 'floor', 'door', 'upstair', 'downstair') OR m.entity_id IS NOT NULL);
+
+    DELETE FROM portals WHERE start_entity_id = ANY(replaced) OR end_entity_id = ANY(replaced);
+    DELETE FROM portal_labels WHERE entity_id = ANY(replaced);
+    DELETE FROM doors WHERE entity_id = ANY(replaced);
+    DELETE FROM impassibles WHERE entity_id = ANY(replaced);
+    DELETE FROM monsters WHERE entity_id = ANY(replaced);
+    DELETE FROM hps WHERE entity_id = ANY(replaced);
+    DELETE FROM weights WHERE entity_id = ANY(replaced);
+    DELETE FROM commands WHERE entity_id = ANY(replaced);
+    DELETE FROM species WHERE entity_id = ANY(replaced);
+    DELETE FROM positions WHERE entity_id = ANY(replaced);
+  END IF;
+
+  PERFORM paint_room_template(room, template);
+  PERFORM admin.audit('save_room', jsonb_build_object(
+    'room_id', room,
+    'name', room_name,
+    'template', template
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
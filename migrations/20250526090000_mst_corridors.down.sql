@@ -0,0 +1,317 @@
+DROP FUNCTION regenerate_dungeon(INT);
+DROP FUNCTION generate_dungeon(INT, INT, INT, INT, INT, BIGINT, DOUBLE PRECISION);
+DROP FUNCTION dungeon_layout(INT, INT, INT, INT, INT, BIGINT, DOUBLE PRECISION);
+
+-- The template generate_dungeon makes, without making a room for it. Planned for the arguments it
+-- is called with, as a generic plan doesn't know how big the grid is and takes seconds instead of
+-- milliseconds.
+CREATE FUNCTION dungeon_layout(
+    width INT,
+    height INT,
+    room_count INT,
+    min_room_size INT,
+    max_room_size INT,
+    seed BIGINT
+) RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+#variable_conflict use_column
+BEGIN
+RETURN QUERY
+WITH 
+-- Generate the BSP tree leaves
+all_leaves AS (
+    SELECT * FROM generate_bsp(width, height, max_room_size, seed)
+),
+-- Count the number of leaves generated
+leaf_count AS (
+    SELECT count(*) AS count FROM all_leaves
+),
+-- Select a subset of random leaves if room_count is specified
+selected_leaves AS (
+    SELECT 
+        all_leaves.*,
+        -- Set a rank for randomized ordering
+        CASE 
+            WHEN room_count IS NULL OR room_count >= (SELECT count FROM leaf_count) THEN 0
+            ELSE seeded_random(seed, all_leaves.id)
+        END AS rank
+    FROM all_leaves
+    -- Only grab sufficiently sized rooms
+    WHERE all_leaves.w > min_room_size AND all_leaves.h > min_room_size
+    -- Order by the rank to get a deterministic but random ordering
+    ORDER BY rank DESC, all_leaves.id
+    -- Limit to exact room count
+    LIMIT CASE 
+        WHEN room_count IS NULL THEN NULL
+        ELSE room_count
+    END
+),
+-- Calculate all possible edges between rooms
+edges AS (
+    SELECT 
+        r1.id AS id1,
+        r2.id AS id2,
+        ABS(r1.x - r2.x) + ABS(r1.y - r2.y) AS distance
+    FROM 
+        selected_leaves r1
+        CROSS JOIN selected_leaves r2
+    WHERE 
+        r1.id < r2.id -- Avoid duplicates and self-connections
+    ORDER BY 
+        distance -- Sort by distance for MST algorithm
+),
+
+-- Use a simpler approach for corridor generation 
+-- Create a star topology by connecting each room to a central room
+corridors AS (
+    -- Find a central room (using the one closest to the average position)
+    WITH central_room AS (
+        SELECT 
+            id,
+            x, y,
+            ABS(x - (SELECT AVG(x) FROM selected_leaves)) + 
+            ABS(y - (SELECT AVG(y) FROM selected_leaves)) AS distance_to_center
+        FROM 
+            selected_leaves
+        ORDER BY 
+            distance_to_center
+        LIMIT 1
+    )
+    
+    -- Connect each room to the central room
+    SELECT 
+        r.id AS id1,
+        c.id AS id2,
+        ABS(r.x - c.x) + ABS(r.y - c.y) AS distance
+    FROM 
+        selected_leaves r,
+        central_room c
+    WHERE 
+        r.id != c.id
+),
+-- Get coordinates for all grid positions
+grid_coords AS (
+    SELECT 
+        x, y
+    FROM 
+        generate_series(0, width-1) AS x
+        CROSS JOIN
+        generate_series(0, height-1) AS y
+),
+-- All potential dungeon tiles (including corridors)
+raw_floor_tiles AS (
+    -- Room tiles
+    SELECT 
+        g.x, g.y
+    FROM 
+        grid_coords g
+    WHERE 
+        EXISTS (
+            SELECT 1 
+            FROM selected_leaves r
+            WHERE 
+                g.x >= r.x AND 
+                g.x < r.x + r.w AND
+                g.y >= r.y AND 
+                g.y < r.y + r.h
+        )
+    
+    UNION
+    
+    -- Corridor tiles - create L-shaped corridors between connected rooms
+    SELECT 
+        x, y
+    FROM (
+        -- For each corridor, get the center points of the two rooms
+        SELECT 
+            r1.x + r1.w/2 AS x1,
+            r1.y + r1.h/2 AS y1,
+            r2.x + r2.w/2 AS x2,
+            r2.y + r2.h/2 AS y2,
+            c.id1, c.id2
+        FROM 
+            corridors c
+            JOIN selected_leaves r1 ON c.id1 = r1.id
+            JOIN selected_leaves r2 ON c.id2 = r2.id
+    ) room_centers
+    -- Generate the horizontal corridor segment
+    CROSS JOIN LATERAL (
+        SELECT 
+            generate_series(
+                LEAST(floor(x1)::int, floor(x2)::int),
+                GREATEST(floor(x1)::int, floor(x2)::int)
+            ) AS x,
+            floor(y1)::int AS y
+    ) horiz_corridor
+    
+    UNION
+    
+    -- Generate the vertical corridor segment for each corridor
+    SELECT 
+        x, y
+    FROM (
+        -- For each corridor, get the center points of the two rooms
+        SELECT 
+            r1.x + r1.w/2 AS x1,
+            r1.y + r1.h/2 AS y1,
+            r2.x + r2.w/2 AS x2,
+            r2.y + r2.h/2 AS y2,
+            c.id1, c.id2
+        FROM 
+            corridors c
+            JOIN selected_leaves r1 ON c.id1 = r1.id
+            JOIN selected_leaves r2 ON c.id2 = r2.id
+    ) room_centers
+    -- Generate the vertical corridor segment
+    CROSS JOIN LATERAL (
+        SELECT 
+            floor(x2)::int AS x,
+            generate_series(
+                LEAST(floor(y1)::int, floor(y2)::int),
+                GREATEST(floor(y1)::int, floor(y2)::int)
+            ) AS y
+    ) vert_corridor
+),
+
+-- Final floor tiles - excluding edge tiles which will become walls
+floor_tiles AS (
+    SELECT x, y 
+    FROM raw_floor_tiles
+    WHERE 
+        x > 0 AND x < width - 1 AND
+        y > 0 AND y < height - 1
+),
+
+-- Edge tiles (floors converted to walls)
+edge_walls AS (
+    SELECT x, y
+    FROM raw_floor_tiles
+    WHERE
+        x = 0 OR x = width - 1 OR
+        y = 0 OR y = height - 1
+),
+-- Identify regular wall tiles (adjacent to floor tiles)
+wall_tiles AS (
+    -- Standard walls: adjacent to floor tiles
+    SELECT DISTINCT
+        g.x, g.y
+    FROM 
+        grid_coords g
+    WHERE 
+        -- Tile is not a floor
+        NOT EXISTS (
+            SELECT 1 FROM floor_tiles f 
+            WHERE f.x = g.x AND f.y = g.y
+        )
+        -- But is adjacent to a floor tile (including diagonals)
+        AND EXISTS (
+            SELECT 1 FROM floor_tiles f
+            WHERE 
+                (f.x BETWEEN g.x - 1 AND g.x + 1) AND
+                (f.y BETWEEN g.y - 1 AND g.y + 1)
+        )
+    
+    UNION
+    
+    -- Include the edge walls we identified earlier
+    SELECT x, y FROM edge_walls
+),
+
+-- Generate dungeon map directly from grid coordinates
+dungeon_map AS (
+    SELECT 
+        g.y,
+        string_agg(
+            CASE 
+                -- Floor tiles 
+                WHEN EXISTS (
+                    SELECT 1 FROM floor_tiles f 
+                    WHERE f.x = g.x AND f.y = g.y
+                ) THEN '+'
+                -- Wall tiles
+                WHEN EXISTS (
+                    SELECT 1 FROM wall_tiles w
+                    WHERE w.x = g.x AND w.y = g.y
+                ) THEN '#'
+                -- Empty space
+                ELSE ' '
+            END,
+            '' ORDER BY g.x
+        ) AS row_str
+    FROM grid_coords g
+    GROUP BY g.y
+    ORDER BY g.y
+),
+-- Combine all rows into a single string
+dungeon_template AS (
+    SELECT string_agg(row_str, E'\n') AS template
+    FROM dungeon_map
+)
+-- Return the template, and the leaves and corridors it was made from
+SELECT 
+    jsonb_build_object(
+      'corridors', (SELECT jsonb_agg(corridors.*) FROM corridors),
+      'rooms', (SELECT jsonb_agg(selected_leaves.*) FROM selected_leaves),
+      'template', (SELECT template FROM dungeon_template)
+    ) "debug_output",
+    (SELECT template FROM dungeon_template) AS template;
+END;
+$$ LANGUAGE plpgsql STABLE SET plan_cache_mode = force_custom_plan;
+
+-- Makes a room with a generated dungeon in it. Without a seed one is picked at random, either way
+-- it is kept on the room with the arguments.
+CREATE FUNCTION generate_dungeon(
+    width INT,
+    height INT,
+    room_count INT DEFAULT NULL,
+    min_room_size INT DEFAULT 3,
+    max_room_size INT DEFAULT 20,
+    seed BIGINT DEFAULT NULL
+) RETURNS TABLE (
+    room_id INT,
+    debug_output jsonb,
+    template TEXT
+) AS $$
+WITH new_room AS (
+    INSERT INTO rooms (min_commands, landing_zone, seed, generator)
+    VALUES (
+        1,
+        false,
+        COALESCE(generate_dungeon.seed, (random() * 9007199254740991)::BIGINT),
+        jsonb_build_object(
+            'width', width,
+            'height', height,
+            'room_count', room_count,
+            'min_room_size', min_room_size,
+            'max_room_size', max_room_size
+        )
+    )
+    RETURNING entity_id, rooms.seed
+)
+SELECT r.entity_id, l.debug_output, l.template
+FROM new_room r
+CROSS JOIN LATERAL dungeon_layout(width, height, room_count, min_room_size, max_room_size, r.seed) l
+CROSS JOIN LATERAL create_room_template(r.entity_id, l.template);
+$$ LANGUAGE SQL;
+
+-- The layout a generated room was made with, for comparing with what is there now
+CREATE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.*
+FROM rooms r
+CROSS JOIN LATERAL dungeon_layout(
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    (r.generator->>'room_count')::INT,
+    (r.generator->>'min_room_size')::INT,
+    (r.generator->>'max_room_size')::INT,
+    r.seed
+) l
+WHERE r.entity_id=room_entity_id AND r.generator IS NOT NULL;
+$$ LANGUAGE SQL STABLE;
+
@@ -0,0 +1,95 @@
+DROP FUNCTION admin.generate_level(TEXT, INT, INT, BIGINT, JSONB);
+
+-- generate_dungeon for game masters, with the seed it ended up using
+CREATE FUNCTION admin.generate_dungeon(width INT, height INT, room_count INT, seed BIGINT)
+RETURNS TABLE (room_id INT, used_seed BIGINT, template TEXT) AS $$
+DECLARE
+  generated RECORD;
+BEGIN
+  SELECT * INTO generated FROM generate_dungeon(width, height, room_count, 3, 20, seed) g;
+  room_id := generated.room_id;
+  template := generated.template;
+  SELECT r.seed INTO used_seed FROM rooms r WHERE r.entity_id=generated.room_id;
+  PERFORM admin.audit('generate_dungeon', jsonb_build_object(
+    'room_id', room_id,
+    'width', width,
+    'height', height,
+    'room_count', room_count,
+    'seed', used_seed
+  ));
+  RETURN NEXT;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.generate_dungeon(INT, INT, INT, BIGINT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.generate_dungeon(INT, INT, INT, BIGINT) TO mpd_admin;
+
+-- Makes a room with a generated dungeon in it. Without a seed one is picked at random, either way
+-- it is kept on the room with the arguments.
+CREATE OR REPLACE FUNCTION generate_dungeon(
+    width INT,
+    height INT,
+    room_count INT DEFAULT NULL,
+    min_room_size INT DEFAULT 3,
+    max_room_size INT DEFAULT 20,
+    seed BIGINT DEFAULT NULL,
+    loop_fraction DOUBLE PRECISION DEFAULT 0.15
+) RETURNS TABLE (
+    room_id INT,
+    debug_output jsonb,
+    template TEXT
+) AS $$
+WITH new_room AS (
+    INSERT INTO rooms (min_commands, landing_zone, seed, generator)
+    VALUES (
+        1,
+        false,
+        COALESCE(generate_dungeon.seed, (random() * 9007199254740991)::BIGINT),
+        jsonb_build_object(
+            'width', width,
+            'height', height,
+            'room_count', room_count,
+            'min_room_size', min_room_size,
+            'max_room_size', max_room_size,
+            'loop_fraction', loop_fraction
+        )
+    )
+    RETURNING entity_id, rooms.seed
+)
+SELECT r.entity_id, l.debug_output, l.template
+FROM new_room r
+CROSS JOIN LATERAL dungeon_layout(
+    width, height, room_count, min_room_size, max_room_size, r.seed, loop_fraction
+) l
+CROSS JOIN LATERAL create_room_template(r.entity_id, l.template);
+$$ LANGUAGE SQL;
+
+-- The layout a generated room was made with, for comparing with what is there now
+CREATE OR REPLACE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.*
+FROM rooms r
+CROSS JOIN LATERAL dungeon_layout(
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    (r.generator->>'room_count')::INT,
+    (r.generator->>'min_room_size')::INT,
+    (r.generator->>'max_room_size')::INT,
+    r.seed,
+    COALESCE((r.generator->>'loop_fraction')::DOUBLE PRECISION, 0)
+) l
+WHERE r.entity_id=room_entity_id AND r.generator IS NOT NULL;
+$$ LANGUAGE SQL STABLE;
+
+
+DROP FUNCTION generate_level(TEXT, INT, INT, BIGINT, JSONB);
+DROP FUNCTION level_layout(TEXT, INT, INT, BIGINT, JSONB);
+DROP FUNCTION stamp_vaults(TEXT, BIGINT, INT);
+DROP FUNCTION maze_layout(INT, INT, BIGINT);
+DROP FUNCTION cave_layout(INT, INT, BIGINT, DOUBLE PRECISION, INT);
+DROP FUNCTION grid_template(BOOLEAN[]);
+DROP TABLE admin.vaults;
+ALTER TABLE rooms DROP COLUMN level_type;
@@ -0,0 +1,419 @@
+-- More kinds of level than BSP rooms and corridors, each recorded on the room as its level_type:
+--   bsp   rooms and corridors, see dungeon_layout
+--   cave  open caverns grown by a cellular automaton
+--   maze  one-tile passages with exactly one way between any two places
+-- Any of them can have prefab vaults from admin.vaults stamped into their open spaces.
+ALTER TABLE rooms ADD COLUMN level_type TEXT;
+
+UPDATE rooms SET level_type='bsp' WHERE generator IS NOT NULL;
+
+-- Templates stamped whole into generated levels, legends and all. Vaults stamped into the same
+-- level share one legend, so a symbol has to mean the same thing in every vault.
+CREATE TABLE admin.vaults (
+  name TEXT PRIMARY KEY,
+  template TEXT NOT NULL
+);
+
+INSERT INTO admin.vaults (name, template) VALUES
+('shrine', '
+$ = floor, gold weight=1
+---
+#####
+#+$+#
+#+++#
+##+##
+'),
+('snake pit', '
+S = floor, spawn=snake
+D = floor, door closed
+---
+#####
+#S+S#
+#+++#
+##D##
+');
+
+-- A template from a grid of which tiles are floor, indexed [y][x] from 1. Walls go on every tile
+-- next to a floor, including diagonally, and everything else is left empty.
+CREATE FUNCTION grid_template(floor_at BOOLEAN[])
+RETURNS TEXT AS $$
+DECLARE
+  height INT := array_length(floor_at, 1);
+  width INT := array_length(floor_at, 2);
+  lines TEXT[] := '{}';
+  line TEXT;
+  walled BOOLEAN;
+BEGIN
+  FOR y IN 1..height LOOP
+    line := '';
+    FOR x IN 1..width LOOP
+      IF floor_at[y][x] THEN
+        line := line || '+';
+        CONTINUE;
+      END IF;
+      walled := false;
+      FOR ny IN greatest(y - 1, 1)..least(y + 1, height) LOOP
+        FOR nx IN greatest(x - 1, 1)..least(x + 1, width) LOOP
+          walled := walled OR floor_at[ny][nx];
+        END LOOP;
+      END LOOP;
+      line := line || CASE WHEN walled THEN '#' ELSE ' ' END;
+    END LOOP;
+    lines := lines || line;
+  END LOOP;
+  RETURN array_to_string(lines, E'\n');
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+-- Caves: each tile starts as rock with a chance of fill, then steps times every tile becomes rock if
+-- five or more of the nine tiles around and including it are. Only the largest open area is kept,
+-- so every part of the cave can be reached.
+CREATE FUNCTION cave_layout(width INT, height INT, seed BIGINT, fill DOUBLE PRECISION, steps INT)
+RETURNS TABLE (debug_output jsonb, template TEXT) AS $$
+DECLARE
+  floor_at BOOLEAN[] := array_fill(false, ARRAY[height, width]);
+  smoothed BOOLEAN[];
+  rock INT;
+  region INT[] := array_fill(0, ARRAY[height, width]);
+  sizes INT[] := '{}';
+  largest INT;
+  stack INT[];
+  cx INT;
+  cy INT;
+  size INT;
+  dys INT[] := ARRAY[-1, 1, 0, 0];
+  dxs INT[] := ARRAY[0, 0, -1, 1];
+BEGIN
+  -- The outermost tiles stay rock
+  FOR y IN 2..height-1 LOOP
+    FOR x IN 2..width-1 LOOP
+      floor_at[y][x] := seeded_random(seed, x, y) >= fill;
+    END LOOP;
+  END LOOP;
+
+  FOR step IN 1..steps LOOP
+    smoothed := floor_at;
+    FOR y IN 2..height-1 LOOP
+      FOR x IN 2..width-1 LOOP
+        rock := 0;
+        FOR ny IN y-1..y+1 LOOP
+          FOR nx IN x-1..x+1 LOOP
+            IF NOT floor_at[ny][nx] THEN
+              rock := rock + 1;
+            END IF;
+          END LOOP;
+        END LOOP;
+        smoothed[y][x] := rock < 5;
+      END LOOP;
+    END LOOP;
+    floor_at := smoothed;
+  END LOOP;
+
+  -- Number the open areas, joined up and down and side to side the same as corridors are
+  FOR y IN 2..height-1 LOOP
+    FOR x IN 2..width-1 LOOP
+      CONTINUE WHEN NOT floor_at[y][x] OR region[y][x] > 0;
+      sizes := sizes || 0;
+      region[y][x] := cardinality(sizes);
+      stack := ARRAY[y, x];
+      size := 0;
+      WHILE cardinality(stack) > 0 LOOP
+        cy := stack[cardinality(stack) - 1];
+        cx := stack[cardinality(stack)];
+        stack := stack[1:cardinality(stack) - 2];
+        size := size + 1;
+        FOR d IN 1..4 LOOP
+          IF floor_at[cy + dys[d]][cx + dxs[d]] AND region[cy + dys[d]][cx + dxs[d]] = 0 THEN
+            region[cy + dys[d]][cx + dxs[d]] := cardinality(sizes);
+            stack := stack || ARRAY[cy + dys[d], cx + dxs[d]];
+          END IF;
+        END LOOP;
+      END LOOP;
+      sizes[cardinality(sizes)] := size;
+    END LOOP;
+  END LOOP;
+
+  SELECT i INTO largest FROM unnest(sizes) WITH ORDINALITY AS s(n, i) ORDER BY n DESC, i LIMIT 1;
+  FOR y IN 1..height LOOP
+    FOR x IN 1..width LOOP
+      floor_at[y][x] := region[y][x] = COALESCE(largest, 0) AND largest IS NOT NULL;
+    END LOOP;
+  END LOOP;
+
+  template := grid_template(floor_at);
+  debug_output := jsonb_build_object(
+    'fill', fill,
+    'steps', steps,
+    'regions', to_jsonb(sizes),
+    'template', template
+  );
+  RETURN NEXT;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- Mazes by recursive backtracking: passages run between tiles at odd coordinates, going on to a
+-- random neighbour that hasn't been reached yet and backing up when there is none.
+CREATE FUNCTION maze_layout(width INT, height INT, seed BIGINT)
+RETURNS TABLE (debug_output jsonb, template TEXT) AS $$
+DECLARE
+  floor_at BOOLEAN[] := array_fill(false, ARRAY[height, width]);
+  -- Array indexes start at 1, so the odd coordinates are the even indexes
+  stack INT[] := ARRAY[2, 2];
+  cx INT;
+  cy INT;
+  dy INT;
+  dx INT;
+  step INT := 0;
+BEGIN
+  IF width < 3 OR height < 3 THEN
+    RAISE EXCEPTION 'Mazes need to be at least 3 by 3, not % by %', width, height;
+  END IF;
+  floor_at[2][2] := true;
+  WHILE cardinality(stack) > 0 LOOP
+    cy := stack[cardinality(stack) - 1];
+    cx := stack[cardinality(stack)];
+    step := step + 1;
+    SELECT d.dy, d.dx INTO dy, dx
+    FROM (VALUES (-2, 0), (2, 0), (0, -2), (0, 2)) AS d(dy, dx)
+    WHERE
+      cy + d.dy BETWEEN 2 AND height - 1 AND
+      cx + d.dx BETWEEN 2 AND width - 1 AND
+      NOT floor_at[cy + d.dy][cx + d.dx]
+    ORDER BY seeded_random(seed, step, d.dy, d.dx)
+    LIMIT 1;
+    IF NOT FOUND THEN
+      stack := stack[1:cardinality(stack) - 2];
+      CONTINUE;
+    END IF;
+    floor_at[cy + dy / 2][cx + dx / 2] := true;
+    floor_at[cy + dy][cx + dx] := true;
+    stack := stack || ARRAY[cy + dy, cx + dx];
+  END LOOP;
+
+  template := grid_template(floor_at);
+  debug_output := jsonb_build_object('steps', step, 'template', template);
+  RETURN NEXT;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- Stamps up to count vaults into a template without a legend, each somewhere that is all floor
+-- with a ring of floor around it, so there is always a way round whatever the vault blocks.
+-- Vaults don't share their rings. Returns the template with the vaults' legend in front.
+CREATE FUNCTION stamp_vaults(template TEXT, seed BIGINT, count INT)
+RETURNS TEXT AS $$
+DECLARE
+  lines TEXT[] := regexp_split_to_array(template, '\n');
+  width INT := (SELECT max(length(l)) FROM unnest(lines) AS l);
+  height INT := cardinality(lines);
+  -- Floor around a vault, kept from later vaults until everything is stamped
+  ring CONSTANT TEXT := chr(1);
+  legend JSONB := '{}';
+  vault_legend JSONB;
+  vault admin.vaults;
+  vault_lines TEXT[];
+  legend_end INT;
+  entry TEXT[];
+  map TEXT[];
+  vw INT;
+  vh INT;
+  spot RECORD;
+BEGIN
+  FOR i IN 1..count LOOP
+    SELECT * INTO vault FROM admin.vaults v ORDER BY seeded_random(seed, i, hashtext(v.name)), v.name LIMIT 1;
+    EXIT WHEN NOT FOUND;
+
+    vault_lines := regexp_split_to_array(vault.template, '\n');
+    SELECT n INTO legend_end FROM generate_subscripts(vault_lines, 1) AS n WHERE trim(vault_lines[n])='---' LIMIT 1;
+    vault_legend := '{}';
+    FOR n IN 1..COALESCE(legend_end, 1) - 1 LOOP
+      CONTINUE WHEN length(trim(vault_lines[n])) = 0;
+      entry := regexp_match(vault_lines[n], '^\s*(\S)\s*=(.*)$');
+      IF entry IS NULL THEN
+        RAISE EXCEPTION 'Legend entries look like "D = floor, door", not % in %', vault_lines[n], vault.name;
+      ELSIF legend ? entry[1] AND legend->>entry[1] != trim(entry[2]) THEN
+        RAISE EXCEPTION 'Vaults disagree on what % is, % says %', entry[1], vault.name, trim(entry[2]);
+      END IF;
+      vault_legend := vault_legend || jsonb_build_object(entry[1], trim(entry[2]));
+    END LOOP;
+    map := ARRAY(
+      SELECT replace(l, ' ', '+')
+      FROM unnest(vault_lines[COALESCE(legend_end, 0) + 1:]) WITH ORDINALITY AS ls(l, n)
+      WHERE length(trim(l)) > 0
+      ORDER BY n
+    );
+    vh := cardinality(map);
+    vw := (SELECT max(length(l)) FROM unnest(map) AS l);
+    CONTINUE WHEN vh = 0;
+
+    -- spot is the top left of the ring, 1 based as substring is
+    SELECT sx, sy INTO spot
+    FROM generate_series(1, width - vw - 1) AS sx, generate_series(1, height - vh - 1) AS sy
+    WHERE NOT EXISTS (
+      SELECT 1 FROM generate_series(sy, sy + vh + 1) AS r
+      WHERE substring(lines[r] FROM sx FOR vw + 2) !~ ('^\+{' || (vw + 2) || '}$')
+    )
+    ORDER BY seeded_random(seed, i, sx, sy)
+    LIMIT 1;
+    CONTINUE WHEN NOT FOUND;
+
+    legend := legend || vault_legend;
+    lines[spot.sy] := overlay(lines[spot.sy] PLACING repeat(ring, vw + 2) FROM spot.sx);
+    lines[spot.sy + vh + 1] := overlay(lines[spot.sy + vh + 1] PLACING repeat(ring, vw + 2) FROM spot.sx);
+    FOR r IN 1..vh LOOP
+      lines[spot.sy + r] := overlay(
+        lines[spot.sy + r] PLACING ring || rpad(map[r], vw, '+') || ring FROM spot.sx
+      );
+    END LOOP;
+  END LOOP;
+
+  RETURN COALESCE(
+    (
+      SELECT string_agg(key || ' = ' || value, E'\n' ORDER BY key) || E'\n---\n'
+      FROM jsonb_each_text(legend)
+    ),
+    ''
+  ) || replace(array_to_string(lines, E'\n'), ring, '+');
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- The template for a level_type, with any vaults asked for stamped in. options are the arguments
+-- particular to the level type, as generate_level keeps them on the room:
+--   bsp   room_count, min_room_size, max_room_size and loop_fraction, as dungeon_layout takes them
+--   cave  fill, how much starts as rock, and steps, how many times it is smoothed
+--   maze  nothing
+-- and vaults, how many vaults to try and stamp.
+CREATE FUNCTION level_layout(level_type TEXT, width INT, height INT, seed BIGINT, options JSONB)
+RETURNS TABLE (debug_output jsonb, template TEXT) AS $$
+DECLARE
+  layout RECORD;
+BEGIN
+  CASE level_type
+    WHEN 'bsp' THEN
+      SELECT * INTO layout FROM dungeon_layout(
+        width,
+        height,
+        (options->>'room_count')::INT,
+        COALESCE((options->>'min_room_size')::INT, 3),
+        COALESCE((options->>'max_room_size')::INT, 20),
+        seed,
+        COALESCE((options->>'loop_fraction')::DOUBLE PRECISION, 0.15)
+      );
+    WHEN 'cave' THEN
+      SELECT * INTO layout FROM cave_layout(
+        width,
+        height,
+        seed,
+        COALESCE((options->>'fill')::DOUBLE PRECISION, 0.45),
+        COALESCE((options->>'steps')::INT, 4)
+      );
+    WHEN 'maze' THEN
+      SELECT * INTO layout FROM maze_layout(width, height, seed);
+    ELSE
+      RAISE EXCEPTION 'Unknown level type %', level_type;
+  END CASE;
+  debug_output := layout.debug_output;
+  template := layout.template;
+  IF COALESCE((options->>'vaults')::INT, 0) > 0 THEN
+    template := stamp_vaults(template, seed, (options->>'vaults')::INT);
+    debug_output := debug_output || jsonb_build_object('template', template);
+  END IF;
+  RETURN NEXT;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- Makes a room with a generated level in it. Without a seed one is picked at random, either way
+-- it is kept on the room with the level type, size and options.
+CREATE FUNCTION generate_level(
+    level_type TEXT,
+    width INT,
+    height INT,
+    seed BIGINT DEFAULT NULL,
+    options JSONB DEFAULT '{}'
+) RETURNS TABLE (
+    room_id INT,
+    debug_output jsonb,
+    template TEXT
+) AS $$
+WITH new_room AS (
+    INSERT INTO rooms (min_commands, landing_zone, seed, generator, level_type)
+    VALUES (
+        1,
+        false,
+        COALESCE(generate_level.seed, (random() * 9007199254740991)::BIGINT),
+        options || jsonb_build_object('width', width, 'height', height),
+        generate_level.level_type
+    )
+    RETURNING entity_id, rooms.seed
+)
+SELECT r.entity_id, l.debug_output, l.template
+FROM new_room r
+CROSS JOIN LATERAL level_layout(generate_level.level_type, width, height, r.seed, options) l
+CROSS JOIN LATERAL create_room_template(r.entity_id, l.template);
+$$ LANGUAGE SQL;
+
+-- A BSP level
+CREATE OR REPLACE FUNCTION generate_dungeon(
+    width INT,
+    height INT,
+    room_count INT DEFAULT NULL,
+    min_room_size INT DEFAULT 3,
+    max_room_size INT DEFAULT 20,
+    seed BIGINT DEFAULT NULL,
+    loop_fraction DOUBLE PRECISION DEFAULT 0.15
+) RETURNS TABLE (
+    room_id INT,
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT * FROM generate_level('bsp', width, height, seed, jsonb_build_object(
+    'room_count', room_count,
+    'min_room_size', min_room_size,
+    'max_room_size', max_room_size,
+    'loop_fraction', loop_fraction
+));
+$$ LANGUAGE SQL;
+
+-- The layout a generated room was made with, for comparing with what is there now
+CREATE OR REPLACE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.*
+FROM rooms r
+CROSS JOIN LATERAL level_layout(
+    COALESCE(r.level_type, 'bsp'),
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    r.seed,
+    r.generator
+) l
+WHERE r.entity_id=room_entity_id AND r.generator IS NOT NULL;
+$$ LANGUAGE SQL STABLE;
+
+DROP FUNCTION admin.generate_dungeon(INT, INT, INT, BIGINT);
+
+-- generate_level for game masters, with the seed it ended up using
+CREATE FUNCTION admin.generate_level(level_type TEXT, width INT, height INT, seed BIGINT, options JSONB)
+RETURNS TABLE (room_id INT, used_seed BIGINT, template TEXT) AS $$
+DECLARE
+  generated RECORD;
+BEGIN
+  SELECT * INTO generated FROM generate_level(level_type, width, height, seed, options) g;
+  room_id := generated.room_id;
+  template := generated.template;
+  SELECT r.seed INTO used_seed FROM rooms r WHERE r.entity_id=generated.room_id;
+  PERFORM admin.audit('generate_level', jsonb_build_object(
+    'room_id', room_id,
+    'level_type', level_type,
+    'width', width,
+    'height', height,
+    'options', options,
+    'seed', used_seed
+  ));
+  RETURN NEXT;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.generate_level(TEXT, INT, INT, BIGINT, JSONB) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.generate_level(TEXT, INT, INT, BIGINT, JSONB) TO mpd_admin;
@@ -0,0 +1,19 @@
+DROP FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, SMALLINT[], SMALLINT[], TEXT[], BOOLEAN[]);
+
+-- The layout a generated room was made with, for comparing with what is there now
+CREATE OR REPLACE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.*
+FROM rooms r
+CROSS JOIN LATERAL level_layout(
+    COALESCE(r.level_type, 'bsp'),
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    r.seed,
+    r.generator
+) l
+WHERE r.entity_id=room_entity_id AND r.generator IS NOT NULL;
+$$ LANGUAGE SQL STABLE;
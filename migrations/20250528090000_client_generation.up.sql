@@ -0,0 +1,74 @@
+-- Levels generated by the client, see src/dungeon.rs, rather than by level_layout. Every tile comes
+-- in as arrays and goes into species, positions and impassibles in a statement each, instead of the
+-- handful of inserts per tile create_room_template makes. The room's generator has engine 'rust'.
+CREATE FUNCTION admin.create_level(
+    level_type TEXT,
+    width INT,
+    height INT,
+    seed BIGINT,
+    options JSONB,
+    xs SMALLINT[],
+    ys SMALLINT[],
+    tile_species TEXT[],
+    tile_impassible BOOLEAN[]
+) RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+BEGIN
+  INSERT INTO rooms (min_commands, landing_zone, seed, generator, level_type)
+  VALUES (
+    1,
+    false,
+    seed,
+    options || jsonb_build_object('width', width, 'height', height, 'engine', 'rust'),
+    level_type
+  )
+  RETURNING entity_id INTO room;
+
+  WITH tiles AS (
+    SELECT nextval('entities_idx')::INTEGER AS entity_id, t.x, t.y, t.species, t.impassible
+    FROM unnest(xs, ys, tile_species, tile_impassible) AS t(x, y, species, impassible)
+  ), new_species AS (
+    INSERT INTO species (entity_id, species) SELECT t.entity_id, t.species FROM tiles t
+  ), new_positions AS (
+    INSERT INTO positions (entity_id, x, y, room_id) SELECT t.entity_id, t.x, t.y, room FROM tiles t
+  )
+  INSERT INTO impassibles (entity_id) SELECT t.entity_id FROM tiles t WHERE t.impassible;
+
+  PERFORM admin.audit('create_level', jsonb_build_object(
+    'room_id', room,
+    'level_type', level_type,
+    'width', width,
+    'height', height,
+    'options', options,
+    'seed', seed,
+    'tiles', cardinality(xs)
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, SMALLINT[], SMALLINT[], TEXT[], BOOLEAN[]) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, SMALLINT[], SMALLINT[], TEXT[], BOOLEAN[]) TO mpd_admin;
+
+-- The layout a generated room was made with, for comparing with what is there now. Rooms the client
+-- generated came from its own generators, which this can't run.
+CREATE OR REPLACE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.*
+FROM rooms r
+CROSS JOIN LATERAL level_layout(
+    COALESCE(r.level_type, 'bsp'),
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    r.seed,
+    r.generator
+) l
+WHERE
+  r.entity_id=room_entity_id AND
+  r.generator IS NOT NULL AND
+  r.generator->>'engine' IS DISTINCT FROM 'rust';
+$$ LANGUAGE SQL STABLE;
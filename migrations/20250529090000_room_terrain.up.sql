@@ -0,0 +1,508 @@
+-- Walls and floors are kept on their room as a grid of symbols, one string per row the same as a
+-- template's lines: # for a wall, + for a floor and a space for nothing. positions is left for the
+-- things that move, open, get picked up or lead somewhere, which were always a fraction of the
+-- entities in a room. Clients fetch the terrain with api.terrain when they arrive in a room.
+ALTER TABLE rooms ADD COLUMN terrain TEXT[] NOT NULL DEFAULT '{}';
+
+-- The symbol at x, y from 0, 0 at the top left, NULL off the grid
+CREATE FUNCTION terrain_at(terrain TEXT[], x INTEGER, y INTEGER)
+RETURNS TEXT AS $$
+  SELECT NULLIF(substring(terrain[y + 1] FROM x + 1 FOR 1), '');
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Every wall and floor of a terrain, by the species they were as entities
+CREATE FUNCTION terrain_tiles(terrain TEXT[])
+RETURNS TABLE (x SMALLINT, y SMALLINT, species TEXT) AS $$
+  SELECT
+    (col - 1)::SMALLINT,
+    (row - 1)::SMALLINT,
+    CASE substring(terrain[row] FROM col FOR 1) WHEN '#' THEN 'wall' ELSE 'floor' END
+  FROM generate_subscripts(terrain, 1) AS row, generate_series(1, length(terrain[row])) AS col
+  WHERE substring(terrain[row] FROM col FOR 1) IN ('#', '+');
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Whether a move onto x, y is stopped, by a wall or by something impassible such as a closed door
+CREATE FUNCTION blocked(room INTEGER, x INTEGER, y INTEGER)
+RETURNS BOOLEAN AS $$
+  SELECT
+    EXISTS (
+      SELECT 1 FROM rooms r WHERE r.entity_id=blocked.room AND terrain_at(r.terrain, blocked.x, blocked.y)='#'
+    ) OR
+    EXISTS (
+      SELECT 1
+      FROM positions p
+      INNER JOIN impassibles i ON i.entity_id=p.entity_id
+      WHERE p.room_id=blocked.room AND p.x=blocked.x AND p.y=blocked.y
+    );
+$$ LANGUAGE SQL STABLE;
+
+-- Walls and floors that are nothing more than that move into their room's terrain. Where a wall and
+-- a floor share a tile the wall wins, as it did when it blocked the way.
+CREATE TEMPORARY TABLE moved_tiles AS
+SELECT p.entity_id, p.room_id, p.x, p.y, CASE s.species WHEN 'wall' THEN '#' ELSE '+' END AS symbol
+FROM positions p
+INNER JOIN species s ON s.entity_id=p.entity_id
+INNER JOIN rooms r ON r.entity_id=p.room_id
+LEFT JOIN impassibles i ON i.entity_id=p.entity_id
+WHERE
+  p.x >= 0 AND p.y >= 0 AND
+  (s.species='wall' AND i.entity_id IS NOT NULL OR s.species='floor' AND i.entity_id IS NULL) AND
+  NOT EXISTS (SELECT 1 FROM names n WHERE n.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM hps h WHERE h.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM monsters m WHERE m.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM weights w WHERE w.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM doors d WHERE d.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM keys k WHERE k.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM portal_labels l WHERE l.entity_id=p.entity_id) AND
+  NOT EXISTS (SELECT 1 FROM commands c WHERE c.entity_id=p.entity_id) AND
+  NOT EXISTS (
+    SELECT 1 FROM portals pt WHERE pt.start_entity_id=p.entity_id OR pt.end_entity_id=p.entity_id
+  );
+
+WITH symbols AS (
+  SELECT room_id, x, y, min(symbol) AS symbol
+  FROM moved_tiles
+  GROUP BY room_id, x, y
+),
+lines AS (
+  SELECT rows.room_id, rows.y, string_agg(COALESCE(s.symbol, ' '), '' ORDER BY cols.x) AS line
+  FROM (
+    SELECT room_id, row_y AS y, max(x) FILTER (WHERE y=row_y) AS width
+    FROM symbols, LATERAL generate_series(0, (SELECT max(y) FROM symbols m WHERE m.room_id=symbols.room_id)) AS row_y
+    GROUP BY room_id, row_y
+  ) rows
+  LEFT JOIN LATERAL generate_series(0, rows.width) AS cols(x) ON true
+  LEFT JOIN symbols s ON s.room_id=rows.room_id AND s.y=rows.y AND s.x=cols.x
+  GROUP BY rows.room_id, rows.y
+)
+UPDATE rooms SET terrain = t.terrain
+FROM (
+  SELECT room_id, array_agg(COALESCE(line, '') ORDER BY y) AS terrain FROM lines GROUP BY room_id
+) t
+WHERE rooms.entity_id=t.room_id;
+
+DELETE FROM impassibles WHERE entity_id IN (SELECT entity_id FROM moved_tiles);
+DELETE FROM species WHERE entity_id IN (SELECT entity_id FROM moved_tiles);
+DELETE FROM positions WHERE entity_id IN (SELECT entity_id FROM moved_tiles);
+DROP TABLE moved_tiles;
+
+-- Walls are in the terrain
+CREATE OR REPLACE FUNCTION room_tick()
+RETURNS TRIGGER AS $$
+BEGIN
+  WITH triggered_rooms AS (
+    SELECT 
+      p.room_id
+    FROM 
+      positions p
+    LEFT JOIN positions po ON 
+      po.room_id=p.room_id
+    INNER JOIN players pl ON 
+      pl.entity_id=po.entity_id
+    LEFT JOIN commands c ON 
+      c.entity_id=pl.entity_id
+    LEFT JOIN rooms r ON r.entity_id=p.room_id
+    WHERE p.entity_id=NEW.entity_id
+    GROUP BY p.room_id, r.min_commands
+    HAVING (COUNT(po.*) = COUNT(c.*)) OR (COUNT(c.*) >= r.min_commands)
+  ),
+  monster_attack_commands AS (
+    SELECT p.entity_id::int, 'attack'::text, null::smallint, null::smallint, com.targ_entity_id::int
+    FROM 
+      triggered_rooms t,
+      positions p,
+      monsters m,
+      hps h,
+      LATERAL (
+        SELECT hs.entity_id "targ_entity_id"
+        FROM hps h 
+        INNER JOIN positions hs ON hs.entity_id=h.entity_id AND hs.room_id=p.room_id
+        WHERE h.entity_id != m.entity_id AND abs(hs.x - p.x) <= 1 AND abs(hs.y - p.y) <= 1
+        LIMIT 1
+      ) com
+      WHERE p.room_id=t.room_id AND p.entity_id=m.entity_id AND h.entity_id=p.entity_id AND h.hp > 0
+  ),
+  monster_move_commands AS (
+    SELECT p.entity_id::int, 'move'::text, gx, gy, null::int
+    FROM 
+      triggered_rooms t,
+      positions p,
+      monsters m,
+      hps h,
+      LATERAL (
+        SELECT x,y
+        FROM positions pt
+        INNER JOIN hps ht ON ht.entity_id=pt.entity_id
+        WHERE pt.room_id=p.room_id AND pt.entity_id != p.entity_id AND ht.hp > 0
+        ORDER BY ABS(pt.x-p.x) + ABS(pt.y - p.y) ASC
+        LIMIT 1
+      ) targ,
+      LATERAL (
+        SELECT gx ,gy 
+        FROM generate_series(-1,1) gx
+        CROSS JOIN generate_series(-1,1) gy
+        WHERE NOT blocked(p.room_id, p.x+gx, p.y+gy)
+        ORDER BY ABS(p.x+gx-targ.x) + ABS(p.y+gy-targ.y) ASC
+        LIMIT 1
+      ) com
+    WHERE 
+      p.room_id=t.room_id AND 
+      p.entity_id=m.entity_id AND 
+      h.entity_id=p.entity_id AND 
+      h.hp > 0 AND 
+      NOT EXISTS (SELECT mac.entity_id FROM monster_attack_commands mac WHERE mac.entity_id=p.entity_id)
+  ),
+  removed_commands AS (
+    DELETE FROM commands
+    USING positions p 
+    WHERE 
+      p.room_id IN (SELECT room_id FROM triggered_rooms) AND 
+      commands.entity_id=p.entity_id
+    RETURNING commands.*
+  ),
+  actioned_commands AS (
+    SELECT rm.* 
+    FROM removed_commands rm
+    INNER JOIN hps ON hps.hp > 0 AND hps.entity_id=rm.entity_id
+    UNION ALL
+    SELECT *
+    FROM monster_attack_commands
+    UNION ALL
+    SELECT *
+    FROM monster_move_commands
+  ),
+  travels AS (
+    UPDATE positions SET 
+      x=targ_p.x,
+      y=targ_p.y,
+      room_id=targ_p.room_id
+    FROM actioned_commands c
+    INNER JOIN portals p ON p.start_entity_id=c.target
+    INNER JOIN positions targ_p ON targ_p.entity_id=p.end_entity_id
+    WHERE c.command_type='travel' AND positions.entity_id=c.entity_id
+  ),
+  picked_up AS (
+    UPDATE positions SET
+      x = 0,
+      y = 0,
+      room_id = c.entity_id
+    FROM commands c
+    WHERE positions.entity_id=c.target AND c.command_type='pickup'
+  ),
+  dropped AS (
+    UPDATE positions SET
+      x = player_pos.x,
+      y = player_pos.y,
+      room_id = player_pos.room_id
+    FROM actioned_commands c
+    INNER JOIN positions player_pos ON player_pos.entity_id = c.entity_id
+    WHERE positions.entity_id = c.target AND c.command_type = 'drop'
+  ),
+  -- Doors next to whoever opens or closes them, the first command for each if there are several.
+  -- Locked doors only open for someone carrying a key to them, and nothing can close on someone.
+  door_commands AS (
+    SELECT DISTINCT ON (d.entity_id) c.command_type, d.entity_id, k.has_key
+    FROM actioned_commands c
+    INNER JOIN doors d ON d.entity_id=c.target
+    INNER JOIN positions dp ON dp.entity_id=d.entity_id
+    INNER JOIN positions pp ON pp.entity_id=c.entity_id
+    CROSS JOIN LATERAL (
+      SELECT EXISTS (
+        SELECT 1 FROM keys
+        INNER JOIN positions kp ON kp.entity_id=keys.entity_id
+        WHERE kp.room_id=c.entity_id AND keys.lock=d.lock
+      ) has_key
+    ) k
+    WHERE
+      c.command_type IN ('open', 'close') AND
+      dp.room_id=pp.room_id AND abs(dp.x - pp.x) <= 1 AND abs(dp.y - pp.y) <= 1 AND
+      (c.command_type='close' OR NOT d.locked OR k.has_key) AND
+      (c.command_type='open' OR NOT EXISTS (
+        SELECT 1 FROM positions op
+        INNER JOIN hps ON hps.entity_id=op.entity_id
+        WHERE op.room_id=dp.room_id AND op.x=dp.x AND op.y=dp.y
+      ))
+    ORDER BY d.entity_id, c.entity_id
+  ),
+  -- Closing a door with its key on you locks it again
+  doors_changed AS (
+    UPDATE doors SET
+      open = dc.command_type='open',
+      locked = dc.command_type='close' AND dc.has_key
+    FROM door_commands dc
+    WHERE doors.entity_id=dc.entity_id
+  ),
+  doors_opened AS (
+    DELETE FROM impassibles
+    USING door_commands dc
+    WHERE impassibles.entity_id=dc.entity_id AND dc.command_type='open'
+  ),
+  doors_closed AS (
+    INSERT INTO impassibles (entity_id)
+    SELECT entity_id FROM door_commands WHERE command_type='close'
+    ON CONFLICT DO NOTHING
+  ),
+  new_pos AS (
+    UPDATE positions SET
+      x = positions.x + c.x,
+      y = positions.y + c.y
+    FROM actioned_commands c
+    WHERE 
+      positions.entity_id=c.entity_id AND 
+      c.command_type='move' AND 
+      positions.room_id IN (SELECT room_id FROM triggered_rooms)
+      AND NOT blocked(positions.room_id, positions.x+c.x, positions.y+c.y)
+      RETURNING *
+    )
+  UPDATE hps 
+  SET hp=hp-1
+  FROM actioned_commands c
+  WHERE c.target=hps.entity_id AND c.command_type='attack';
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- A layer that is only a wall or only a floor paints the room's terrain instead of being an entity
+CREATE OR REPLACE FUNCTION create_room_template(room_entity_id INT, template TEXT)
+RETURNS VOID AS $$
+DECLARE
+  lines TEXT[] := regexp_split_to_array(template, '\n');
+  legend_end INTEGER;
+  legend JSONB := jsonb_build_object(
+    '#', jsonb_build_array(template_layer('wall impassible')),
+    '+', jsonb_build_array(template_layer('floor'))
+  );
+  entry TEXT[];
+  map TEXT := template;
+  tile RECORD;
+  layer RECORD;
+  entity INTEGER;
+  painted TEXT[] := (SELECT r.terrain FROM rooms r WHERE r.entity_id=room_entity_id);
+  painted_row TEXT;
+BEGIN
+  SELECT i INTO legend_end FROM generate_subscripts(lines, 1) AS i WHERE trim(lines[i])='---' LIMIT 1;
+  IF legend_end IS NOT NULL THEN
+    FOR i IN 1..legend_end-1 LOOP
+      CONTINUE WHEN length(trim(lines[i])) = 0;
+      entry := regexp_match(lines[i], '^\s*(\S)\s*=(.*)$');
+      IF entry IS NULL THEN
+        RAISE EXCEPTION 'Legend entries look like "D = floor, door", not %', lines[i];
+      END IF;
+      legend := legend || jsonb_build_object(entry[1], (
+        SELECT jsonb_agg(template_layer(part) ORDER BY n)
+        FROM unnest(string_to_array(entry[2], ',')) WITH ORDINALITY AS parts(part, n)
+      ));
+    END LOOP;
+    map := array_to_string(lines[legend_end+1:], E'\n');
+  END IF;
+
+  FOR tile IN
+    WITH map_lines AS (
+      SELECT row_number() OVER () - 1 AS y, line
+      FROM regexp_split_to_table(map, '\n') AS line
+      WHERE length(trim(line)) > 0
+    )
+    SELECT x::SMALLINT AS x, y::SMALLINT AS y, substring(line FROM x+1 FOR 1) AS symbol
+    FROM map_lines, LATERAL generate_series(0, length(line)-1) AS x
+    WHERE substring(line FROM x+1 FOR 1) != ' '
+  LOOP
+    IF NOT legend ? tile.symbol THEN
+      RAISE EXCEPTION 'Unknown symbol % at %, %', tile.symbol, tile.x, tile.y;
+    END IF;
+    FOR layer IN
+      SELECT * FROM jsonb_to_recordset(legend -> tile.symbol) AS l(
+        species TEXT, impassible BOOLEAN, monster BOOLEAN, hp INTEGER, weight INTEGER, portal TEXT,
+        door TEXT, lock TEXT, key TEXT
+      )
+    LOOP
+      IF
+        (layer.species='wall' AND layer.impassible OR layer.species='floor' AND layer.impassible IS NOT TRUE) AND
+        layer.monster IS NULL AND layer.hp IS NULL AND layer.weight IS NULL AND layer.portal IS NULL AND
+        layer.door IS NULL AND layer.lock IS NULL AND layer.key IS NULL
+      THEN
+        painted_row := COALESCE(painted[tile.y + 1], '');
+        painted[tile.y + 1] := overlay(
+          rpad(painted_row, greatest(length(painted_row), tile.x + 1))
+          PLACING CASE layer.species WHEN 'wall' THEN '#' ELSE '+' END
+          FROM tile.x + 1
+        );
+        CONTINUE;
+      END IF;
+      INSERT INTO species (species) VALUES (layer.species) RETURNING entity_id INTO entity;
+      INSERT INTO positions (entity_id, x, y, room_id) VALUES (entity, tile.x, tile.y, room_entity_id);
+      IF layer.impassible OR layer.door IN ('closed', 'locked') THEN
+        INSERT INTO impassibles (entity_id) VALUES (entity);
+      END IF;
+      IF layer.door IS NOT NULL THEN
+        INSERT INTO doors (entity_id, open, locked, lock)
+        VALUES (entity, layer.door='open', layer.door='locked', layer.lock);
+      END IF;
+      IF layer.key IS NOT NULL THEN
+        INSERT INTO keys (entity_id, lock) VALUES (entity, layer.key);
+      END IF;
+      IF layer.monster THEN
+        INSERT INTO monsters (entity_id) VALUES (entity);
+      END IF;
+      IF layer.hp IS NOT NULL THEN
+        INSERT INTO hps (entity_id, hp, maxhp) VALUES (entity, layer.hp, layer.hp);
+      END IF;
+      IF layer.weight IS NOT NULL THEN
+        INSERT INTO weights (entity_id, weight) VALUES (entity, layer.weight);
+      END IF;
+      IF layer.portal IS NOT NULL THEN
+        WITH other_ends AS (
+          SELECT l.entity_id
+          FROM portal_labels l
+          INNER JOIN positions p ON p.entity_id=l.entity_id
+          WHERE l.label=layer.portal AND p.room_id != room_entity_id
+        )
+        INSERT INTO portals (start_entity_id, end_entity_id)
+        SELECT entity, entity_id FROM other_ends
+        UNION ALL
+        SELECT entity_id, entity FROM other_ends;
+        INSERT INTO portal_labels (entity_id, label) VALUES (entity, layer.portal);
+      END IF;
+    END LOOP;
+  END LOOP;
+
+  -- Rows below the end of an empty terrain start out NULL, and it may not start at 1
+  UPDATE rooms SET terrain = COALESCE(
+    (SELECT array_agg(COALESCE(painted[i], '') ORDER BY i) FROM generate_series(1, array_upper(painted, 1)) AS i),
+    '{}'
+  )
+  WHERE entity_id=room_entity_id;
+END;
+$$ LANGUAGE plpgsql;
+
+-- The terrain is replaced along with everything else
+CREATE OR REPLACE FUNCTION admin.save_room(room_name TEXT, template TEXT)
+RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+  replaced INTEGER[];
+BEGIN
+  SELECT r.entity_id INTO room
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  WHERE n.name=room_name;
+
+  IF room IS NULL THEN
+    INSERT INTO rooms (min_commands, landing_zone) VALUES (1, false) RETURNING entity_id INTO room;
+    INSERT INTO names (entity_id, name) VALUES (room, room_name);
+  ELSE
+    UPDATE rooms SET terrain='{}' WHERE entity_id=room;
+    SELECT ARRAY_AGG(p.entity_id) INTO replaced
+    FROM positions p
+    LEFT JOIN species s ON s.entity_id=p.entity_id
+    LEFT JOIN monsters m ON m.entity_id=p.entity_id
+    WHERE
+      p.room_id=room AND
+      (s.species IN ('wall', 'floor', 'door', 'upstair', 'downstair') OR m.entity_id IS NOT NULL);
+
+    DELETE FROM portals WHERE start_entity_id = ANY(replaced) OR end_entity_id = ANY(replaced);
+    DELETE FROM portal_labels WHERE entity_id = ANY(replaced);
+    DELETE FROM doors WHERE entity_id = ANY(replaced);
+    DELETE FROM impassibles WHERE entity_id = ANY(replaced);
+    DELETE FROM monsters WHERE entity_id = ANY(replaced);
+    DELETE FROM hps WHERE entity_id = ANY(replaced);
+    DELETE FROM weights WHERE entity_id = ANY(replaced);
+    DELETE FROM commands WHERE entity_id = ANY(replaced);
+    DELETE FROM species WHERE entity_id = ANY(replaced);
+    DELETE FROM positions WHERE entity_id = ANY(replaced);
+  END IF;
+
+  PERFORM paint_room_template(room, template);
+  PERFORM admin.audit('save_room', jsonb_build_object(
+    'room_id', room,
+    'name', room_name,
+    'template', template
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- The terrain as the wall and floor tiles it used to be
+CREATE OR REPLACE FUNCTION admin.room_tiles(room_name TEXT)
+RETURNS TABLE (x SMALLINT, y SMALLINT, species TEXT, monster BOOLEAN) AS $$
+  SELECT t.x, t.y, t.species, false
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  CROSS JOIN LATERAL terrain_tiles(r.terrain) t
+  WHERE n.name=room_name
+  UNION ALL
+  SELECT p.x, p.y, s.species, m.entity_id IS NOT NULL
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  INNER JOIN positions p ON p.room_id=r.entity_id
+  INNER JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN monsters m ON m.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  WHERE n.name=room_name AND (m.entity_id IS NULL OR h.hp > 0)
+  ORDER BY y, x;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+DROP FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, SMALLINT[], SMALLINT[], TEXT[], BOOLEAN[]);
+
+-- Levels generated by the client, see src/dungeon.rs, rather than by level_layout. The terrain
+-- comes in whole, so a level is a single insert however big it is. The room's generator has
+-- engine 'rust'.
+CREATE FUNCTION admin.create_level(
+    level_type TEXT,
+    width INT,
+    height INT,
+    seed BIGINT,
+    options JSONB,
+    terrain TEXT[]
+) RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+BEGIN
+  INSERT INTO rooms (min_commands, landing_zone, seed, generator, level_type, terrain)
+  VALUES (
+    1,
+    false,
+    seed,
+    options || jsonb_build_object('width', width, 'height', height, 'engine', 'rust'),
+    level_type,
+    terrain
+  )
+  RETURNING entity_id INTO room;
+
+  PERFORM admin.audit('create_level', jsonb_build_object(
+    'room_id', room,
+    'level_type', level_type,
+    'width', width,
+    'height', height,
+    'options', options,
+    'seed', seed
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[]) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[]) TO mpd_admin;
+
+-- The terrain of the player's room. It only changes when the room is rebuilt, so clients fetch it
+-- when they arrive in a room rather than with every snapshot.
+CREATE FUNCTION api.terrain(session TEXT)
+RETURNS TABLE (room_id INTEGER, terrain TEXT[]) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT r.entity_id, r.terrain
+  FROM positions p
+  INNER JOIN rooms r ON r.entity_id=p.room_id
+  WHERE p.entity_id=current_player();
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION api.terrain(TEXT) OWNER TO mpd_game;
+REVOKE EXECUTE ON FUNCTION api.terrain(TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION api.terrain(TEXT) TO mpd_player;
+
+-- Snapshots no longer have the walls and floors older clients draw rooms from
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 2;
+$$ LANGUAGE SQL IMMUTABLE;
@@ -0,0 +1,28 @@
+CREATE OR REPLACE FUNCTION api.say(session TEXT, channel TEXT, recipient TEXT, message TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  IF current_player() IS NULL THEN
+    RETURN false;
+  END IF;
+  CASE channel
+    WHEN 'room' THEN
+      INSERT INTO messages (speaker, message, channel, room_id)
+      VALUES (current_player(), say.message, 'room', current_room());
+    WHEN 'world' THEN
+      INSERT INTO messages (speaker, message, channel)
+      VALUES (current_player(), say.message, 'world');
+    WHEN 'direct' THEN
+      INSERT INTO messages (speaker, recipient, message, channel)
+      SELECT DISTINCT ON (n.entity_id) current_player(), n.entity_id, say.message, 'direct'
+      FROM names n
+      -- Rooms have names too, but can't be spoken to
+      WHERE
+        lower(n.name)=lower(say.recipient) AND
+        NOT EXISTS (SELECT 1 FROM rooms r WHERE r.entity_id=n.entity_id);
+    ELSE
+      RAISE EXCEPTION 'unknown channel %', channel;
+  END CASE;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
@@ -0,0 +1,47 @@
+-- A tell goes to one entity. Players come before anything else with their name, then whoever is
+-- in the speaker's room, and a name that still matches more than one is an error.
+CREATE OR REPLACE FUNCTION api.say(session TEXT, channel TEXT, recipient TEXT, message TEXT)
+RETURNS BOOLEAN AS $$
+DECLARE
+  recipients INTEGER[];
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  IF current_player() IS NULL THEN
+    RETURN false;
+  END IF;
+  CASE channel
+    WHEN 'room' THEN
+      INSERT INTO messages (speaker, message, channel, room_id)
+      VALUES (current_player(), say.message, 'room', current_room());
+    WHEN 'world' THEN
+      INSERT INTO messages (speaker, message, channel)
+      VALUES (current_player(), say.message, 'world');
+    WHEN 'direct' THEN
+      SELECT array_agg(c.entity_id ORDER BY c.entity_id) INTO recipients
+      FROM (
+        SELECT
+          n.entity_id,
+          rank() OVER (ORDER BY
+            EXISTS (SELECT 1 FROM players pl WHERE pl.entity_id=n.entity_id) DESC,
+            p.room_id IS NOT DISTINCT FROM current_room() DESC
+          ) AS preference
+        FROM names n
+        LEFT JOIN positions p ON p.entity_id=n.entity_id
+        -- Rooms have names too, but can't be spoken to
+        WHERE
+          lower(n.name)=lower(say.recipient) AND
+          NOT EXISTS (SELECT 1 FROM rooms r WHERE r.entity_id=n.entity_id)
+      ) c
+      WHERE c.preference=1;
+      IF cardinality(recipients) > 1 THEN
+        RAISE EXCEPTION 'There is more than one called %', say.recipient;
+      END IF;
+      INSERT INTO messages (speaker, recipient, message, channel)
+      SELECT current_player(), r, say.message, 'direct'
+      FROM unnest(recipients) r;
+    ELSE
+      RAISE EXCEPTION 'unknown channel %', channel;
+  END CASE;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
@@ -0,0 +1,41 @@
+DROP FUNCTION api.chat(TEXT, BIGINT, BIGINT, BIGINT);
+ALTER TYPE api.chat_line DROP ATTRIBUTE message_id;
+
+-- A page of the chat the player can see, newest first, before and/or after a point in time
+CREATE FUNCTION api.chat(session TEXT, before TIMESTAMPTZ, after TIMESTAMPTZ, page_size BIGINT)
+RETURNS SETOF api.chat_line AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  -- The policy on messages picks out what this player may read
+  RETURN QUERY
+  SELECT
+    COALESCE(sn.name, ss.species),
+    COALESCE(rn.name, rs.species),
+    m.channel,
+    m.message,
+    m.sent_at
+  FROM messages m
+  LEFT JOIN names sn ON sn.entity_id=m.speaker
+  LEFT JOIN species ss ON ss.entity_id=m.speaker
+  LEFT JOIN names rn ON rn.entity_id=m.recipient
+  LEFT JOIN species rs ON rs.entity_id=m.recipient
+  WHERE
+    current_player() IS NOT NULL AND
+    (before IS NULL OR m.sent_at < before) AND
+    (after IS NULL OR m.sent_at > after)
+  ORDER BY m.sent_at DESC
+  LIMIT page_size;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+
+ALTER FUNCTION api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT) OWNER TO mpd_game;
+REVOKE EXECUTE ON FUNCTION api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT) TO mpd_player;
+
+ALTER TABLE messages DROP COLUMN message_id;
+
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 2;
+$$ LANGUAGE SQL IMMUTABLE;
@@ -0,0 +1,52 @@
+-- Lines sent in the same transaction share sent_at, so chat is paged by an id of its own. Older
+-- messages are numbered in the order they were sent.
+ALTER TABLE messages ADD COLUMN message_id BIGSERIAL;
+UPDATE messages m
+SET message_id=o.message_id
+FROM (SELECT ctid, row_number() OVER (ORDER BY sent_at, ctid) AS message_id FROM messages) o
+WHERE m.ctid=o.ctid;
+SELECT setval(pg_get_serial_sequence('messages', 'message_id'), COALESCE(MAX(message_id), 0) + 1, false)
+FROM messages;
+ALTER TABLE messages ADD PRIMARY KEY (message_id);
+GRANT USAGE ON SEQUENCE messages_message_id_seq TO mpd_game;
+
+DROP FUNCTION api.chat(TEXT, TIMESTAMPTZ, TIMESTAMPTZ, BIGINT);
+ALTER TYPE api.chat_line ADD ATTRIBUTE message_id BIGINT;
+
+-- A page of the chat the player can see, newest first, before and/or after a message_id
+CREATE FUNCTION api.chat(session TEXT, before BIGINT, after BIGINT, page_size BIGINT)
+RETURNS SETOF api.chat_line AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  -- The policy on messages picks out what this player may read
+  RETURN QUERY
+  SELECT
+    COALESCE(sn.name, ss.species),
+    COALESCE(rn.name, rs.species),
+    m.channel,
+    m.message,
+    m.sent_at,
+    m.message_id
+  FROM messages m
+  LEFT JOIN names sn ON sn.entity_id=m.speaker
+  LEFT JOIN species ss ON ss.entity_id=m.speaker
+  LEFT JOIN names rn ON rn.entity_id=m.recipient
+  LEFT JOIN species rs ON rs.entity_id=m.recipient
+  WHERE
+    current_player() IS NOT NULL AND
+    (before IS NULL OR m.message_id < before) AND
+    (after IS NULL OR m.message_id > after)
+  ORDER BY m.message_id DESC
+  LIMIT page_size;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION api.chat(TEXT, BIGINT, BIGINT, BIGINT) OWNER TO mpd_game;
+REVOKE EXECUTE ON FUNCTION api.chat(TEXT, BIGINT, BIGINT, BIGINT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION api.chat(TEXT, BIGINT, BIGINT, BIGINT) TO mpd_player;
+
+-- api.chat takes message_ids where it took times
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 3;
+$$ LANGUAGE SQL IMMUTABLE;
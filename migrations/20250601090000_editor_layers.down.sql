@@ -0,0 +1,73 @@
+DROP FUNCTION admin.room_tiles(TEXT);
+
+CREATE OR REPLACE FUNCTION admin.save_room(room_name TEXT, template TEXT)
+RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+  replaced INTEGER[];
+BEGIN
+  SELECT r.entity_id INTO room
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  WHERE n.name=room_name;
+
+  IF room IS NULL THEN
+    INSERT INTO rooms (min_commands, landing_zone) VALUES (1, false) RETURNING entity_id INTO room;
+    INSERT INTO names (entity_id, name) VALUES (room, room_name);
+  ELSE
+    UPDATE rooms SET terrain='{}' WHERE entity_id=room;
+    SELECT ARRAY_AGG(p.entity_id) INTO replaced
+    FROM positions p
+    LEFT JOIN species s ON s.entity_id=p.entity_id
+    LEFT JOIN monsters m ON m.entity_id=p.entity_id
+    WHERE
+      p.room_id=room AND
+      (s.species IN ('wall', 'floor', 'door', 'upstair', 'downstair') OR m.entity_id IS NOT NULL);
+
+    DELETE FROM portals WHERE start_entity_id = ANY(replaced) OR end_entity_id = ANY(replaced);
+    DELETE FROM portal_labels WHERE entity_id = ANY(replaced);
+    DELETE FROM doors WHERE entity_id = ANY(replaced);
+    DELETE FROM impassibles WHERE entity_id = ANY(replaced);
+    DELETE FROM monsters WHERE entity_id = ANY(replaced);
+    DELETE FROM hps WHERE entity_id = ANY(replaced);
+    DELETE FROM weights WHERE entity_id = ANY(replaced);
+    DELETE FROM commands WHERE entity_id = ANY(replaced);
+    DELETE FROM species WHERE entity_id = ANY(replaced);
+    DELETE FROM positions WHERE entity_id = ANY(replaced);
+  END IF;
+
+  PERFORM paint_room_template(room, template);
+  PERFORM admin.audit('save_room', jsonb_build_object(
+    'room_id', room,
+    'name', room_name,
+    'template', template
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- The terrain as the wall and floor tiles it used to be
+CREATE FUNCTION admin.room_tiles(room_name TEXT)
+RETURNS TABLE (x SMALLINT, y SMALLINT, species TEXT, monster BOOLEAN) AS $$
+  SELECT t.x, t.y, t.species, false
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  CROSS JOIN LATERAL terrain_tiles(r.terrain) t
+  WHERE n.name=room_name
+  UNION ALL
+  SELECT p.x, p.y, s.species, m.entity_id IS NOT NULL
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  INNER JOIN positions p ON p.room_id=r.entity_id
+  INNER JOIN species s ON s.entity_id=p.entity_id
+  LEFT JOIN monsters m ON m.entity_id=p.entity_id
+  LEFT JOIN hps h ON h.entity_id=p.entity_id
+  WHERE n.name=room_name AND (m.entity_id IS NULL OR h.hp > 0)
+  ORDER BY y, x;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.room_tiles(TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.room_tiles(TEXT) TO mpd_admin;
+
+DROP FUNCTION delete_entities(INTEGER[]);
+DROP FUNCTION template_entities(INTEGER);
@@ -0,0 +1,159 @@
+-- What each unnamed entity in a room is, written the way a template legend would put it down,
+-- see template_layer. Monsters are written with their maxhp, and ones that are just a spawn of
+-- admin.spawn_templates' snake are written as spawn=snake, as the editor's S puts them down.
+-- Portals without a label can't be written, they are only kept by saving the same layer in the
+-- same place again.
+CREATE FUNCTION template_entities(room INTEGER)
+RETURNS TABLE (entity_id INTEGER, x SMALLINT, y SMALLINT, layer TEXT, dead BOOLEAN) AS $$
+  WITH described AS (
+    SELECT
+      p.entity_id,
+      p.x,
+      p.y,
+      concat_ws(
+        ' ',
+        s.species,
+        CASE WHEN i.entity_id IS NOT NULL AND d.entity_id IS NULL THEN 'impassible' END,
+        CASE WHEN m.entity_id IS NOT NULL THEN 'monster' END,
+        'hp=' || h.maxhp,
+        'weight=' || w.weight,
+        CASE WHEN d.locked THEN 'locked' WHEN d.open THEN 'open' WHEN d.entity_id IS NOT NULL THEN 'closed' END,
+        'lock=' || d.lock,
+        'key=' || k.lock,
+        'portal=' || l.label
+      ) AS layer,
+      m.entity_id IS NOT NULL AND COALESCE(h.hp <= 0, false) AS dead
+    FROM positions p
+    INNER JOIN species s ON s.entity_id=p.entity_id
+    LEFT JOIN impassibles i ON i.entity_id=p.entity_id
+    LEFT JOIN monsters m ON m.entity_id=p.entity_id
+    LEFT JOIN hps h ON h.entity_id=p.entity_id
+    LEFT JOIN weights w ON w.entity_id=p.entity_id
+    LEFT JOIN doors d ON d.entity_id=p.entity_id
+    LEFT JOIN keys k ON k.entity_id=p.entity_id
+    LEFT JOIN portal_labels l ON l.entity_id=p.entity_id
+    WHERE
+      p.room_id=room AND
+      NOT EXISTS (SELECT 1 FROM names n WHERE n.entity_id=p.entity_id) AND
+      NOT EXISTS (SELECT 1 FROM players pl WHERE pl.entity_id=p.entity_id)
+  )
+  SELECT
+    e.entity_id,
+    e.x,
+    e.y,
+    CASE
+      WHEN e.layer = concat_ws(
+        ' ',
+        t.species,
+        CASE WHEN t.monster THEN 'monster' END,
+        'hp=' || t.hp,
+        'weight=' || t.weight
+      ) THEN 'spawn=snake'
+      ELSE e.layer
+    END,
+    e.dead
+  FROM described e
+  LEFT JOIN admin.spawn_templates t ON t.template='snake';
+$$ LANGUAGE SQL STABLE;
+
+REVOKE EXECUTE ON FUNCTION template_entities(INTEGER) FROM PUBLIC;
+
+CREATE FUNCTION delete_entities(entities INTEGER[])
+RETURNS VOID AS $$
+BEGIN
+  DELETE FROM portals WHERE start_entity_id = ANY(entities) OR end_entity_id = ANY(entities);
+  DELETE FROM portal_labels WHERE entity_id = ANY(entities);
+  DELETE FROM doors WHERE entity_id = ANY(entities);
+  DELETE FROM keys WHERE entity_id = ANY(entities);
+  DELETE FROM impassibles WHERE entity_id = ANY(entities);
+  DELETE FROM monsters WHERE entity_id = ANY(entities);
+  DELETE FROM hps WHERE entity_id = ANY(entities);
+  DELETE FROM weights WHERE entity_id = ANY(entities);
+  DELETE FROM commands WHERE entity_id = ANY(entities);
+  DELETE FROM species WHERE entity_id = ANY(entities);
+  DELETE FROM positions WHERE entity_id = ANY(entities);
+END;
+$$ LANGUAGE plpgsql;
+
+REVOKE EXECUTE ON FUNCTION delete_entities(INTEGER[]) FROM PUBLIC;
+
+-- Everything the template puts down again where it already was is left as it is, so it keeps its
+-- portals, door state and hp. The rest of what template_entities lists is replaced, and named
+-- entities such as players aren't touched.
+CREATE OR REPLACE FUNCTION admin.save_room(room_name TEXT, template TEXT)
+RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+  existing INTEGER[] := '{}';
+  replaced INTEGER[];
+BEGIN
+  SELECT r.entity_id INTO room
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  WHERE n.name=room_name;
+
+  IF room IS NULL THEN
+    INSERT INTO rooms (min_commands, landing_zone) VALUES (1, false) RETURNING entity_id INTO room;
+    INSERT INTO names (entity_id, name) VALUES (room, room_name);
+  ELSE
+    UPDATE rooms SET terrain='{}' WHERE entity_id=room;
+    SELECT COALESCE(array_agg(e.entity_id), '{}') INTO existing FROM template_entities(room) e;
+  END IF;
+
+  PERFORM paint_room_template(room, template);
+
+  -- Pairs up what was there with what was just painted, one to one. A painted entity with a
+  -- living pair goes, as does an existing one without a pair.
+  WITH entities AS (
+    SELECT e.*, e.entity_id = ANY(existing) AS was_there
+    FROM template_entities(room) e
+  ),
+  numbered AS (
+    SELECT e.*, row_number() OVER (PARTITION BY e.x, e.y, e.layer, e.was_there ORDER BY e.entity_id) AS n
+    FROM entities e
+    WHERE NOT e.dead
+  ),
+  kept AS (
+    SELECT o.entity_id AS kept_id, p.entity_id AS painted_id
+    FROM numbered o
+    INNER JOIN numbered p ON p.x=o.x AND p.y=o.y AND p.layer=o.layer AND p.n=o.n
+    WHERE o.was_there AND NOT p.was_there
+  )
+  SELECT array_agg(e.entity_id) INTO replaced
+  FROM entities e
+  WHERE
+    e.was_there AND NOT EXISTS (SELECT 1 FROM kept k WHERE k.kept_id=e.entity_id) OR
+    NOT e.was_there AND EXISTS (SELECT 1 FROM kept k WHERE k.painted_id=e.entity_id);
+
+  PERFORM delete_entities(replaced);
+  PERFORM admin.audit('save_room', jsonb_build_object(
+    'room_id', room,
+    'name', room_name,
+    'template', template
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+-- Each wall and floor of the terrain, and everything template_entities can write, as legend
+-- layers. Dead monsters aren't shown, saving takes them away.
+DROP FUNCTION admin.room_tiles(TEXT);
+
+CREATE FUNCTION admin.room_tiles(room_name TEXT)
+RETURNS TABLE (x SMALLINT, y SMALLINT, layer TEXT) AS $$
+  SELECT t.x, t.y, CASE t.species WHEN 'wall' THEN 'wall impassible' ELSE 'floor' END AS layer
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  CROSS JOIN LATERAL terrain_tiles(r.terrain) t
+  WHERE n.name=room_name
+  UNION ALL
+  SELECT e.x, e.y, e.layer
+  FROM names n
+  INNER JOIN rooms r ON r.entity_id=n.entity_id
+  CROSS JOIN LATERAL template_entities(r.entity_id) e
+  WHERE n.name=room_name AND NOT e.dead
+  ORDER BY y, x, layer;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.room_tiles(TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.room_tiles(TEXT) TO mpd_admin;
@@ -0,0 +1,86 @@
+DROP FUNCTION admin.regenerate_level(INT);
+
+CREATE OR REPLACE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+SELECT l.*
+FROM rooms r
+CROSS JOIN LATERAL level_layout(
+    COALESCE(r.level_type, 'bsp'),
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    r.seed,
+    r.generator
+) l
+WHERE
+  r.entity_id=room_entity_id AND
+  r.generator IS NOT NULL AND
+  r.generator->>'engine' IS DISTINCT FROM 'rust';
+$$ LANGUAGE SQL STABLE;
+
+CREATE FUNCTION admin.generate_level(level_type TEXT, width INT, height INT, seed BIGINT, options JSONB)
+RETURNS TABLE (room_id INT, used_seed BIGINT, template TEXT) AS $$
+DECLARE
+  generated RECORD;
+BEGIN
+  SELECT * INTO generated FROM generate_level(level_type, width, height, seed, options) g;
+  room_id := generated.room_id;
+  template := generated.template;
+  SELECT r.seed INTO used_seed FROM rooms r WHERE r.entity_id=generated.room_id;
+  PERFORM admin.audit('generate_level', jsonb_build_object(
+    'room_id', room_id,
+    'level_type', level_type,
+    'width', width,
+    'height', height,
+    'options', options,
+    'seed', used_seed
+  ));
+  RETURN NEXT;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.generate_level(TEXT, INT, INT, BIGINT, JSONB) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.generate_level(TEXT, INT, INT, BIGINT, JSONB) TO mpd_admin;
+
+REVOKE SELECT ON admin.vaults FROM mpd_admin;
+
+DROP FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[], TEXT);
+
+CREATE FUNCTION admin.create_level(
+    level_type TEXT,
+    width INT,
+    height INT,
+    seed BIGINT,
+    options JSONB,
+    terrain TEXT[]
+) RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+BEGIN
+  INSERT INTO rooms (min_commands, landing_zone, seed, generator, level_type, terrain)
+  VALUES (
+    1,
+    false,
+    seed,
+    options || jsonb_build_object('width', width, 'height', height, 'engine', 'rust'),
+    level_type,
+    terrain
+  )
+  RETURNING entity_id INTO room;
+
+  PERFORM admin.audit('create_level', jsonb_build_object(
+    'room_id', room,
+    'level_type', level_type,
+    'width', width,
+    'height', height,
+    'options', options,
+    'seed', seed
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[]) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[]) TO mpd_admin;
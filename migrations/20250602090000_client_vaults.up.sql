@@ -0,0 +1,107 @@
+-- Vaults are stamped by the client along with the rest of the level, see src/dungeon.rs. What
+-- they put down comes as a template of their legend symbols, painted before the terrain is set so
+-- that only the entities are kept from it.
+DROP FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[]);
+
+CREATE FUNCTION admin.create_level(
+    level_type TEXT,
+    width INT,
+    height INT,
+    seed BIGINT,
+    options JSONB,
+    terrain TEXT[],
+    vaults TEXT
+) RETURNS INTEGER AS $$
+DECLARE
+  room INTEGER;
+BEGIN
+  INSERT INTO rooms (min_commands, landing_zone, seed, generator, level_type)
+  VALUES (
+    1,
+    false,
+    seed,
+    options || jsonb_build_object('width', width, 'height', height, 'engine', 'rust'),
+    level_type
+  )
+  RETURNING entity_id INTO room;
+
+  IF vaults IS NOT NULL THEN
+    PERFORM create_room_template(room, vaults);
+  END IF;
+  UPDATE rooms r SET terrain=create_level.terrain WHERE r.entity_id=room;
+
+  PERFORM admin.audit('create_level', jsonb_build_object(
+    'room_id', room,
+    'level_type', level_type,
+    'width', width,
+    'height', height,
+    'options', options,
+    'seed', seed
+  ));
+  RETURN room;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[], TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.create_level(TEXT, INT, INT, BIGINT, JSONB, TEXT[], TEXT) TO mpd_admin;
+
+GRANT SELECT ON admin.vaults TO mpd_admin;
+
+-- Game masters generate every level in the client now
+DROP FUNCTION admin.generate_level(TEXT, INT, INT, BIGINT, JSONB);
+
+-- Only the database's own generators can be run here, rooms the client generated have to be made
+-- again by the client
+CREATE OR REPLACE FUNCTION regenerate_dungeon(room_entity_id INT)
+RETURNS TABLE (
+    debug_output jsonb,
+    template TEXT
+) AS $$
+BEGIN
+  IF EXISTS (
+    SELECT 1 FROM rooms r WHERE r.entity_id=room_entity_id AND r.generator->>'engine'='rust'
+  ) THEN
+    RAISE EXCEPTION 'Room % was generated by the client, make it again with mpdungeon2 gm regenerate', room_entity_id;
+  END IF;
+  RETURN QUERY
+  SELECT l.*
+  FROM rooms r
+  CROSS JOIN LATERAL level_layout(
+    COALESCE(r.level_type, 'bsp'),
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    r.seed,
+    r.generator
+  ) l
+  WHERE r.entity_id=room_entity_id AND r.generator IS NOT NULL;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- What a generated room was made with, for the client to make it again. The database's own
+-- generators are run here and their template returned, the client's are left to the client.
+CREATE FUNCTION admin.regenerate_level(room INT)
+RETURNS TABLE (
+    engine TEXT,
+    level_type TEXT,
+    width INT,
+    height INT,
+    seed BIGINT,
+    generator JSONB,
+    template TEXT
+) AS $$
+  SELECT
+    r.generator->>'engine',
+    COALESCE(r.level_type, 'bsp'),
+    (r.generator->>'width')::INT,
+    (r.generator->>'height')::INT,
+    r.seed,
+    r.generator,
+    CASE WHEN r.generator->>'engine' IS DISTINCT FROM 'rust' THEN
+      (SELECT d.template FROM regenerate_dungeon(r.entity_id) d)
+    END
+  FROM rooms r
+  WHERE r.entity_id=room AND r.generator IS NOT NULL;
+$$ LANGUAGE SQL STABLE SECURITY DEFINER SET search_path = public, pg_temp;
+
+REVOKE EXECUTE ON FUNCTION admin.regenerate_level(INT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION admin.regenerate_level(INT) TO mpd_admin;
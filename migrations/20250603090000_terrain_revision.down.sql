@@ -0,0 +1,26 @@
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 3;
+$$ LANGUAGE SQL IMMUTABLE;
+
+DROP FUNCTION api.terrain(TEXT, INTEGER, INTEGER);
+
+CREATE FUNCTION api.terrain(session TEXT)
+RETURNS TABLE (room_id INTEGER, terrain TEXT[]) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT r.entity_id, r.terrain
+  FROM positions p
+  INNER JOIN rooms r ON r.entity_id=p.room_id
+  WHERE p.entity_id=current_player();
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION api.terrain(TEXT) OWNER TO mpd_game;
+REVOKE EXECUTE ON FUNCTION api.terrain(TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION api.terrain(TEXT) TO mpd_player;
+
+DROP TRIGGER bump_terrain_revision ON rooms;
+DROP FUNCTION bump_terrain_revision();
+ALTER TABLE rooms DROP COLUMN terrain_revision;
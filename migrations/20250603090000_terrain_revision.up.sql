@@ -0,0 +1,44 @@
+-- Counts the changes to a room's terrain, so clients can tell when the room they are in was saved
+-- or generated again and fetch its terrain once more
+ALTER TABLE rooms ADD COLUMN terrain_revision INTEGER NOT NULL DEFAULT 0;
+
+CREATE FUNCTION bump_terrain_revision()
+RETURNS TRIGGER AS $$
+BEGIN
+  NEW.terrain_revision := OLD.terrain_revision + 1;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_terrain_revision
+BEFORE UPDATE OF terrain ON rooms
+FOR EACH ROW
+WHEN (OLD.terrain IS DISTINCT FROM NEW.terrain)
+EXECUTE FUNCTION bump_terrain_revision();
+
+-- The terrain of the player's room, unless it is the room and revision the client already has
+DROP FUNCTION api.terrain(TEXT);
+
+CREATE FUNCTION api.terrain(session TEXT, known_room INTEGER, known_revision INTEGER)
+RETURNS TABLE (room_id INTEGER, revision INTEGER, terrain TEXT[]) AS $$
+BEGIN
+  PERFORM set_config('mpd.session', session, true);
+  RETURN QUERY
+  SELECT r.entity_id, r.terrain_revision, r.terrain
+  FROM positions p
+  INNER JOIN rooms r ON r.entity_id=p.room_id
+  WHERE
+    p.entity_id=current_player() AND
+    (r.entity_id, r.terrain_revision) IS DISTINCT FROM (known_room, known_revision);
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION api.terrain(TEXT, INTEGER, INTEGER) OWNER TO mpd_game;
+REVOKE EXECUTE ON FUNCTION api.terrain(TEXT, INTEGER, INTEGER) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION api.terrain(TEXT, INTEGER, INTEGER) TO mpd_player;
+
+-- api.terrain takes what the client has and says which revision it returns
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 4;
+$$ LANGUAGE SQL IMMUTABLE;
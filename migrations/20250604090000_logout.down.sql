@@ -0,0 +1,8 @@
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 4;
+$$ LANGUAGE SQL IMMUTABLE;
+
+DROP FUNCTION api.logout(TEXT);
+
+REVOKE DELETE ON sessions FROM mpd_game;
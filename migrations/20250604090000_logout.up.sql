@@ -0,0 +1,22 @@
+-- Clients end their session when they quit, so the character can be played again straight away
+-- rather than once end_stale_sessions has seen it go quiet
+GRANT DELETE ON sessions TO mpd_game;
+
+-- False if the session had already ended
+CREATE FUNCTION api.logout(session TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+  DELETE FROM sessions WHERE token=session;
+  RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public, pg_temp;
+
+ALTER FUNCTION api.logout(TEXT) OWNER TO mpd_game;
+REVOKE EXECUTE ON FUNCTION api.logout(TEXT) FROM PUBLIC;
+GRANT EXECUTE ON FUNCTION api.logout(TEXT) TO mpd_player;
+
+-- api.logout
+CREATE OR REPLACE FUNCTION api.version()
+RETURNS INTEGER AS $$
+  SELECT 5;
+$$ LANGUAGE SQL IMMUTABLE;
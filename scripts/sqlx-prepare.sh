@@ -0,0 +1,11 @@
+#!/bin/sh
+# Regenerates the query metadata in .sqlx, run it after adding or changing a query or migration.
+# Needs DATABASE_URL pointing at a database with every migration applied (mpdungeon2 migrate up).
+set -e
+: "${DATABASE_URL:?set DATABASE_URL to a migrated database}"
+cd "$(dirname "$0")/.."
+rm -rf .sqlx
+mkdir .sqlx
+# The macros only write metadata when they run, so make sure every one of them does
+cargo clean -p mpdungeon2
+SQLX_OFFLINE=false SQLX_OFFLINE_DIR="$PWD/.sqlx" cargo check --workspace --all-targets
@@ -0,0 +1,5 @@
+-- The $1 most recent changes, newest first
+SELECT at, operator, action, details::text AS "details!"
+FROM admin.audit_log
+ORDER BY id DESC
+LIMIT $1;
@@ -0,0 +1,10 @@
+-- The commands waiting on their room to tick
+SELECT
+  entity_id AS "entity_id!",
+  name,
+  room_id,
+  command_type,
+  x,
+  y,
+  target
+FROM admin.commands();
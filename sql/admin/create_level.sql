@@ -0,0 +1,2 @@
+-- Makes a room of the rows $6 and paints the vault template $7 over them
+SELECT admin.create_level($1, $2, $3, $4, $5, $6, $7) AS "room_id!";
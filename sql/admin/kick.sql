@@ -0,0 +1,2 @@
+-- False if the player wasn't logged in
+SELECT admin.kick($1) AS "kicked!";
@@ -0,0 +1,11 @@
+-- Every player, where they are and when their client was last heard from
+SELECT
+  entity_id AS "entity_id!",
+  name,
+  room_id,
+  x,
+  y,
+  hp,
+  maxhp,
+  last_seen
+FROM admin.players();
@@ -0,0 +1,10 @@
+-- What room $1 was generated with, and the template itself if the database generated it
+SELECT
+  engine,
+  level_type AS "level_type!",
+  width AS "width!",
+  height AS "height!",
+  seed AS "seed!",
+  generator AS "generator!: sqlx::types::Json<Options>",
+  template
+FROM admin.regenerate_level($1);
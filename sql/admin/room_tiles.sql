@@ -0,0 +1,2 @@
+-- The room named $1 as legend layers, for the editor to rebuild its template from
+SELECT x AS "x!", y AS "y!", layer AS "layer!" FROM admin.room_tiles($1);
@@ -0,0 +1,9 @@
+-- Every room with how many players and entities are in it
+SELECT
+  room_id AS "room_id!",
+  name,
+  players AS "players!",
+  entities AS "entities!",
+  min_commands,
+  landing_zone
+FROM admin.rooms();
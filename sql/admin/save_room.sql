@@ -0,0 +1,2 @@
+-- Creates the room named $1 if there isn't one yet
+SELECT admin.save_room($1, $2) AS "room_id!";
@@ -0,0 +1,2 @@
+-- $3 is the new maximum, which is left alone if NULL
+SELECT FROM admin.set_hp($1, $2, $3);
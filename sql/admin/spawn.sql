@@ -0,0 +1 @@
+SELECT admin.spawn($1, $2, $3, $4) AS "entity_id!";
@@ -0,0 +1 @@
+SELECT FROM admin.teleport($1, $2, $3, $4);
@@ -0,0 +1 @@
+SELECT name, template FROM admin.vaults ORDER BY name;
@@ -1,8 +1,9 @@
-SELECT ss.species "sender!", rs.species "receiver!",message "message!"
-FROM messages
-LEFT JOIN species ss ON ss.entity_id=messages.speaker
-LEFT JOIN species rs ON rs.entity_id=messages.recipient
-WHERE messages.speaker=$1 OR messages.recipient=$1
-ORDER BY sent_at ASC;
-
-
+-- Newest first so LIMIT takes the page closest to the cursor, the client reverses it
+SELECT
+  sender AS "sender!",
+  receiver,
+  channel AS "channel!",
+  message AS "message!",
+  sent_at AS "sent_at!",
+  message_id AS "message_id!"
+FROM api.chat($1, $2, $3, $4);
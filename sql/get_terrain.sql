@@ -0,0 +1,6 @@
+-- Nothing if $2 and $3 are the room the player is in and its terrain's revision
+SELECT
+  room_id AS "room_id!",
+  revision AS "revision!",
+  terrain AS "rows!"
+FROM api.terrain($1, $2, $3);
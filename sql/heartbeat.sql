@@ -0,0 +1,2 @@
+-- False once the session has been taken over
+SELECT api.heartbeat($1) AS "alive!";
@@ -1,6 +1,2 @@
-INSERT INTO commands (entity_id, command_type, x, y, target) values ($1, $2, $3, $4, $5) ON CONFLICT(entity_id) DO UPDATE
-SET 
-  command_type=EXCLUDED.command_type,
-  x=EXCLUDED.x,
-  y=EXCLUDED.y,
-  target=EXCLUDED.target;
+-- False if the session is no longer the character's
+SELECT api.submit_command($1, $2, $3, $4, $5) AS "accepted!";
@@ -1,4 +1,3 @@
-SELECT n.entity_id "entity_id!", p.entity_id IS NOT NULL as "logged_in!"
-FROM names n 
-LEFT JOIN players p ON p.entity_id=n.entity_id AND p.password=$2
-WHERE n.name=$1
+-- No row if the password is wrong or the name is free, a NULL token if someone else is playing
+-- and $3 (takeover) is false
+SELECT entity_id AS "entity_id!", token FROM api.login($1, $2, $3);
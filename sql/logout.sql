@@ -0,0 +1,2 @@
+-- False if the session had already ended
+SELECT api.logout($1) AS "ended!";
@@ -0,0 +1,2 @@
+-- No row if the name is taken
+SELECT entity_id AS "entity_id!", token AS "token!" FROM api.register($1, $2);
@@ -0,0 +1,2 @@
+-- Stands in for pg_cron on databases without it, see the job_scheduler migration
+SELECT run_due_jobs() AS ran;
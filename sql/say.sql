@@ -1,8 +1,3 @@
-INSERT INTO messages (speaker, recipient, message)
-SELECT DISTINCT ON(speaker.entity_id) speaker.entity_id, recipient.entity_id, $3
-FROM positions speaker
-INNER JOIN species recipient ON recipient.species=$2
-INNER JOIN positions p ON p.entity_id=recipient.entity_id AND p.room_id=speaker.room_id
-WHERE speaker.entity_id=$1
-;
-
+-- $2 is the channel, 'room', 'world' or 'direct' to the name in $3. False if there was no one to
+-- say it to.
+SELECT api.say($1, $2, $3, $4) AS "delivered!";
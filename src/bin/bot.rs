@@ -0,0 +1,174 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, ValueEnum};
+use mpdungeon2::{
+    bot::{BotAction, FollowQuest, Hunt, RandomWalk, Strategy},
+    client::GameClient,
+    state::State,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StrategyKind {
+    Random,
+    Hunt,
+    Quest,
+}
+
+/// Logs in a crowd of bot players and reports how long their commands take to be ticked
+#[derive(Parser)]
+struct Args {
+    /// The database to play on, logged in as mpd_player with the password given to
+    /// `mpdungeon2 migrate up --player-password`, e.g. postgresql://mpd_player:<password>@localhost/postgres
+    server_addr: String,
+    /// How many bots to log in
+    #[arg(short = 'n', long, default_value_t = 10)]
+    count: usize,
+    #[arg(short, long, value_enum, default_value_t = StrategyKind::Random)]
+    strategy: StrategyKind,
+    /// Bots are named <prefix><index> and registered on first use
+    #[arg(long, default_value = "bot")]
+    prefix: String,
+    #[arg(short, long, default_value = "bot")]
+    password: String,
+    /// Stop after this many seconds, runs until killed if not given
+    #[arg(short, long)]
+    duration: Option<u64>,
+    /// Seconds between latency reports
+    #[arg(long, default_value_t = 10)]
+    report_every: u64,
+}
+
+struct Bot {
+    name: String,
+    client: GameClient,
+    strategy: Box<dyn Strategy>,
+    last_state: Option<Arc<State>>,
+    // When the command still waiting on a room tick was sent, and how many had reached the server before it
+    pending: Option<(Instant, u64)>,
+}
+
+#[derive(Default)]
+struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    fn percentile(&self, p: f64) -> Duration {
+        let i = ((self.0.len() - 1) as f64 * p).round() as usize;
+        self.0[i]
+    }
+
+    fn report(&mut self, label: &str) {
+        if self.0.is_empty() {
+            println!("{}: no ticks", label);
+            return;
+        }
+        self.0.sort();
+        println!(
+            "{}: {} ticks, p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+            label,
+            self.0.len(),
+            self.percentile(0.5),
+            self.percentile(0.95),
+            self.percentile(0.99),
+            self.0[self.0.len() - 1],
+        );
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut bots = (0..args.count)
+        .map(|i| {
+            let name = format!("{}{}", args.prefix, i);
+            let seed = i as u64 + 1;
+            let strategy: Box<dyn Strategy> = match args.strategy {
+                StrategyKind::Random => Box::new(RandomWalk::new(seed)),
+                StrategyKind::Hunt => Box::new(Hunt::new("snake", seed)),
+                StrategyKind::Quest => Box::new(FollowQuest::new(seed)),
+            };
+            Bot {
+                client: GameClient::connect(
+                    args.server_addr.clone(),
+                    name.clone(),
+                    args.password.clone(),
+                    // Restarting a run shouldn't have to wait for the last one's sessions to go stale
+                    true,
+                    None,
+                ),
+                name,
+                strategy,
+                last_state: None,
+                pending: None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let started = Instant::now();
+    let mut last_report = Instant::now();
+    let mut interval = Latencies::default();
+    let mut total = Latencies::default();
+    while args
+        .duration
+        .is_none_or(|d| started.elapsed() < Duration::from_secs(d))
+    {
+        std::thread::sleep(Duration::from_millis(10));
+        bots.retain_mut(|bot| match bot.client.exit_result() {
+            None => true,
+            Some(result) => {
+                eprintln!("{}: {}", bot.name, result);
+                false
+            }
+        });
+        if bots.is_empty() {
+            break;
+        }
+
+        for bot in &mut bots {
+            let state = bot.client.state();
+            if state.self_entity_id.is_none()
+                || bot
+                    .last_state
+                    .as_ref()
+                    .is_some_and(|last| Arc::ptr_eq(last, &state))
+            {
+                continue;
+            }
+            bot.last_state = Some(state.clone());
+
+            // room_tick deletes the commands it carries out, so a cleared command has been ticked
+            let commanded = state
+                .entities
+                .iter()
+                .find(|e| Some(e.entity_id) == state.self_entity_id)
+                .is_some_and(|e| e.command_type.is_some());
+            if let Some((since, submitted_before)) = bot.pending {
+                if state.commands_submitted <= submitted_before || commanded {
+                    continue;
+                }
+                interval.0.push(since.elapsed());
+                total.0.push(since.elapsed());
+                bot.pending = None;
+            }
+
+            match bot.strategy.next_action(&state) {
+                Some(BotAction::Act(command)) => {
+                    bot.client.send(command);
+                    bot.pending = Some((Instant::now(), state.commands_submitted));
+                }
+                Some(BotAction::Say(channel, message)) => bot.client.say(channel, message),
+                None => {}
+            }
+        }
+
+        if last_report.elapsed() >= Duration::from_secs(args.report_every) {
+            interval.report(&format!("last {}s", args.report_every));
+            interval = Latencies::default();
+            last_report = Instant::now();
+        }
+    }
+
+    total.report("total");
+}
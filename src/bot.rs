@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    client::Command,
+    networking::Channel,
+    rng::Rng,
+    state::{State, WorldEntity},
+};
+
+pub enum BotAction {
+    Act(Command),
+    Say(Channel, String),
+}
+
+// Decides what a bot does next. Only asked once the bot's previous command has been ticked.
+pub trait Strategy {
+    fn next_action(&mut self, state: &State) -> Option<BotAction>;
+}
+
+const DIRECTIONS: [(i16, i16); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+fn self_entity(state: &State) -> Option<&WorldEntity> {
+    state
+        .entities
+        .iter()
+        .find(|e| Some(e.entity_id) == state.self_entity_id)
+}
+
+fn is_alive(e: &WorldEntity) -> bool {
+    e.hp.is_some_and(|hp| hp > 0)
+}
+
+fn inventory(state: &State) -> impl Iterator<Item = &WorldEntity> {
+    state
+        .entities
+        .iter()
+        .filter(|e| Some(e.room_id) == state.self_entity_id)
+}
+
+fn in_room<'a>(state: &'a State, me: &'a WorldEntity) -> impl Iterator<Item = &'a WorldEntity> {
+    state
+        .entities
+        .iter()
+        .filter(|e| e.room_id == me.room_id && e.entity_id != me.entity_id)
+}
+
+// The first step of a shortest path over the room's known floor, or None if the goal can't be reached
+fn step_towards(state: &State, me: &WorldEntity, goal: (i16, i16)) -> Option<(i16, i16)> {
+    let mut walls = HashSet::new();
+    let mut floor = HashSet::new();
+    for (x, y, c) in state.terrain.iter().flat_map(|t| t.tiles()) {
+        match c {
+            '#' => walls.insert((x, y)),
+            _ => floor.insert((x, y)),
+        };
+    }
+    for e in in_room(state, me) {
+        match e.species.as_deref() {
+            Some("wall") => walls.insert((e.x, e.y)),
+            _ => floor.insert((e.x, e.y)),
+        };
+    }
+    let start = (me.x, me.y);
+    let mut came_from = HashMap::from([(start, start)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(tile) = queue.pop_front() {
+        if tile == goal {
+            let mut tile = tile;
+            while came_from[&tile] != start {
+                tile = came_from[&tile];
+            }
+            return Some((tile.0 - start.0, tile.1 - start.1));
+        }
+        for (dx, dy) in DIRECTIONS {
+            let next = (tile.0 + dx, tile.1 + dy);
+            if floor.contains(&next) && !walls.contains(&next) && !came_from.contains_key(&next) {
+                came_from.insert(next, tile);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+fn nearest<'a>(
+    me: &WorldEntity,
+    candidates: impl Iterator<Item = &'a WorldEntity>,
+) -> Option<&'a WorldEntity> {
+    candidates.min_by_key(|e| (e.x - me.x).abs().max((e.y - me.y).abs()))
+}
+
+// Walks next to a living target and attacks it, or towards it if it is further away
+fn hunt(state: &State, me: &WorldEntity, target: &WorldEntity) -> Option<BotAction> {
+    if (target.x - me.x).abs() <= 1 && (target.y - me.y).abs() <= 1 {
+        return Some(BotAction::Act(Command::Attack(target.entity_id)));
+    }
+    let (dx, dy) = step_towards(state, me, (target.x, target.y))?;
+    Some(BotAction::Act(Command::Move { dx, dy }))
+}
+
+// Walks onto the nearest portal and takes it
+fn leave_room(state: &State, me: &WorldEntity) -> Option<BotAction> {
+    let portal = nearest(me, in_room(state, me).filter(|e| e.ends.is_some()))?;
+    if (portal.x, portal.y) == (me.x, me.y) {
+        return Some(BotAction::Act(Command::Travel(portal.entity_id)));
+    }
+    let (dx, dy) = step_towards(state, me, (portal.x, portal.y))?;
+    Some(BotAction::Act(Command::Move { dx, dy }))
+}
+
+pub struct RandomWalk {
+    rng: Rng,
+}
+
+impl RandomWalk {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Strategy for RandomWalk {
+    fn next_action(&mut self, _state: &State) -> Option<BotAction> {
+        let (dx, dy) = DIRECTIONS[self.rng.below(DIRECTIONS.len())];
+        Some(BotAction::Act(Command::Move { dx, dy }))
+    }
+}
+
+// Chases down the nearest living thing of a species, wandering through portals when there is none
+pub struct Hunt {
+    species: String,
+    wander: RandomWalk,
+}
+
+impl Hunt {
+    pub fn new(species: &str, seed: u64) -> Self {
+        Self {
+            species: species.to_owned(),
+            wander: RandomWalk::new(seed),
+        }
+    }
+}
+
+impl Strategy for Hunt {
+    fn next_action(&mut self, state: &State) -> Option<BotAction> {
+        let me = self_entity(state)?;
+        let prey = in_room(state, me)
+            .filter(|e| e.species.as_deref() == Some(self.species.as_str()) && is_alive(e));
+        if let Some(target) = nearest(me, prey) {
+            return hunt(state, me, target);
+        }
+        leave_room(state, me).or_else(|| self.wander.next_action(state))
+    }
+}
+
+// Plays the innkeeper's snake quest: kill a snake, carry the corpse back and hand it in
+pub struct FollowQuest {
+    hunt: Hunt,
+    // The corpse that has already been offered to the innkeeper, so it isn't offered every tick
+    offered: Option<i32>,
+}
+
+impl FollowQuest {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            hunt: Hunt::new("snake", seed),
+            offered: None,
+        }
+    }
+}
+
+impl Strategy for FollowQuest {
+    fn next_action(&mut self, state: &State) -> Option<BotAction> {
+        let me = self_entity(state)?;
+        if let Some(corpse) = inventory(state).find(|e| e.species.as_deref() == Some("snake")) {
+            let innkeeper = in_room(state, me).find(|e| e.species.as_deref() == Some("innkeeper"));
+            return match innkeeper {
+                Some(innkeeper) if self.offered != Some(corpse.entity_id) => {
+                    self.offered = Some(corpse.entity_id);
+                    Some(BotAction::Say(
+                        Channel::Direct(innkeeper.name.clone()?),
+                        "I have slain a snake, here is its corpse.".to_owned(),
+                    ))
+                }
+                // Wait for the innkeeper to take it
+                Some(_) => None,
+                None => leave_room(state, me),
+            };
+        }
+        let corpse = in_room(state, me).filter(|e| {
+            e.species.as_deref() == Some("snake") && !is_alive(e) && e.weight.is_some()
+        });
+        if let Some(corpse) = nearest(me, corpse) {
+            if (corpse.x, corpse.y) == (me.x, me.y) {
+                return Some(BotAction::Act(Command::Pickup(corpse.entity_id)));
+            }
+            let (dx, dy) = step_towards(state, me, (corpse.x, corpse.y))?;
+            return Some(BotAction::Act(Command::Move { dx, dy }));
+        }
+        self.hunt.next_action(state)
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+#[derive(PartialEq, Debug)]
+pub enum ChatCommand {
+    Say(String),
+    Tell { recipient: String, message: String },
+    Shout(String),
+    Who,
+    Help,
+    Quit,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ChatError {
+    UnknownCommand(String),
+    MissingArgument(&'static str, &'static str),
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::UnknownCommand(command) => {
+                write!(f, "Unknown command /{}, try /help", command)
+            }
+            ChatError::MissingArgument(command, argument) => {
+                write!(f, "Usage: /{} {}", command, argument)
+            }
+        }
+    }
+}
+
+pub const HELP: &[&str] = &[
+    "/say <message>         talk to everyone in the room",
+    "/tell <name> <message> talk to one person",
+    "/shout <message>       talk to everyone in the world",
+    "/who                   list who is nearby",
+    "/help                  show this help",
+    "/quit                  leave the game",
+    "Text without a leading / is said to the room",
+];
+
+fn required<'a>(
+    command: &'static str,
+    argument: &'static str,
+    text: &'a str,
+) -> Result<&'a str, ChatError> {
+    if text.is_empty() {
+        Err(ChatError::MissingArgument(command, argument))
+    } else {
+        Ok(text)
+    }
+}
+
+// Parses a line typed in command mode. Returns None for a blank line.
+pub fn parse(line: &str) -> Option<Result<ChatCommand, ChatError>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let Some(line) = line.strip_prefix('/') else {
+        return Some(Ok(ChatCommand::Say(line.to_owned())));
+    };
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim_start();
+    Some(match command {
+        "say" | "s" => required("say", "<message>", rest).map(|m| ChatCommand::Say(m.to_owned())),
+        "shout" => required("shout", "<message>", rest).map(|m| ChatCommand::Shout(m.to_owned())),
+        "tell" | "t" => match rest.split_once(char::is_whitespace) {
+            Some((recipient, message)) if !message.trim().is_empty() => Ok(ChatCommand::Tell {
+                recipient: recipient.to_owned(),
+                message: message.trim_start().to_owned(),
+            }),
+            _ => Err(ChatError::MissingArgument("tell", "<name> <message>")),
+        },
+        "who" => Ok(ChatCommand::Who),
+        "help" | "?" => Ok(ChatCommand::Help),
+        "quit" | "q" => Ok(ChatCommand::Quit),
+        other => Err(ChatError::UnknownCommand(other.to_owned())),
+    })
+}
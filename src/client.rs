@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::{
+    networking::{Channel, ExitResult, PlayerCommand, PlayerMessage, ServerConnection},
+    recording::Recorder,
+    state::State,
+};
+
+// Everything a player can ask their character to do on the next room tick
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Move { dx: i16, dy: i16 },
+    Attack(i32),
+    Pickup(i32),
+    Drop(i32),
+    Travel(i32),
+    Open(i32),
+    Close(i32),
+}
+
+impl Command {
+    fn into_player_command(self, entity_id: i32) -> PlayerCommand {
+        let (command_type, x, y, command_target) = match self {
+            Command::Move { dx, dy } => ("move", Some(dx), Some(dy), None),
+            Command::Attack(target) => ("attack", None, None, Some(target)),
+            Command::Pickup(target) => ("pickup", None, None, Some(target)),
+            Command::Drop(target) => ("drop", None, None, Some(target)),
+            Command::Travel(target) => ("travel", None, None, Some(target)),
+            Command::Open(target) => ("open", None, None, Some(target)),
+            Command::Close(target) => ("close", None, None, Some(target)),
+        };
+        PlayerCommand {
+            entity_id,
+            command_type: command_type.to_owned(),
+            x,
+            y,
+            command_target,
+        }
+    }
+}
+
+// A logged in player, independent of how (or whether) the game is shown to anyone
+pub struct GameClient {
+    connection: ServerConnection,
+    exit_result: Option<ExitResult>,
+}
+
+impl GameClient {
+    // Connects and logs in on a background thread, registering the player if the name is new.
+    // Until that finishes the state has no self_entity_id and commands are ignored.
+    // If someone is already playing the character, `takeover` logs them out instead of giving up.
+    // Everything the server sends and the player does is written to `recorder` if one is given.
+    pub fn connect(
+        server_addr: String,
+        username: String,
+        password: String,
+        takeover: bool,
+        recorder: Option<Recorder>,
+    ) -> Self {
+        Self {
+            connection: ServerConnection::new(server_addr, username, password, takeover, recorder),
+            exit_result: None,
+        }
+    }
+
+    // Plays on a world simulated in this process, no server needed
+    pub fn offline(username: String, recorder: Option<Recorder>) -> Self {
+        Self {
+            connection: ServerConnection::offline(username, recorder),
+            exit_result: None,
+        }
+    }
+
+    pub fn state(&self) -> Arc<State> {
+        self.connection.state_rx.borrow().clone()
+    }
+
+    // Receives every new state as it is fetched from the server
+    pub fn subscribe(&self) -> watch::Receiver<Arc<State>> {
+        self.connection.state_rx.clone()
+    }
+
+    pub fn entity_id(&self) -> Option<i32> {
+        self.connection.state_rx.borrow().self_entity_id
+    }
+
+    pub fn send(&self, command: Command) {
+        if let Some(entity_id) = self.entity_id() {
+            self.connection
+                .create_commmand(command.into_player_command(entity_id));
+        }
+    }
+
+    pub fn say(&self, channel: Channel, message: String) {
+        if let Some(speaker) = self.entity_id() {
+            self.connection.say(PlayerMessage {
+                speaker,
+                channel,
+                message,
+            });
+        }
+    }
+
+    pub fn fetch_older_chat(&self) {
+        self.connection.fetch_older_chat();
+    }
+
+    // Drains feedback from the server that isn't part of the state, such as failed tells
+    pub fn notices(&self) -> impl Iterator<Item = String> + '_ {
+        self.connection.notice_rx.try_iter()
+    }
+
+    // Returns why the connection ended, once it has
+    pub fn exit_result(&mut self) -> Option<&ExitResult> {
+        if let Some(handle) = self
+            .connection
+            .join_handle
+            .take_if(|handle| handle.is_finished())
+        {
+            // The connection thread panics on anything it can't handle, which is as good as hanging up
+            self.exit_result = Some(handle.join().unwrap_or(ExitResult::ConnectionLost));
+        }
+        self.exit_result.as_ref()
+    }
+}
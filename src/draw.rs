@@ -1,9 +1,9 @@
-use std::io::{Write, stdout};
+use std::io::{Stdout, Write, stdout};
 
 use crossterm::{
     ExecutableCommand, QueueableCommand,
     cursor::{Hide, MoveTo, Show},
-    event::{Event, KeyCode, KeyEvent, KeyModifiers, poll, read},
+    event::{Event, KeyEventKind, poll, read},
     execute, queue,
     style::{Color, Print, SetForegroundColor},
     terminal::{
@@ -12,7 +12,18 @@ use crossterm::{
     },
 };
 
-use crate::state::State;
+use mpdungeon2::{
+    client::Command,
+    state::{State, WorldEntity},
+};
+
+use crate::{
+    keymap::{Action, Keymap},
+    line_editor::{LineEditor, LineEvent},
+};
+
+// The chat panel runs down the right of the map until the HP bar
+const CHAT_ROWS: usize = 29;
 
 enum InputMode {
     Normal,
@@ -21,90 +32,202 @@ enum InputMode {
 }
 
 pub struct Drawer {
+    keymap: Keymap,
     mode: InputMode,
-    current_command: String,
+    command_line: LineEditor,
     inventory_selected_index: usize,
+    // Client-side lines such as command errors, shown after the chat
+    notices: Vec<String>,
+    // How many lines the chat panel is scrolled back from the newest
+    chat_scroll: usize,
 }
 
 #[derive(PartialEq)]
 pub enum InputEvent {
     Quit,
-    Move((i16, i16)),
-    Attack(i32),
-    Travel(i32),
-    Pickup(i32),
-    Drop(i32),
-    Say(String),
+    Act(Command),
+    CommandLine(String),
+    FetchOlderChat,
 }
 
-impl Drawer {
-    // Get the appropriate wall character based on adjacent walls
-    fn get_wall_char(&self, s: &State, entity: &crate::state::WorldEntity) -> &'static str {
-        // Check for walls in all 8 directions (N, NE, E, SE, S, SW, W, NW)
-        let directions = [
-            (0, -1),  // North
-            (1, -1),  // Northeast
-            (1, 0),   // East
-            (1, 1),   // Southeast
-            (0, 1),   // South
-            (-1, 1),  // Southwest
-            (-1, 0),  // West
-            (-1, -1), // Northwest
-        ];
-        
-        // We mostly care about cardinal directions for box drawing
-        let mut north = false;
-        let mut east = false;
-        let mut south = false;
-        let mut west = false;
-        
-        for (idx, (dx, dy)) in directions.iter().enumerate() {
-            let adjacent_wall = s.entities.iter().any(|e| 
-                e.x == entity.x + dx && 
-                e.y == entity.y + dy && 
-                e.species.as_deref() == Some("wall") &&
-                e.room_id == entity.room_id
-            );
-            
-            // Set cardinal direction flags
-            match idx {
-                0 => north = adjacent_wall, // North
-                2 => east = adjacent_wall,  // East
-                4 => south = adjacent_wall, // South
-                6 => west = adjacent_wall,  // West
-                _ => {}  // We ignore diagonals for basic box drawing
-            }
-        }
-        
-        // Return the appropriate double-line box drawing character based on adjacent walls
-        match (north, east, south, west) {
-            (true, true, true, true) => "╬", // All four directions
-            (true, true, true, false) => "╠", // North, East, South
-            (true, true, false, true) => "╩", // North, East, West
-            (true, false, true, true) => "╣", // North, South, West
-            (false, true, true, true) => "╦", // East, South, West
-            (true, true, false, false) => "╚", // North, East
-            (true, false, true, false) => "║", // North, South
-            (true, false, false, true) => "╝", // North, West
-            (false, true, true, false) => "╔", // East, South
-            (false, true, false, true) => "═", // East, West
-            (false, false, true, true) => "╗", // South, West
-            (true, false, false, false) => "║", // North only
-            (false, true, false, false) => "═", // East only
-            (false, false, true, false) => "║", // South only
-            (false, false, false, true) => "═", // West only
-            (false, false, false, false) => "■", // No connections (isolated wall)
+// Whether the room's terrain has a wall at x, y
+fn is_wall(s: &State, x: i16, y: i16) -> bool {
+    s.terrain.as_ref().is_some_and(|t| t.is_wall(x, y))
+}
+
+// Get the appropriate wall character based on adjacent walls
+pub fn wall_char(s: &State, x: i16, y: i16) -> &'static str {
+    // Check for walls in all 8 directions (N, NE, E, SE, S, SW, W, NW)
+    let directions = [
+        (0, -1),  // North
+        (1, -1),  // Northeast
+        (1, 0),   // East
+        (1, 1),   // Southeast
+        (0, 1),   // South
+        (-1, 1),  // Southwest
+        (-1, 0),  // West
+        (-1, -1), // Northwest
+    ];
+
+    // We mostly care about cardinal directions for box drawing
+    let mut north = false;
+    let mut east = false;
+    let mut south = false;
+    let mut west = false;
+
+    for (idx, (dx, dy)) in directions.iter().enumerate() {
+        let adjacent_wall = is_wall(s, x + dx, y + dy);
+
+        // Set cardinal direction flags
+        match idx {
+            0 => north = adjacent_wall, // North
+            2 => east = adjacent_wall,  // East
+            4 => south = adjacent_wall, // South
+            6 => west = adjacent_wall,  // West
+            _ => {}                     // We ignore diagonals for basic box drawing
         }
     }
 
-    pub fn new() -> Self {
+    // Return the appropriate double-line box drawing character based on adjacent walls
+    match (north, east, south, west) {
+        (true, true, true, true) => "╬",     // All four directions
+        (true, true, true, false) => "╠",    // North, East, South
+        (true, true, false, true) => "╩",    // North, East, West
+        (true, false, true, true) => "╣",    // North, South, West
+        (false, true, true, true) => "╦",    // East, South, West
+        (true, true, false, false) => "╚",   // North, East
+        (true, false, true, false) => "║",   // North, South
+        (true, false, false, true) => "╝",   // North, West
+        (false, true, true, false) => "╔",   // East, South
+        (false, true, false, true) => "═",   // East, West
+        (false, false, true, true) => "╗",   // South, West
+        (true, false, false, false) => "║",  // North only
+        (false, true, false, false) => "═",  // East only
+        (false, false, true, false) => "║",  // South only
+        (false, false, false, true) => "═",  // West only
+        (false, false, false, false) => "■", // No connections (isolated wall)
+    }
+}
+
+pub fn colour(s: &State, e: &WorldEntity) -> Color {
+    match (e.entity_id, e.species.as_deref(), e.hp) {
+        (_, _, Some(hp)) if hp <= 0 => Color::Red,
+        (eid, _, _) if Some(eid) == s.self_entity_id => Color::Cyan,
+        (_, Some("snake"), _) => Color::Green,
+        (_, Some("gold" | "key"), _) => Color::Yellow,
+        (_, _, _) => Color::White,
+    }
+}
+
+pub fn glyph(s: &State, e: &WorldEntity) -> &'static str {
+    match (e.species.as_deref().unwrap_or_default(), e.hp) {
+        (_, Some(hp)) if hp <= 0 => "%",
+        ("human", _) => "@",
+        ("door", _) if e.open == Some(true) => "'",
+        ("door", _) => "║",
+        ("snake", _) => "s",
+        ("floor", _) => "+",
+        ("wall", _) => wall_char(s, e.x, e.y),
+        ("upstair", _) => "<",
+        ("downstair", _) => ">",
+        ("gold", _) => "$",
+        ("key", _) => "-",
+        _ => "?",
+    }
+}
+
+// The room's walls and floors, under everything in it
+pub fn draw_terrain(stdout: &mut Stdout, s: &State) {
+    let Some(terrain) = &s.terrain else {
+        return;
+    };
+    queue!(stdout, SetForegroundColor(Color::White)).unwrap();
+    for (x, y, c) in terrain.tiles() {
+        let glyph = match c {
+            '#' => wall_char(s, x, y),
+            _ => "+",
+        };
+        queue!(stdout, MoveTo(x as u16, y as u16), Print(glyph)).unwrap();
+    }
+}
+
+impl Drawer {
+    pub fn new(keymap: Keymap) -> Self {
         let mut stdout = stdout();
         enable_raw_mode().unwrap();
         execute!(stdout, SetForegroundColor(Color::White), Hide,).unwrap();
         Self {
+            keymap,
             mode: InputMode::Normal,
-            current_command: String::new(),
+            command_line: LineEditor::load(),
             inventory_selected_index: 0,
+            notices: vec![],
+            chat_scroll: 0,
+        }
+    }
+
+    pub fn notify(&mut self, notice: impl Into<String>) {
+        self.notices.push(notice.into());
+    }
+
+    fn draw_chat(&self, stdout: &mut Stdout, s: &State) {
+        let total = s.chat.len() + self.notices.len();
+        let end = total - self.chat_scroll.min(total);
+        let start = end.saturating_sub(CHAT_ROWS);
+        for (row, line) in (start..end).enumerate() {
+            queue!(stdout, MoveTo(30, row as u16)).unwrap();
+            let Some(message) = s.chat.get(line) else {
+                queue!(
+                    stdout,
+                    SetForegroundColor(Color::DarkGrey),
+                    Print(&self.notices[line - s.chat.len()])
+                )
+                .unwrap();
+                continue;
+            };
+            let prefix = match (message.channel.as_str(), &message.receiver) {
+                ("world", _) => "[world] ".to_owned(),
+                ("direct", Some(receiver)) => format!("[to {}] ", receiver),
+                _ => String::new(),
+            };
+            queue!(
+                stdout,
+                SetForegroundColor(Color::DarkYellow),
+                Print(prefix),
+                SetForegroundColor(Color::Red),
+                Print(&message.sender),
+                Print(": "),
+                SetForegroundColor(Color::White),
+                Print(&message.message)
+            )
+            .unwrap();
+        }
+        if self.chat_scroll > 0 {
+            queue!(
+                stdout,
+                MoveTo(30, CHAT_ROWS as u16),
+                SetForegroundColor(Color::DarkGrey),
+                Print(format!("-- {} newer lines below --", total - end))
+            )
+            .unwrap();
+        }
+    }
+
+    fn scroll_chat(&mut self, s: &State, action: Action) -> Option<InputEvent> {
+        let total = s.chat.len() + self.notices.len();
+        let top = total.saturating_sub(CHAT_ROWS);
+        match action {
+            Action::ChatPageUp => {
+                self.chat_scroll = (self.chat_scroll + CHAT_ROWS - 1).min(top);
+                // Ask for the next page once the top of what we have is on screen
+                (self.chat_scroll == top && !s.chat_history_complete)
+                    .then_some(InputEvent::FetchOlderChat)
+            }
+            Action::ChatPageDown => {
+                self.chat_scroll = self.chat_scroll.saturating_sub(CHAT_ROWS - 1);
+                None
+            }
+            _ => None,
         }
     }
 
@@ -114,205 +237,145 @@ impl Drawer {
             .entities
             .iter()
             .find(|e| Some(e.entity_id) == s.self_entity_id);
-        if poll(std::time::Duration::from_secs(0)).unwrap() {
-            let event = read().unwrap();
-            match event {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('c'),
-                    modifiers,
-                    ..
-                }) if modifiers & KeyModifiers::CONTROL == KeyModifiers::CONTROL => {
-                    events.push(InputEvent::Quit);
-                }
-                _ => {}
+        if !poll(std::time::Duration::from_secs(0)).unwrap() {
+            return events;
+        }
+        let Event::Key(key) = read().unwrap() else {
+            return events;
+        };
+        if key.kind == KeyEventKind::Release {
+            return events;
+        }
+        match self.keymap.global(&key) {
+            Some(Action::Quit) => events.push(InputEvent::Quit),
+            Some(action @ (Action::ChatPageUp | Action::ChatPageDown)) => {
+                events.extend(self.scroll_chat(s, action));
+                return events;
             }
-            if let Some(self_entity) = self_entity {
-                let mut loc = None;
-                let mut travel = false;
-                let mut pickup = false;
-                match self.mode {
-                    InputMode::Command => match event {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Backspace,
-                            ..
-                        }) => {
-                            self.current_command.pop();
-                            return vec![];
-                        }
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Enter,
-                            ..
-                        }) => {
-                            let events = vec![InputEvent::Say(self.current_command.clone())];
-                            self.current_command = String::new();
-                            self.mode = InputMode::Normal;
-                            return events;
-                        }
-                        Event::Key(KeyEvent { code, .. }) => {
-                            if let Some(c) = code.as_char() {
-                                self.current_command.push(c);
-                            }
-                            return vec![];
-                        }
-                        _ => {
-                            return vec![];
-                        }
-                    },
-                    InputMode::Inventory => match event {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Esc, ..
-                        }) => {
+            _ => {}
+        }
+        let Some(self_entity) = self_entity else {
+            return events;
+        };
+        match self.mode {
+            InputMode::Command => match self.command_line.handle_key(&key) {
+                Some(LineEvent::Submit(text)) => {
+                    events.push(InputEvent::CommandLine(text));
+                    self.mode = InputMode::Normal;
+                }
+                Some(LineEvent::Cancel) => {
+                    self.mode = InputMode::Normal;
+                }
+                None => {}
+            },
+            InputMode::Inventory => {
+                let inventory = s
+                    .entities
+                    .iter()
+                    .filter(|e| Some(e.room_id) == s.self_entity_id)
+                    .collect::<Vec<_>>();
+                match self.keymap.inventory(&key) {
+                    Some(Action::Quit) => events.push(InputEvent::Quit),
+                    Some(Action::Close) => {
+                        self.mode = InputMode::Normal;
+                        self.inventory_selected_index = 0;
+                    }
+                    Some(Action::SelectNext) if !inventory.is_empty() => {
+                        self.inventory_selected_index =
+                            (self.inventory_selected_index + 1) % inventory.len();
+                    }
+                    Some(Action::SelectPrevious) if !inventory.is_empty() => {
+                        self.inventory_selected_index = if self.inventory_selected_index == 0 {
+                            inventory.len() - 1
+                        } else {
+                            self.inventory_selected_index - 1
+                        };
+                    }
+                    Some(Action::Drop) => {
+                        if let Some(item) = inventory.get(self.inventory_selected_index) {
+                            events.push(InputEvent::Act(Command::Drop(item.entity_id)));
+                            // Exit inventory mode after dropping
                             self.mode = InputMode::Normal;
                             self.inventory_selected_index = 0;
                         }
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('j'), ..
-                        }) => {
-                            let inventory_count = s.entities
-                                .iter()
-                                .filter(|e| Some(e.room_id) == s.self_entity_id)
-                                .count();
-                            if inventory_count > 0 {
-                                self.inventory_selected_index = (self.inventory_selected_index + 1) % inventory_count;
-                            }
-                        }
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('k'), ..
-                        }) => {
-                            let inventory_count = s.entities
-                                .iter()
-                                .filter(|e| Some(e.room_id) == s.self_entity_id)
-                                .count();
-                            if inventory_count > 0 {
-                                self.inventory_selected_index = if self.inventory_selected_index == 0 {
-                                    if inventory_count > 0 { inventory_count - 1 } else { 0 }
-                                } else {
-                                    self.inventory_selected_index - 1
-                                };
-                            }
-                        }
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('d'), ..
-                        }) => {
-                            let inventory = s.entities
-                                .iter()
-                                .filter(|e| Some(e.room_id) == s.self_entity_id)
-                                .collect::<Vec<_>>();
-                            
-                            if !inventory.is_empty() && self.inventory_selected_index < inventory.len() {
-                                let item = inventory[self.inventory_selected_index];
-                                events.push(InputEvent::Drop(item.entity_id));
-                                // Exit inventory mode after dropping
-                                self.mode = InputMode::Normal;
-                                self.inventory_selected_index = 0;
-                            }
-                        }
-                        _ => {}
-                    },
-                    InputMode::Normal => {
-                        match event {
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('i'),
-                                ..
-                            }) => {
-                                self.mode = InputMode::Inventory;
-                            }
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char(':'),
-                                ..
-                            }) => {
-                                self.current_command = String::new();
-                                self.mode = InputMode::Command;
-                            }
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char(','),
-                                ..
-                            }) => pickup = true,
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('>'),
-                                ..
-                            }) => travel = true,
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('<'),
-                                ..
-                            }) => travel = true,
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('h'),
-                                ..
-                            }) => loc = Some((-1, 0)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('j'),
-                                ..
-                            }) => loc = Some((0, 1)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('k'),
-                                ..
-                            }) => loc = Some((0, -1)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('l'),
-                                ..
-                            }) => loc = Some((1, 0)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('y'),
-                                ..
-                            }) => loc = Some((-1, -1)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('u'),
-                                ..
-                            }) => loc = Some((1, -1)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('b'),
-                                ..
-                            }) => loc = Some((-1, 1)),
-                            Event::Key(KeyEvent {
-                                code: KeyCode::Char('n'),
-                                ..
-                            }) => loc = Some((1, 1)),
-                            _ => {}
+                    }
+                    _ => {}
+                }
+            }
+            InputMode::Normal => {
+                match self.keymap.normal(&key) {
+                    Some(Action::Quit) => events.push(InputEvent::Quit),
+                    Some(Action::OpenInventory) => {
+                        self.mode = InputMode::Inventory;
+                    }
+                    Some(Action::OpenCommand) => {
+                        self.command_line.clear();
+                        self.mode = InputMode::Command;
+                    }
+                    Some(Action::Pickup) => {
+                        if let Some(target) = s.entities.iter().find(|e| {
+                            e.x == self_entity.x && e.y == self_entity.y && e.weight.is_some()
+                        }) {
+                            events.push(InputEvent::Act(Command::Pickup(target.entity_id)));
                         }
-                        if pickup == true {
-                            if let Some(target) = s.entities.iter().find(|e| {
-                                e.x == self_entity.x && e.y == self_entity.y && e.weight.is_some()
-                            }) {
-                                events.push(InputEvent::Pickup(target.entity_id));
-                            }
+                    }
+                    Some(Action::Travel) => {
+                        if let Some(target) = s.entities.iter().find(|e| {
+                            e.x == self_entity.x && e.y == self_entity.y && e.ends.is_some()
+                        }) {
+                            events.push(InputEvent::Act(Command::Travel(target.entity_id)));
                         }
-                        if travel == true {
-                            if let Some(target) = s.entities.iter().find(|e| {
-                                e.x == self_entity.x && e.y == self_entity.y && e.ends.is_some()
-                            }) {
-                                events.push(InputEvent::Travel(target.entity_id));
-                            }
+                    }
+                    Some(action @ (Action::OpenDoor | Action::CloseDoor)) => {
+                        // Doors are opened and closed from next to them, not from in the doorway
+                        let closing = action == Action::CloseDoor;
+                        if let Some(door) = s.entities.iter().find(|e| {
+                            e.open == Some(closing)
+                                && (e.x, e.y) != (self_entity.x, self_entity.y)
+                                && (e.x - self_entity.x).abs() <= 1
+                                && (e.y - self_entity.y).abs() <= 1
+                        }) {
+                            events.push(InputEvent::Act(if closing {
+                                Command::Close(door.entity_id)
+                            } else {
+                                Command::Open(door.entity_id)
+                            }));
                         }
-                        if let Some((loc_x, loc_y)) = loc {
+                    }
+                    Some(action) => {
+                        if let Some((loc_x, loc_y)) = action.direction() {
                             if let Some(target) = s.entities.iter().find(|e| {
                                 e.x == loc_x + self_entity.x
                                     && e.y == loc_y + self_entity.y
                                     && e.hp.filter(|h| *h > 0).is_some()
                             }) {
-                                events.push(InputEvent::Attack(target.entity_id));
+                                events.push(InputEvent::Act(Command::Attack(target.entity_id)));
                             } else {
-                                events.push(InputEvent::Move((loc_x, loc_y)));
+                                events.push(InputEvent::Act(Command::Move {
+                                    dx: loc_x,
+                                    dy: loc_y,
+                                }));
                             }
                         }
                     }
+                    None => {}
                 }
             }
         }
 
-        return events;
+        events
     }
 
     pub fn draw(&mut self, s: &State) {
         let mut stdout = std::io::stdout();
-        execute!(stdout, BeginSynchronizedUpdate).unwrap();
+        execute!(stdout, BeginSynchronizedUpdate, Hide).unwrap();
         stdout
             .queue(terminal::Clear(terminal::ClearType::All))
             .unwrap();
+        draw_terrain(&mut stdout, s);
         let mut sorted_entities: Vec<&_> = s
             .entities
             .iter()
-            .map(|e| e)
             .filter(|e| Some(e.room_id) != s.self_entity_id)
             .collect();
         sorted_entities.sort_by(|a, b| match (a.maxhp, b.maxhp, a.entity_id, b.entity_id) {
@@ -326,28 +389,12 @@ impl Drawer {
         });
         for e in &sorted_entities {
             match (e.x, e.y, &e.species) {
-                (x, y, Some(species)) if x >= 0 && y >= 0 => {
+                (x, y, Some(_)) if x >= 0 && y >= 0 => {
                     queue!(
                         stdout,
                         MoveTo(e.x as u16, e.y as u16),
-                        SetForegroundColor(match (e.entity_id, e.species.as_deref(), e.hp) {
-                            (_, _, Some(hp)) if hp <= 0 => Color::Red,
-                            (eid, _, _) if Some(eid) == s.self_entity_id => Color::Cyan,
-                            (_, Some("snake"), _) => Color::Green,
-                            (_, Some("gold"), _) => Color::Yellow,
-                            (_, _, _) => Color::White,
-                        }),
-                        Print(match (species.as_str(), e.hp) {
-                            (_, Some(hp)) if hp <= 0 => "%",
-                            ("human", _) => "@",
-                            ("door", _) => "║",
-                            ("snake", _) => "s",
-                            ("floor", _) => "+",
-                            ("wall", _) => self.get_wall_char(s, e),
-                            ("upstair", _) => "<",
-                            ("gold", _) => "$",
-                            _ => "?",
-                        })
+                        SetForegroundColor(colour(s, e)),
+                        Print(glyph(s, e))
                     )
                     .unwrap();
                 }
@@ -401,18 +448,7 @@ impl Drawer {
 
         match self.mode {
             InputMode::Normal => {
-                for (id, message) in s.chat.iter().enumerate() {
-                    queue!(
-                        stdout,
-                        MoveTo(30, id as u16),
-                        SetForegroundColor(Color::Red),
-                        Print(&message.sender),
-                        Print(": "),
-                        SetForegroundColor(Color::White),
-                        Print(&message.message)
-                    )
-                    .unwrap();
-                }
+                self.draw_chat(&mut stdout, s);
             }
 
             InputMode::Inventory => {
@@ -428,19 +464,28 @@ impl Drawer {
                     .iter()
                     .filter(|e| Some(e.room_id) == s.self_entity_id)
                     .collect::<Vec<_>>();
-                
+
                 for (i, e) in inventory.iter().enumerate() {
                     let item_color = match e.species.as_deref() {
-                        Some("gold") => Color::Yellow,
-                        _ => Color::White
+                        Some("gold" | "key") => Color::Yellow,
+                        _ => Color::White,
                     };
-                    
+
                     queue!(
                         stdout,
                         MoveTo(32, (i + 1) as u16),
-                        SetForegroundColor(if i == self.inventory_selected_index { Color::Yellow } else { item_color }),
-                        Print(format!("{}{}", 
-                            if i == self.inventory_selected_index { "> " } else { "  " },
+                        SetForegroundColor(if i == self.inventory_selected_index {
+                            Color::Yellow
+                        } else {
+                            item_color
+                        }),
+                        Print(format!(
+                            "{}{}",
+                            if i == self.inventory_selected_index {
+                                "> "
+                            } else {
+                                "  "
+                            },
                             e.species.as_deref().unwrap_or("")
                         ))
                     )
@@ -448,23 +493,14 @@ impl Drawer {
                 }
             }
             InputMode::Command => {
-                for (id, message) in s.chat.iter().enumerate() {
-                    queue!(
-                        stdout,
-                        MoveTo(30, id as u16),
-                        SetForegroundColor(Color::Red),
-                        Print(&message.sender),
-                        Print(": "),
-                        SetForegroundColor(Color::White),
-                        Print(&message.message)
-                    )
-                    .unwrap();
-                }
+                self.draw_chat(&mut stdout, s);
                 queue!(
                     stdout,
                     MoveTo(0, 0),
                     SetForegroundColor(Color::White),
-                    Print(format!(":{}", self.current_command))
+                    Print(format!(":{}", self.command_line.text())),
+                    MoveTo(1 + self.command_line.cursor_column() as u16, 0),
+                    Show
                 )
                 .unwrap();
             }
@@ -0,0 +1,712 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+
+use crate::rng::Rng;
+
+// Level generation done here rather than in the database. The generators make the same kinds of
+// level as level_layout, drawn from Rng rather than seeded_random, so the same seed gives a
+// different layout here than there. Levels are put in the database with insert, and rooms record
+// which engine made them so regenerate can make them again with the same one.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum LevelType {
+    /// Rooms in a binary space partition joined by corridors
+    Bsp,
+    /// Smoothed random rock, leaving the largest open area
+    Cave,
+    /// Corridors a tile wide with exactly one way between any two places
+    Maze,
+}
+
+impl LevelType {
+    // What rooms.level_type holds for levels of this type
+    pub fn name(self) -> &'static str {
+        match self {
+            LevelType::Bsp => "bsp",
+            LevelType::Cave => "cave",
+            LevelType::Maze => "maze",
+        }
+    }
+}
+
+// The arguments particular to each level type, with the same names and defaults as level_layout
+// reads from rooms.generator
+#[derive(Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room_count: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_room_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_room_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_fraction: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vaults: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Database(sqlx::Error),
+    // A vault that can't be stamped, or two that can't be stamped together
+    Vault(String),
+    // A level type in rooms.level_type that LevelType doesn't have
+    UnknownLevelType(String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Database(e) => write!(f, "{}", e),
+            LevelError::Vault(problem) => write!(f, "{}", problem),
+            LevelError::UnknownLevelType(level_type) => {
+                write!(f, "Unknown level type {}", level_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<sqlx::Error> for LevelError {
+    fn from(e: sqlx::Error) -> Self {
+        LevelError::Database(e)
+    }
+}
+
+// A prefab from admin.vaults. Its legend goes in front of the level's template, so vaults stamped
+// into the same level have to agree on what each symbol is.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Vault {
+    pub name: String,
+    legend: BTreeMap<char, String>,
+    // Every row as wide as the widest, with spaces as floor
+    rows: Vec<Vec<char>>,
+}
+
+impl Vault {
+    // Reads a vault's template the way create_room_template would
+    pub fn parse(name: &str, template: &str) -> Result<Self, LevelError> {
+        let lines = template.split('\n').collect::<Vec<_>>();
+        let (legend_lines, map) = match lines.iter().position(|l| l.trim() == "---") {
+            Some(end) => (&lines[..end], &lines[end + 1..]),
+            None => (&[][..], &lines[..]),
+        };
+        let mut legend = BTreeMap::new();
+        for line in legend_lines.iter().filter(|l| !l.trim().is_empty()) {
+            let entry = line.split_once('=').and_then(|(symbol, entry)| {
+                let mut symbol = symbol.trim().chars();
+                match (symbol.next(), symbol.next()) {
+                    (Some(symbol), None) => Some((symbol, entry.trim().to_owned())),
+                    _ => None,
+                }
+            });
+            let Some((symbol, entry)) = entry else {
+                return Err(LevelError::Vault(format!(
+                    "Legend entries look like \"D = floor, door\", not {} in {}",
+                    line, name
+                )));
+            };
+            legend.insert(symbol, entry);
+        }
+        let map = map
+            .iter()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.chars().map(|c| if c == ' ' { '+' } else { c }).collect())
+            .collect::<Vec<Vec<char>>>();
+        let width = map.iter().map(Vec::len).max().unwrap_or(0);
+        let mut rows = vec![];
+        for (y, mut row) in map.into_iter().enumerate() {
+            if let Some((x, symbol)) = row
+                .iter()
+                .enumerate()
+                .find(|(_, c)| !['#', '+'].contains(c) && !legend.contains_key(c))
+            {
+                return Err(LevelError::Vault(format!(
+                    "Unknown symbol {:?} at {}, {} in {}",
+                    symbol, x, y, name
+                )));
+            }
+            row.resize(width, '+');
+            rows.push(row);
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            legend,
+            rows,
+        })
+    }
+
+    fn width(&self) -> i32 {
+        self.rows.first().map_or(0, Vec::len) as i32
+    }
+
+    fn height(&self) -> i32 {
+        self.rows.len() as i32
+    }
+}
+
+// Every vault in admin.vaults, by name
+pub async fn load_vaults(db: impl PgExecutor<'_>) -> Result<Vec<Vault>, LevelError> {
+    let vaults = sqlx::query_file!("sql/admin/vaults.sql")
+        .fetch_all(db)
+        .await?;
+    vaults
+        .iter()
+        .map(|v| Vault::parse(&v.name, &v.template))
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cell {
+    Empty,
+    Wall,
+    Floor,
+}
+
+impl Cell {
+    pub fn symbol(self) -> char {
+        match self {
+            Cell::Empty => ' ',
+            Cell::Wall => '#',
+            Cell::Floor => '+',
+        }
+    }
+
+    pub fn species(self) -> Option<&'static str> {
+        match self {
+            Cell::Empty => None,
+            Cell::Wall => Some("wall"),
+            Cell::Floor => Some("floor"),
+        }
+    }
+}
+
+// A generated level, one cell per tile from 0, 0 at the top left, and the legend symbols of
+// whatever vaults put down on its floor
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Grid {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    legend: BTreeMap<char, String>,
+    features: BTreeMap<(i32, i32), char>,
+}
+
+impl Grid {
+    pub fn new(width: i32, height: i32) -> Self {
+        let (width, height) = (width.max(0), height.max(0));
+        Self {
+            width,
+            height,
+            cells: vec![Cell::Empty; (width * height) as usize],
+            legend: BTreeMap::new(),
+            features: BTreeMap::new(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    // Empty anywhere off the grid
+    pub fn get(&self, x: i32, y: i32) -> Cell {
+        if self.contains(x, y) {
+            self.cells[(y * self.width + x) as usize]
+        } else {
+            Cell::Empty
+        }
+    }
+
+    // Does nothing off the grid
+    pub fn set(&mut self, x: i32, y: i32, cell: Cell) {
+        if self.contains(x, y) {
+            self.cells[(y * self.width + x) as usize] = cell;
+        }
+    }
+
+    // Every tile that isn't empty
+    pub fn tiles(&self) -> impl Iterator<Item = (i16, i16, Cell)> + '_ {
+        (0..self.height)
+            .flat_map(move |y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| (x as i16, y as i16, self.get(x, y)))
+            .filter(|(_, _, cell)| *cell != Cell::Empty)
+    }
+
+    // The legend symbol of what a vault put down on the tile, if anything
+    pub fn feature(&self, x: i32, y: i32) -> Option<char> {
+        self.features.get(&(x, y)).copied()
+    }
+
+    // What the vaults put down, as a template of just their legend symbols for create_room_template
+    // to paint. None if no vaults were stamped. Rows with nothing on them get a wall at the start,
+    // as blank lines would be skipped, and admin.create_level sets the terrain after painting.
+    pub fn vault_template(&self) -> Option<String> {
+        let last = self.features.keys().map(|&(_, y)| y).max()?;
+        let rows = (0..=last)
+            .map(|y| {
+                let row = (0..self.width)
+                    .map(|x| self.feature(x, y).unwrap_or(' '))
+                    .collect::<String>();
+                match row.trim_end() {
+                    "" => "#".to_owned(),
+                    row => row.to_owned(),
+                }
+            })
+            .collect::<Vec<_>>();
+        Some(format!("{}---\n{}", self.legend_lines(), rows.join("\n")))
+    }
+
+    fn legend_lines(&self) -> String {
+        self.legend
+            .iter()
+            .map(|(symbol, entry)| format!("{} = {}\n", symbol, entry))
+            .collect()
+    }
+
+    // A string of symbols for each row, as a room's terrain is stored
+    pub fn rows(&self) -> Vec<String> {
+        (0..self.height)
+            .map(|y| (0..self.width).map(|x| self.get(x, y).symbol()).collect())
+            .collect()
+    }
+
+    // The areas of floor that can be walked between, up and down and side to side the same as
+    // players move, in the order their first tile comes reading from the top left
+    pub fn regions(&self) -> Vec<Vec<(i32, i32)>> {
+        let mut reached = vec![false; self.cells.len()];
+        let mut regions = vec![];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) != Cell::Floor || reached[(y * self.width + x) as usize] {
+                    continue;
+                }
+                let mut region = vec![];
+                let mut stack = vec![(x, y)];
+                reached[(y * self.width + x) as usize] = true;
+                while let Some((cx, cy)) = stack.pop() {
+                    region.push((cx, cy));
+                    for (nx, ny) in [(cx - 1, cy), (cx + 1, cy), (cx, cy - 1), (cx, cy + 1)] {
+                        if self.get(nx, ny) == Cell::Floor
+                            && !reached[(ny * self.width + nx) as usize]
+                        {
+                            reached[(ny * self.width + nx) as usize] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                regions.push(region);
+            }
+        }
+        regions
+    }
+
+    // grid_template: walls on every empty tile next to a floor, including diagonally
+    fn wall_in(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let by_floor =
+                    (-1..=1).any(|dy| (-1..=1).any(|dx| self.get(x + dx, y + dy) == Cell::Floor));
+                if self.get(x, y) == Cell::Empty && by_floor {
+                    self.set(x, y, Cell::Wall);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Grid {
+    // The template create_room_template would make the same tiles from, without a trailing
+    // newline. Stamped vaults put their legend in front, as stamp_vaults does.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.legend.is_empty() {
+            writeln!(f, "{}---", self.legend_lines())?;
+        }
+        let rows = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| self.feature(x, y).unwrap_or(self.get(x, y).symbol()))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", rows.join("\n"))
+    }
+}
+
+// A leaf of the BSP tree, one candidate room of a generated dungeon
+#[derive(Clone, Copy)]
+struct Partition {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Partition {
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.w / 2, self.y + self.h / 2)
+    }
+}
+
+// generate_bsp: splits the space across its longer side until every partition fits
+fn partitions(rng: &mut Rng, width: i32, height: i32, max_room_size: i32) -> Vec<Partition> {
+    let max_room_size = max_room_size.max(1);
+    let mut leaves = vec![];
+    let mut queue = vec![(
+        Partition {
+            x: 0,
+            y: 0,
+            w: width,
+            h: height,
+        },
+        true,
+    )];
+    while let Some((p, is_root)) = queue.pop() {
+        if p.w <= max_room_size && p.h <= max_room_size {
+            if !is_root && p.w > 0 && p.h > 0 {
+                leaves.push(p);
+            }
+            continue;
+        }
+        let (first, second) = if p.w >= p.h {
+            let split = 1 + rng.below((p.w - 1) as usize) as i32;
+            (
+                Partition { w: split, ..p },
+                Partition {
+                    x: p.x + split,
+                    w: p.w - split,
+                    ..p
+                },
+            )
+        } else {
+            let split = 1 + rng.below((p.h - 1) as usize) as i32;
+            (
+                Partition { h: split, ..p },
+                Partition {
+                    y: p.y + split,
+                    h: p.h - split,
+                    ..p
+                },
+            )
+        };
+        queue.push((second, false));
+        queue.push((first, false));
+    }
+    leaves
+}
+
+// dungeon_layout: BSP rooms joined by L-shaped corridors along a minimum spanning tree, with
+// loop_fraction as many corridors again that make loops
+pub fn bsp(
+    rng: &mut Rng,
+    width: i32,
+    height: i32,
+    room_count: Option<usize>,
+    min_room_size: i32,
+    max_room_size: i32,
+    loop_fraction: f64,
+) -> Grid {
+    let leaves = partitions(rng, width, height, max_room_size);
+    let leaf_count = leaves.len();
+    let mut selected = leaves
+        .into_iter()
+        .filter(|l| l.w > min_room_size && l.h > min_room_size)
+        .collect::<Vec<_>>();
+    if let Some(room_count) = room_count {
+        if room_count < leaf_count {
+            for i in (1..selected.len()).rev() {
+                selected.swap(i, rng.below(i + 1));
+            }
+        }
+        selected.truncate(room_count);
+    }
+
+    let mut raw_floor = BTreeSet::new();
+    for r in &selected {
+        for x in r.x..r.x + r.w {
+            for y in r.y..r.y + r.h {
+                raw_floor.insert((x, y));
+            }
+        }
+    }
+    // Kruskal's algorithm over every pair of rooms, shortest first
+    let mut edges = vec![];
+    for (i, a) in selected.iter().enumerate() {
+        for (j, b) in selected.iter().enumerate().skip(i + 1) {
+            let ((x1, y1), (x2, y2)) = (a.center(), b.center());
+            edges.push(((x1 - x2).abs() + (y1 - y2).abs(), i, j));
+        }
+    }
+    edges.sort();
+    let mut components = (0..selected.len()).collect::<Vec<_>>();
+    let (mut tree, mut loops) = (vec![], vec![]);
+    for (distance, i, j) in edges {
+        let (a, b) = (components[i], components[j]);
+        if a == b {
+            loops.push((distance, i, j));
+            continue;
+        }
+        for c in components.iter_mut().filter(|c| **c == b) {
+            *c = a;
+        }
+        tree.push((i, j));
+    }
+    let extra = (loop_fraction * tree.len() as f64).round() as usize;
+    tree.extend(loops.iter().take(extra).map(|&(_, i, j)| (i, j)));
+    for (i, j) in tree {
+        let ((x1, y1), (x2, y2)) = (selected[i].center(), selected[j].center());
+        for x in x1.min(x2)..=x1.max(x2) {
+            raw_floor.insert((x, y1));
+        }
+        for y in y1.min(y2)..=y1.max(y2) {
+            raw_floor.insert((x2, y));
+        }
+    }
+
+    // Floor on the outermost tiles is walled over rather than left open to the void
+    let mut grid = Grid::new(width, height);
+    for &(x, y) in &raw_floor {
+        let on_edge = x == 0 || x == width - 1 || y == 0 || y == height - 1;
+        grid.set(x, y, if on_edge { Cell::Wall } else { Cell::Floor });
+    }
+    grid.wall_in();
+    grid
+}
+
+// cave_layout: each tile starts as rock with a chance of fill, then steps times every tile becomes
+// rock if five or more of the nine tiles around and including it are. Only the largest open area
+// is kept, so every part of the cave can be reached.
+pub fn cave(rng: &mut Rng, width: i32, height: i32, fill: f64, steps: i32) -> Grid {
+    let mut grid = Grid::new(width, height);
+    // The outermost tiles stay rock
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            if rng.fraction() >= fill {
+                grid.set(x, y, Cell::Floor);
+            }
+        }
+    }
+
+    for _ in 0..steps {
+        let mut smoothed = grid.clone();
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let rock = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter(|(dx, dy)| grid.get(x + dx, y + dy) != Cell::Floor)
+                    .count();
+                smoothed.set(x, y, if rock < 5 { Cell::Floor } else { Cell::Empty });
+            }
+        }
+        grid = smoothed;
+    }
+
+    let mut regions = grid.regions();
+    let largest = (0..regions.len()).fold(None, |largest: Option<usize>, i| match largest {
+        Some(l) if regions[l].len() >= regions[i].len() => Some(l),
+        _ => Some(i),
+    });
+    if let Some(largest) = largest {
+        regions.swap_remove(largest);
+    }
+    for (x, y) in regions.into_iter().flatten() {
+        grid.set(x, y, Cell::Empty);
+    }
+    grid.wall_in();
+    grid
+}
+
+// maze_layout: passages run between tiles at odd coordinates, going on to a random neighbour that
+// hasn't been reached yet and backing up when there is none. Too small for a maze is left empty.
+pub fn maze(rng: &mut Rng, width: i32, height: i32) -> Grid {
+    let mut grid = Grid::new(width, height);
+    if width < 3 || height < 3 {
+        return grid;
+    }
+    grid.set(1, 1, Cell::Floor);
+    let mut stack = vec![(1, 1)];
+    while let Some(&(x, y)) = stack.last() {
+        let unreached = [(0, -2), (0, 2), (-2, 0), (2, 0)]
+            .into_iter()
+            .filter(|(dx, dy)| {
+                (1..width - 1).contains(&(x + dx))
+                    && (1..height - 1).contains(&(y + dy))
+                    && grid.get(x + dx, y + dy) != Cell::Floor
+            })
+            .collect::<Vec<_>>();
+        if unreached.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let (dx, dy) = unreached[rng.below(unreached.len())];
+        grid.set(x + dx / 2, y + dy / 2, Cell::Floor);
+        grid.set(x + dx, y + dy, Cell::Floor);
+        stack.push((x + dx, y + dy));
+    }
+    grid.wall_in();
+    grid
+}
+
+// stamp_vaults: up to count vaults, each somewhere that is all floor with a ring of floor around
+// it, so there is always a way round whatever the vault blocks. Vaults don't share their rings.
+fn stamp_vaults(
+    grid: &mut Grid,
+    rng: &mut Rng,
+    vaults: &[Vault],
+    count: i32,
+) -> Result<(), LevelError> {
+    let mut taken = BTreeSet::new();
+    for _ in 0..count {
+        if vaults.is_empty() {
+            break;
+        }
+        let vault = &vaults[rng.below(vaults.len())];
+        let (vw, vh) = (vault.width(), vault.height());
+        if vh == 0 {
+            continue;
+        }
+        for (symbol, entry) in &vault.legend {
+            if grid.legend.get(symbol).is_some_and(|e| e != entry) {
+                return Err(LevelError::Vault(format!(
+                    "Vaults disagree on what {} is, {} says {}",
+                    symbol, vault.name, entry
+                )));
+            }
+        }
+        // The top left of the ring
+        let spots = (0..grid.height - vh - 1)
+            .flat_map(|sy| (0..grid.width - vw - 1).map(move |sx| (sx, sy)))
+            .filter(|&(sx, sy)| {
+                (sy..sy + vh + 2).all(|y| {
+                    (sx..sx + vw + 2)
+                        .all(|x| grid.get(x, y) == Cell::Floor && !taken.contains(&(x, y)))
+                })
+            })
+            .collect::<Vec<_>>();
+        if spots.is_empty() {
+            continue;
+        }
+        let (sx, sy) = spots[rng.below(spots.len())];
+        taken.extend((sy..sy + vh + 2).flat_map(|y| (sx..sx + vw + 2).map(move |x| (x, y))));
+        grid.legend.extend(vault.legend.clone());
+        for (dy, row) in vault.rows.iter().enumerate() {
+            for (dx, &symbol) in row.iter().enumerate() {
+                let (x, y) = (sx + 1 + dx as i32, sy + 1 + dy as i32);
+                match symbol {
+                    '#' => grid.set(x, y, Cell::Wall),
+                    '+' => grid.set(x, y, Cell::Floor),
+                    symbol => {
+                        grid.set(x, y, Cell::Floor);
+                        grid.features.insert((x, y), symbol);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// level_layout. Vaults are picked from the ones given, which should be every vault in admin.vaults
+// for the level to come out the same when it is made again.
+pub fn generate(
+    level_type: LevelType,
+    width: i32,
+    height: i32,
+    seed: i64,
+    options: &Options,
+    vaults: &[Vault],
+) -> Result<Grid, LevelError> {
+    let rng = &mut Rng::new(seed as u64);
+    let mut grid = match level_type {
+        LevelType::Bsp => bsp(
+            rng,
+            width,
+            height,
+            options.room_count.map(|n| n.max(0) as usize),
+            options.min_room_size.unwrap_or(3),
+            options.max_room_size.unwrap_or(20),
+            options.loop_fraction.unwrap_or(0.15),
+        ),
+        LevelType::Cave => cave(
+            rng,
+            width,
+            height,
+            options.fill.unwrap_or(0.45),
+            options.steps.unwrap_or(4),
+        ),
+        LevelType::Maze => maze(rng, width, height),
+    };
+    if let Some(count) = options.vaults {
+        stamp_vaults(&mut grid, rng, vaults, count)?;
+    }
+    Ok(grid)
+}
+
+// Makes a new room of the grid through admin.create_level, which stores its rows as the room's
+// terrain and paints what the vaults put down over it. Returns the room's entity_id.
+pub async fn insert(
+    db: impl PgExecutor<'_>,
+    level_type: LevelType,
+    seed: i64,
+    options: &Options,
+    grid: &Grid,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_file_scalar!(
+        "sql/admin/create_level.sql",
+        level_type.name(),
+        grid.width(),
+        grid.height(),
+        seed,
+        sqlx::types::Json(options) as _,
+        &grid.rows(),
+        grid.vault_template()
+    )
+    .fetch_one(db)
+    .await
+}
+
+// The template of the level a room was generated with, made again by the engine that made it.
+// None if the room wasn't generated.
+pub async fn regenerate(db: &PgPool, room_id: i32) -> Result<Option<String>, LevelError> {
+    let Some(level) = sqlx::query_file!("sql/admin/regenerate_level.sql", room_id)
+        .fetch_optional(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+    if level.engine.as_deref() != Some("rust") {
+        return Ok(level.template);
+    }
+    let Ok(level_type) = LevelType::from_str(&level.level_type, true) else {
+        return Err(LevelError::UnknownLevelType(level.level_type));
+    };
+    let vaults = load_vaults(db).await?;
+    let grid = generate(
+        level_type,
+        level.width,
+        level.height,
+        level.seed,
+        &level.generator,
+        &vaults,
+    )?;
+    Ok(Some(grid.to_string()))
+}
@@ -0,0 +1,214 @@
+use std::{
+    io::{Write, stdout},
+    time::Duration,
+};
+
+use crossterm::{
+    ExecutableCommand,
+    cursor::{Hide, MoveTo, Show},
+    event::{Event, KeyEventKind, poll, read},
+    execute, queue,
+    style::{Color, Print, SetForegroundColor},
+    terminal::{self, BeginSynchronizedUpdate, EndSynchronizedUpdate},
+};
+use mpdungeon2::{
+    state::State,
+    template::{RoomTemplate, Tile},
+};
+use sqlx::PgPool;
+
+use crate::{
+    draw::{colour, draw_terrain, glyph},
+    keymap::{Action, Keymap},
+};
+
+// Where the game draws the HP bar, the status line goes there and notices below it
+const STATUS_ROW: u16 = 30;
+
+struct Editor {
+    room: String,
+    template: RoomTemplate,
+    cursor: (i16, i16),
+    // Index into Tile::ALL of what Paint puts down
+    brush: usize,
+    modified: bool,
+    // Set by the first Close with unsaved changes, the second one discards them
+    closing: bool,
+    notice: String,
+}
+
+// Puts the terminal back however the editor is left
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Self {
+        terminal::enable_raw_mode().unwrap();
+        RawMode
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        terminal::disable_raw_mode().unwrap();
+        execute!(stdout(), terminal::Clear(terminal::ClearType::All), Show).unwrap();
+    }
+}
+
+// Edits the room with this name, or a new one if there isn't one yet, until it is closed
+pub async fn run(db: &PgPool, room: String, keymap: Keymap) -> Result<(), sqlx::Error> {
+    let tiles = sqlx::query_file!("sql/admin/room_tiles.sql", room)
+        .fetch_all(db)
+        .await?;
+    let template = RoomTemplate::from_layers(tiles.iter().map(|t| (t.x, t.y, t.layer.as_str())));
+    let mut editor = Editor {
+        notice: if template.is_empty() {
+            format!("{} is a new room", room)
+        } else {
+            format!("Editing {}", room)
+        },
+        room,
+        template,
+        cursor: (0, 0),
+        brush: 0,
+        modified: false,
+        closing: false,
+    };
+
+    let _raw_mode = RawMode::enable();
+    loop {
+        editor.draw();
+        if !poll(Duration::from_millis(100)).unwrap() {
+            continue;
+        }
+        let Event::Key(key) = read().unwrap() else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        let action = match keymap.global(&key) {
+            Some(Action::Quit) => Some(Action::Quit),
+            _ => keymap.editor(&key),
+        };
+        let Some(action) = action else {
+            continue;
+        };
+        if action != Action::Close {
+            editor.closing = false;
+        }
+        match action {
+            Action::Quit => return Ok(()),
+            Action::Close if !editor.modified || editor.closing => return Ok(()),
+            Action::Close => {
+                editor.closing = true;
+                editor.notice = "Unsaved changes, close again to throw them away".to_owned();
+            }
+            Action::Save => editor.save(db).await,
+            Action::Paint => {
+                let (x, y) = editor.cursor;
+                editor.template.set(x, y, Some(Tile::ALL[editor.brush]));
+                editor.modified = true;
+            }
+            Action::Erase => {
+                let (x, y) = editor.cursor;
+                editor.template.set(x, y, None);
+                editor.modified = true;
+            }
+            Action::NextTile => editor.brush = (editor.brush + 1) % Tile::ALL.len(),
+            Action::PreviousTile => {
+                editor.brush = (editor.brush + Tile::ALL.len() - 1) % Tile::ALL.len()
+            }
+            action => {
+                if let Some((dx, dy)) = action.direction() {
+                    // Templates start at 0, 0 and the status line is below the map
+                    editor.cursor = (
+                        (editor.cursor.0 + dx).max(0),
+                        (editor.cursor.1 + dy).clamp(0, STATUS_ROW as i16 - 1),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Editor {
+    async fn save(&mut self, db: &PgPool) {
+        if self.template.is_empty() {
+            self.notice = "Nothing to save".to_owned();
+            return;
+        }
+        if let Some(row) = self.template.blank_row() {
+            self.notice = format!("Row {} is empty, rooms can't have gaps between rows", row);
+            return;
+        }
+        let saved = sqlx::query_file_scalar!(
+            "sql/admin/save_room.sql",
+            self.room,
+            self.template.to_string()
+        )
+        .fetch_one(db)
+        .await;
+        self.notice = match saved {
+            Ok(room_id) => {
+                self.modified = false;
+                format!("Saved {} as room {}", self.room, room_id)
+            }
+            Err(e) => format!("Could not save: {}", e),
+        };
+    }
+
+    fn draw(&self) {
+        let mut stdout = stdout();
+        execute!(stdout, BeginSynchronizedUpdate, Hide).unwrap();
+        queue!(stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
+        // The same drawing as the game, so walls join up the way players will see them
+        let preview = State {
+            entities: self.template.entities(),
+            terrain: Some(self.template.terrain()),
+            self_entity_id: None,
+            chat: vec![],
+            chat_history_complete: true,
+            commands_submitted: 0,
+        };
+        draw_terrain(&mut stdout, &preview);
+        for e in &preview.entities {
+            queue!(
+                stdout,
+                MoveTo(e.x as u16, e.y as u16),
+                SetForegroundColor(colour(&preview, e)),
+                Print(glyph(&preview, e))
+            )
+            .unwrap();
+        }
+        let brush = Tile::ALL[self.brush];
+        // Painting over a cell takes away what the editor has no tile for, so say what it is
+        let layers = self.template.layers(self.cursor.0, self.cursor.1);
+        queue!(
+            stdout,
+            MoveTo(0, STATUS_ROW),
+            SetForegroundColor(Color::White),
+            Print(format!(
+                "{}{}  brush: {} {}  at {}, {}{}",
+                self.room,
+                if self.modified { "*" } else { "" },
+                brush.symbol(),
+                brush.name(),
+                self.cursor.0,
+                self.cursor.1,
+                if layers.is_empty() {
+                    String::new()
+                } else {
+                    format!(": {}", layers.join(", "))
+                }
+            )),
+            MoveTo(0, STATUS_ROW + 1),
+            SetForegroundColor(Color::DarkGrey),
+            Print(&self.notice),
+            MoveTo(self.cursor.0 as u16, self.cursor.1 as u16),
+            Show
+        )
+        .unwrap();
+        stdout.flush().unwrap();
+        stdout.execute(EndSynchronizedUpdate).unwrap();
+    }
+}
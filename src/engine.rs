@@ -0,0 +1,606 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    dungeon,
+    networking::{Channel, PlayerCommand, PlayerMessage},
+    rng::Rng,
+    state::{Message, Terrain, WorldEntity},
+};
+
+// The same game as the database runs, kept in memory so it can be played without a server.
+// Each map stands in for the table of the same name, and each method for the SQL it replaces.
+
+#[derive(Clone, Copy, PartialEq)]
+struct Position {
+    x: i16,
+    y: i16,
+    room_id: i32,
+}
+
+struct Room {
+    min_commands: Option<i32>,
+    landing_zone: bool,
+    terrain: Terrain,
+}
+
+struct Hp {
+    hp: i32,
+    maxhp: i32,
+}
+
+struct Door {
+    open: bool,
+    locked: bool,
+    lock: Option<String>,
+}
+
+struct StoredMessage {
+    speaker: i32,
+    recipient: Option<i32>,
+    channel: &'static str,
+    room_id: Option<i32>,
+    message: String,
+    sent_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct World {
+    next_entity_id: i32,
+    // Ordered so everything is visited by entity_id, like the queries that ORDER BY it
+    positions: BTreeMap<i32, Position>,
+    names: HashMap<i32, String>,
+    species: HashMap<i32, String>,
+    commands: HashMap<i32, PlayerCommand>,
+    players: HashMap<i32, String>,
+    rooms: BTreeMap<i32, Room>,
+    impassibles: BTreeSet<i32>,
+    hps: BTreeMap<i32, Hp>,
+    portals: Vec<(i32, i32)>,
+    monsters: BTreeSet<i32>,
+    weights: HashMap<i32, i32>,
+    doors: BTreeMap<i32, Door>,
+    keys: HashMap<i32, String>,
+    messages: Vec<StoredMessage>,
+}
+
+impl World {
+    // The world from the seed data migration, with a generated level below the dungeon
+    pub fn seeded(rng: &mut Rng) -> Self {
+        let mut world = World::default();
+
+        let tavern = world.create_room(Some(1), true);
+        world.names.insert(tavern, "Tavern".to_owned());
+        world.create_room_template(
+            tavern,
+            "
+##########
+#++++++++#
+#++++++++#
+#++++++++#
+#++++++++#
+#++++++++#
+####++####",
+        );
+        let doors = [(4, 6), (5, 6)].map(|(x, y)| world.create_thing("door", x, y, tavern));
+        for door in doors {
+            world.doors.insert(
+                door,
+                Door {
+                    open: true,
+                    locked: false,
+                    lock: None,
+                },
+            );
+        }
+
+        let innkeeper = world.create_creature("innkeeper", 10, 4, 1, tavern);
+        world.names.insert(innkeeper, "Innkeeper".to_owned());
+
+        let dungeon = world.create_room(None, false);
+        world.names.insert(dungeon, "Dungeon".to_owned());
+        world.create_room_template(
+            dungeon,
+            "
+##########       #####
+#++++++++#       #+++#
+#+++####+#       #+++#
+#####++#+#########+++#
+#++++++#+++++++++++++#
+#++++++#+#############
+#####+##+#
+    #++++#
+    ######
+",
+        );
+        let upstair = world.create_thing("upstair", 1, 1, dungeon);
+        for door in doors {
+            world.portals.push((door, upstair));
+            world.portals.push((upstair, door));
+        }
+        for (x, y) in [(3, 5), (18, 2)] {
+            let snake = world.create_creature("snake", 5, x, y, dungeon);
+            world.monsters.insert(snake);
+        }
+
+        let level = world.generate_dungeon(rng, 40, 20, None, 3, 12, 0.15);
+        let floor = world.floor_tiles(level);
+        if !floor.is_empty() {
+            let downstair = world.create_thing("downstair", 20, 1, dungeon);
+            let (x, y) = floor[rng.below(floor.len())];
+            let upstair = world.create_thing("upstair", x, y, level);
+            world.portals.push((downstair, upstair));
+            world.portals.push((upstair, downstair));
+            for _ in 0..3 {
+                let (x, y) = floor[rng.below(floor.len())];
+                let snake = world.create_creature("snake", 5, x, y, level);
+                world.monsters.insert(snake);
+            }
+        }
+
+        world
+    }
+
+    fn new_entity(&mut self) -> i32 {
+        self.next_entity_id += 1;
+        self.next_entity_id
+    }
+
+    fn create_room(&mut self, min_commands: Option<i32>, landing_zone: bool) -> i32 {
+        let room_id = self.new_entity();
+        self.rooms.insert(
+            room_id,
+            Room {
+                min_commands,
+                landing_zone,
+                terrain: Terrain {
+                    room_id,
+                    revision: 0,
+                    rows: vec![],
+                },
+            },
+        );
+        room_id
+    }
+
+    fn create_thing(&mut self, species: &str, x: i16, y: i16, room_id: i32) -> i32 {
+        let entity_id = self.new_entity();
+        self.species.insert(entity_id, species.to_owned());
+        self.positions.insert(entity_id, Position { x, y, room_id });
+        entity_id
+    }
+
+    fn create_creature(&mut self, species: &str, hp: i32, x: i16, y: i16, room_id: i32) -> i32 {
+        let entity_id = self.create_thing(species, x, y, room_id);
+        self.hps.insert(entity_id, Hp { hp, maxhp: hp });
+        self.weights.insert(entity_id, 1);
+        entity_id
+    }
+
+    // create_room_template: '#' is a wall, '+' a floor, blank lines are skipped. Both go into the
+    // room's terrain rather than being things of their own.
+    fn create_room_template(&mut self, room_id: i32, template: &str) {
+        let lines = template.split('\n').filter(|l| !l.trim().is_empty());
+        let rows = lines
+            .map(|l| {
+                l.replace(|c| c != '#' && c != '+', " ")
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect();
+        self.set_terrain(room_id, rows);
+    }
+
+    // The bump_terrain_revision trigger along with the update
+    fn set_terrain(&mut self, room_id: i32, rows: Vec<String>) {
+        if let Some(terrain) = self.rooms.get_mut(&room_id).map(|r| &mut r.terrain)
+            && terrain.rows != rows
+        {
+            terrain.revision += 1;
+            terrain.rows = rows;
+        }
+    }
+
+    fn floor_tiles(&self, room_id: i32) -> Vec<(i16, i16)> {
+        self.rooms.get(&room_id).map_or(vec![], |r| {
+            r.terrain
+                .tiles()
+                .filter(|(_, _, c)| *c == '+')
+                .map(|(x, y, _)| (x, y))
+                .collect()
+        })
+    }
+
+    // generate_dungeon, with the layout dungeon::bsp makes. Returns the new room's entity_id.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_dungeon(
+        &mut self,
+        rng: &mut Rng,
+        width: i32,
+        height: i32,
+        room_count: Option<usize>,
+        min_room_size: i32,
+        max_room_size: i32,
+        loop_fraction: f64,
+    ) -> i32 {
+        let room_id = self.create_room(Some(1), false);
+        let grid = dungeon::bsp(
+            rng,
+            width,
+            height,
+            room_count,
+            min_room_size,
+            max_room_size,
+            loop_fraction,
+        );
+        self.set_terrain(room_id, grid.rows());
+        room_id
+    }
+
+    // The login function, which registers a name that hasn't been seen before
+    // None if the name belongs to someone with a different password, or to something that isn't a player.
+    pub fn login(&mut self, username: &str, password: &str) -> Option<i32> {
+        if let Some(&entity_id) = self
+            .names
+            .iter()
+            .find(|(_, name)| *name == username)
+            .map(|(id, _)| id)
+        {
+            return (self.players.get(&entity_id).map(String::as_str) == Some(password))
+                .then_some(entity_id);
+        }
+        let entity_id = self.new_entity();
+        self.names.insert(entity_id, username.to_owned());
+        self.players.insert(entity_id, password.to_owned());
+        if let Some((&room_id, _)) = self.rooms.iter().find(|(_, r)| r.landing_zone) {
+            self.positions.insert(
+                entity_id,
+                Position {
+                    x: 1,
+                    y: 1,
+                    room_id,
+                },
+            );
+        }
+        self.hps.insert(entity_id, Hp { hp: 10, maxhp: 10 });
+        self.species.insert(entity_id, "human".to_owned());
+        Some(entity_id)
+    }
+
+    // insert_command.sql and the room_tick trigger it sets off
+    pub fn insert_command(&mut self, command: PlayerCommand) {
+        let entity_id = command.entity_id;
+        self.commands.insert(entity_id, command);
+        self.room_tick(entity_id);
+    }
+
+    // blocked(): a wall in the room's terrain, or something impassible standing there
+    fn is_impassible(&self, positions: &BTreeMap<i32, Position>, at: Position) -> bool {
+        let wall = self
+            .rooms
+            .get(&at.room_id)
+            .is_some_and(|r| r.terrain.is_wall(at.x, at.y));
+        wall || self
+            .impassibles
+            .iter()
+            .any(|id| positions.get(id) == Some(&at))
+    }
+
+    fn is_alive(&self, entity_id: i32) -> bool {
+        self.hps.get(&entity_id).is_some_and(|h| h.hp > 0)
+    }
+
+    // room_tick: once everyone in the room has a command in (or min_commands have), carry out every
+    // command in the room and let the monsters act. Everything is decided from the room as it was
+    // before the tick, as the trigger's CTEs all see the same snapshot.
+    fn room_tick(&mut self, trigger: i32) {
+        let Some(room_id) = self.positions.get(&trigger).map(|p| p.room_id) else {
+            return;
+        };
+        let players = self
+            .players
+            .keys()
+            .filter(|id| self.positions.get(id).is_some_and(|p| p.room_id == room_id))
+            .collect::<Vec<_>>();
+        let commanded = players
+            .iter()
+            .filter(|id| self.commands.contains_key(id))
+            .count();
+        let min_commands = self.rooms.get(&room_id).and_then(|r| r.min_commands);
+        if players.is_empty()
+            || commanded != players.len() && min_commands.is_none_or(|m| (commanded as i32) < m)
+        {
+            return;
+        }
+
+        let snapshot = self.positions.clone();
+        let in_room = snapshot
+            .iter()
+            .filter(|(_, p)| p.room_id == room_id)
+            .collect::<Vec<_>>();
+
+        let mut actioned = vec![];
+        for &(&monster, &p) in in_room.iter().filter(|(id, _)| self.monsters.contains(id)) {
+            if !self.is_alive(monster) {
+                continue;
+            }
+            let adjacent = in_room.iter().find(|(id, t)| {
+                **id != monster
+                    && self.hps.contains_key(id)
+                    && (t.x - p.x).abs() <= 1
+                    && (t.y - p.y).abs() <= 1
+            });
+            if let Some(&(&target, _)) = adjacent {
+                actioned.push(PlayerCommand {
+                    entity_id: monster,
+                    command_type: "attack".to_owned(),
+                    x: None,
+                    y: None,
+                    command_target: Some(target),
+                });
+                continue;
+            }
+            let distance = |t: &Position, x: i16, y: i16| (t.x - x).abs() + (t.y - y).abs();
+            let Some((_, target)) = in_room
+                .iter()
+                .filter(|(id, _)| **id != monster && self.is_alive(**id))
+                .min_by_key(|(_, t)| distance(t, p.x, p.y))
+            else {
+                continue;
+            };
+            let step = (-1..=1)
+                .flat_map(|gx| (-1..=1).map(move |gy| (gx, gy)))
+                .filter(|&(gx, gy)| {
+                    let at = Position {
+                        x: p.x + gx,
+                        y: p.y + gy,
+                        room_id,
+                    };
+                    !self.is_impassible(&snapshot, at)
+                })
+                .min_by_key(|&(gx, gy)| distance(target, p.x + gx, p.y + gy));
+            if let Some((gx, gy)) = step {
+                actioned.push(PlayerCommand {
+                    entity_id: monster,
+                    command_type: "move".to_owned(),
+                    x: Some(gx),
+                    y: Some(gy),
+                    command_target: None,
+                });
+            }
+        }
+
+        let removed = in_room
+            .iter()
+            .filter_map(|(id, _)| self.commands.remove(id))
+            .collect::<Vec<_>>();
+        actioned.splice(
+            0..0,
+            removed.into_iter().filter(|c| self.is_alive(c.entity_id)),
+        );
+
+        // The first open or close for each door, by whoever comes first, as DISTINCT ON picks it
+        let mut door_commands = BTreeMap::new();
+        let mut attacked = BTreeSet::new();
+        for c in &actioned {
+            let target = c.command_target.unwrap_or_default();
+            let Some(&from) = snapshot.get(&c.entity_id) else {
+                continue;
+            };
+            match c.command_type.as_str() {
+                "travel" => {
+                    let end = self
+                        .portals
+                        .iter()
+                        .find(|(start, _)| *start == target)
+                        .and_then(|(_, end)| snapshot.get(end));
+                    if let Some(&end) = end {
+                        self.positions.insert(c.entity_id, end);
+                    }
+                }
+                "pickup" if self.positions.contains_key(&target) => {
+                    self.positions.insert(
+                        target,
+                        Position {
+                            x: 0,
+                            y: 0,
+                            room_id: c.entity_id,
+                        },
+                    );
+                }
+                "drop" if self.positions.contains_key(&target) => {
+                    self.positions.insert(target, from);
+                }
+                "move" => {
+                    let to = Position {
+                        x: from.x + c.x.unwrap_or_default(),
+                        y: from.y + c.y.unwrap_or_default(),
+                        room_id: from.room_id,
+                    };
+                    if !self.is_impassible(&snapshot, to) {
+                        self.positions.insert(c.entity_id, to);
+                    }
+                }
+                "open" | "close" => {
+                    let Some((door, at)) = self.doors.get(&target).zip(snapshot.get(&target))
+                    else {
+                        continue;
+                    };
+                    if at.room_id != from.room_id
+                        || (at.x - from.x).abs() > 1
+                        || (at.y - from.y).abs() > 1
+                    {
+                        continue;
+                    }
+                    let has_key = self.keys.iter().any(|(key, lock)| {
+                        Some(lock) == door.lock.as_ref()
+                            && snapshot.get(key).is_some_and(|k| k.room_id == c.entity_id)
+                    });
+                    let opening = c.command_type == "open";
+                    let blocked = if opening {
+                        door.locked && !has_key
+                    } else {
+                        in_room
+                            .iter()
+                            .any(|(id, p)| **p == *at && self.hps.contains_key(id))
+                    };
+                    if !blocked {
+                        door_commands
+                            .entry(target)
+                            .and_modify(|first: &mut (i32, bool, bool)| {
+                                if c.entity_id < first.0 {
+                                    *first = (c.entity_id, opening, has_key)
+                                }
+                            })
+                            .or_insert((c.entity_id, opening, has_key));
+                    }
+                }
+                // Any number of attackers only take one hp a tick, as an UPDATE only touches a row once
+                "attack" => {
+                    attacked.insert(target);
+                }
+                _ => {}
+            }
+        }
+        // Closing a door with its key on you locks it again
+        for (entity_id, (_, opening, has_key)) in door_commands {
+            if let Some(door) = self.doors.get_mut(&entity_id) {
+                door.open = opening;
+                door.locked = !opening && has_key;
+            }
+            if opening {
+                self.impassibles.remove(&entity_id);
+            } else {
+                self.impassibles.insert(entity_id);
+            }
+        }
+        for target in attacked {
+            if let Some(h) = self.hps.get_mut(&target) {
+                h.hp -= 1;
+            }
+        }
+    }
+
+    // say_room.sql, shout.sql and say.sql.
+    // Ok(false) if there was no one to say it to, or Err like api.say's exception when the name
+    // matches more than one
+    pub fn say(&mut self, m: &PlayerMessage) -> Result<bool, String> {
+        let room_id = self.positions.get(&m.speaker).map(|p| p.room_id);
+        let message = |channel, recipient, room_id| StoredMessage {
+            speaker: m.speaker,
+            recipient,
+            channel,
+            room_id,
+            message: m.message.clone(),
+            sent_at: Utc::now(),
+        };
+        match &m.channel {
+            Channel::Room => self.messages.push(message("room", None, room_id)),
+            Channel::World => self.messages.push(message("world", None, None)),
+            Channel::Direct(name) => {
+                // Players first, then whoever is in the speaker's room
+                let preference = |id: &i32| {
+                    (
+                        !self.players.contains_key(id),
+                        self.positions.get(id).map(|p| p.room_id) != room_id,
+                    )
+                };
+                let named = self
+                    .names
+                    .keys()
+                    .filter(|id| {
+                        self.names[id].to_lowercase() == name.to_lowercase()
+                            && !self.rooms.contains_key(id)
+                    })
+                    .collect::<Vec<_>>();
+                let Some(best) = named.iter().map(|id| preference(id)).min() else {
+                    return Ok(false);
+                };
+                let recipients = named
+                    .into_iter()
+                    .filter(|id| preference(id) == best)
+                    .collect::<Vec<_>>();
+                if recipients.len() > 1 {
+                    return Err(format!("There is more than one called {}", name));
+                }
+                self.messages
+                    .push(message("direct", Some(*recipients[0]), None));
+            }
+        }
+        Ok(true)
+    }
+
+    fn display_name(&self, entity_id: i32) -> Option<String> {
+        self.names
+            .get(&entity_id)
+            .or_else(|| self.species.get(&entity_id))
+            .cloned()
+    }
+
+    // get_chat.sql, oldest first and without paging as there is only ever one player to talk to
+    pub fn chat(&self, user_id: i32) -> Vec<Message> {
+        let room_id = self.positions.get(&user_id).map(|p| p.room_id);
+        self.messages
+            .iter()
+            .zip(1..)
+            .filter(|(m, _)| {
+                m.speaker == user_id
+                    || m.recipient == Some(user_id)
+                    || m.channel == "room" && m.room_id.is_some() && m.room_id == room_id
+                    || m.channel == "world"
+            })
+            .map(|(m, message_id)| Message {
+                sender: self.display_name(m.speaker).unwrap_or_default(),
+                receiver: m.recipient.and_then(|r| self.display_name(r)),
+                channel: m.channel.to_owned(),
+                message: m.message.clone(),
+                sent_at: m.sent_at,
+                message_id,
+            })
+            .collect()
+    }
+
+    // get_terrain.sql: the terrain of the user's room
+    pub fn terrain(&self, user_id: i32) -> Option<Terrain> {
+        let me = self.positions.get(&user_id)?;
+        self.rooms.get(&me.room_id).map(|r| r.terrain.clone())
+    }
+
+    // get_world_entities.sql: the user's room and everything they are carrying
+    pub fn entities(&self, user_id: i32) -> Vec<WorldEntity> {
+        let Some(me) = self.positions.get(&user_id) else {
+            return vec![];
+        };
+        self.positions
+            .iter()
+            .filter(|(_, p)| p.room_id == me.room_id || p.room_id == user_id)
+            .map(|(&entity_id, p)| {
+                let command = self.commands.get(&entity_id);
+                let hp = self.hps.get(&entity_id);
+                let ends = self
+                    .portals
+                    .iter()
+                    .filter(|(start, _)| *start == entity_id)
+                    .map(|(_, end)| *end)
+                    .collect::<Vec<_>>();
+                WorldEntity {
+                    entity_id,
+                    x: p.x,
+                    y: p.y,
+                    room_id: p.room_id,
+                    species: self.species.get(&entity_id).cloned(),
+                    name: self.names.get(&entity_id).cloned(),
+                    command_type: command.map(|c| c.command_type.clone()),
+                    command_x: command.and_then(|c| c.x),
+                    command_y: command.and_then(|c| c.y),
+                    hp: hp.map(|h| h.hp),
+                    maxhp: hp.map(|h| h.maxhp),
+                    ends: (!ends.is_empty()).then_some(ends),
+                    weight: self.weights.get(&entity_id).copied(),
+                    open: self.doors.get(&entity_id).map(|d| d.open),
+                }
+            })
+            .collect()
+    }
+}
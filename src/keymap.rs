@@ -0,0 +1,443 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    MoveNorth,
+    MoveNorthEast,
+    MoveEast,
+    MoveSouthEast,
+    MoveSouth,
+    MoveSouthWest,
+    MoveWest,
+    MoveNorthWest,
+    Pickup,
+    Travel,
+    OpenDoor,
+    CloseDoor,
+    OpenInventory,
+    OpenCommand,
+    SelectNext,
+    SelectPrevious,
+    Drop,
+    Close,
+    ChatPageUp,
+    ChatPageDown,
+    Paint,
+    Erase,
+    NextTile,
+    PreviousTile,
+    Save,
+    // Removes a default binding from the key it is assigned to
+    #[serde(rename = "none")]
+    Unbind,
+}
+
+impl Action {
+    pub fn direction(self) -> Option<(i16, i16)> {
+        match self {
+            Action::MoveNorth => Some((0, -1)),
+            Action::MoveNorthEast => Some((1, -1)),
+            Action::MoveEast => Some((1, 0)),
+            Action::MoveSouthEast => Some((1, 1)),
+            Action::MoveSouth => Some((0, 1)),
+            Action::MoveSouthWest => Some((-1, 1)),
+            Action::MoveWest => Some((-1, 0)),
+            Action::MoveNorthWest => Some((-1, -1)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn parse(s: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // A lone "-" is a key in its own right, so only strip prefixes that leave something behind
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let (modifier, len) = if lower.starts_with("ctrl-") {
+                (KeyModifiers::CONTROL, 5)
+            } else if lower.starts_with("alt-") {
+                (KeyModifiers::ALT, 4)
+            } else if lower.starts_with("shift-") {
+                (KeyModifiers::SHIFT, 6)
+            } else {
+                break;
+            };
+            if rest.len() == len {
+                break;
+            }
+            modifiers |= modifier;
+            rest = &rest[len..];
+        }
+
+        let code = match rest.to_ascii_lowercase().as_str() {
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "insert" => KeyCode::Insert,
+            "delete" => KeyCode::Delete,
+            "backspace" => KeyCode::Backspace,
+            "enter" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "esc" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            f if f.len() > 1 && f.starts_with('f') => KeyCode::F(f[1..].parse().ok()?),
+            _ => {
+                let mut chars = rest.chars();
+                let c = chars.next()?;
+                if chars.next().is_some() {
+                    return None;
+                }
+                KeyCode::Char(c)
+            }
+        };
+        Some(Self::new(code, modifiers))
+    }
+
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Terminals report shifted letters as the capital, with or without SHIFT, so "shift-a" is
+        // bound as "A" and shift is dropped from every character
+        match code {
+            KeyCode::Char(c) => {
+                let c = if modifiers.contains(KeyModifiers::SHIFT) {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                };
+                Self {
+                    code: KeyCode::Char(c),
+                    modifiers: modifiers - KeyModifiers::SHIFT,
+                }
+            }
+            _ => Self { code, modifiers },
+        }
+    }
+}
+
+impl From<&KeyEvent> for KeyBinding {
+    fn from(event: &KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+pub enum KeymapError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    InvalidKey(PathBuf, String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Read(path, e) => write!(f, "Could not read {}: {}", path.display(), e),
+            KeymapError::Parse(path, e) => write!(f, "Could not parse {}: {}", path.display(), e),
+            KeymapError::InvalidKey(path, key) => {
+                write!(f, "Unknown key {:?} in {}", key, path.display())
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    #[serde(default)]
+    global: HashMap<String, Action>,
+    #[serde(default)]
+    normal: HashMap<String, Action>,
+    #[serde(default)]
+    inventory: HashMap<String, Action>,
+    #[serde(default)]
+    editor: HashMap<String, Action>,
+}
+
+pub struct Keymap {
+    global: HashMap<KeyBinding, Action>,
+    normal: HashMap<KeyBinding, Action>,
+    inventory: HashMap<KeyBinding, Action>,
+    editor: HashMap<KeyBinding, Action>,
+}
+
+const DEFAULT_GLOBAL: &[(&str, Action)] = &[
+    ("ctrl-c", Action::Quit),
+    ("pageup", Action::ChatPageUp),
+    ("pagedown", Action::ChatPageDown),
+];
+
+const DEFAULT_NORMAL: &[(&str, Action)] = &[
+    ("i", Action::OpenInventory),
+    (":", Action::OpenCommand),
+    (",", Action::Pickup),
+    (">", Action::Travel),
+    ("<", Action::Travel),
+    ("o", Action::OpenDoor),
+    ("c", Action::CloseDoor),
+    // vi-keys
+    ("h", Action::MoveWest),
+    ("j", Action::MoveSouth),
+    ("k", Action::MoveNorth),
+    ("l", Action::MoveEast),
+    ("y", Action::MoveNorthWest),
+    ("u", Action::MoveNorthEast),
+    ("b", Action::MoveSouthWest),
+    ("n", Action::MoveSouthEast),
+    // Arrow keys
+    ("left", Action::MoveWest),
+    ("down", Action::MoveSouth),
+    ("up", Action::MoveNorth),
+    ("right", Action::MoveEast),
+    // Digits, from the number row or the numpad with numlock on, which report the same keys
+    ("4", Action::MoveWest),
+    ("2", Action::MoveSouth),
+    ("8", Action::MoveNorth),
+    ("6", Action::MoveEast),
+    ("7", Action::MoveNorthWest),
+    ("9", Action::MoveNorthEast),
+    ("1", Action::MoveSouthWest),
+    ("3", Action::MoveSouthEast),
+];
+
+const DEFAULT_INVENTORY: &[(&str, Action)] = &[
+    ("esc", Action::Close),
+    ("j", Action::SelectNext),
+    ("k", Action::SelectPrevious),
+    ("down", Action::SelectNext),
+    ("up", Action::SelectPrevious),
+    ("2", Action::SelectNext),
+    ("8", Action::SelectPrevious),
+    ("d", Action::Drop),
+];
+
+const DEFAULT_EDITOR: &[(&str, Action)] = &[
+    ("esc", Action::Close),
+    ("ctrl-s", Action::Save),
+    ("space", Action::Paint),
+    ("x", Action::Erase),
+    ("delete", Action::Erase),
+    ("]", Action::NextTile),
+    ("[", Action::PreviousTile),
+    ("h", Action::MoveWest),
+    ("j", Action::MoveSouth),
+    ("k", Action::MoveNorth),
+    ("l", Action::MoveEast),
+    ("y", Action::MoveNorthWest),
+    ("u", Action::MoveNorthEast),
+    ("b", Action::MoveSouthWest),
+    ("n", Action::MoveSouthEast),
+    ("left", Action::MoveWest),
+    ("down", Action::MoveSouth),
+    ("up", Action::MoveNorth),
+    ("right", Action::MoveEast),
+];
+
+impl Default for Keymap {
+    fn default() -> Self {
+        fn bindings(defaults: &[(&str, Action)]) -> HashMap<KeyBinding, Action> {
+            defaults
+                .iter()
+                .map(|(key, action)| (KeyBinding::parse(key).unwrap(), *action))
+                .collect()
+        }
+        Self {
+            global: bindings(DEFAULT_GLOBAL),
+            normal: bindings(DEFAULT_NORMAL),
+            inventory: bindings(DEFAULT_INVENTORY),
+            editor: bindings(DEFAULT_EDITOR),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("mpdungeon2").join("keymap.toml"))
+    }
+
+    // Loads the defaults, overridden by any bindings in the file at `path`.
+    // A missing file at the default location is not an error, an explicitly requested one is.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, KeymapError> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(KeymapError::Read(path, e)),
+        };
+        let file: KeymapFile =
+            toml::from_str(&contents).map_err(|e| KeymapError::Parse(path.clone(), e))?;
+
+        let mut keymap = Self::default();
+        for (bindings, overrides) in [
+            (&mut keymap.global, file.global),
+            (&mut keymap.normal, file.normal),
+            (&mut keymap.inventory, file.inventory),
+            (&mut keymap.editor, file.editor),
+        ] {
+            for (key, action) in overrides {
+                let binding = KeyBinding::parse(&key)
+                    .ok_or_else(|| KeymapError::InvalidKey(path.clone(), key.clone()))?;
+                if action == Action::Unbind {
+                    bindings.remove(&binding);
+                } else {
+                    bindings.insert(binding, action);
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn global(&self, event: &KeyEvent) -> Option<Action> {
+        self.global.get(&event.into()).copied()
+    }
+
+    pub fn normal(&self, event: &KeyEvent) -> Option<Action> {
+        self.normal.get(&event.into()).copied()
+    }
+
+    pub fn inventory(&self, event: &KeyEvent) -> Option<Action> {
+        self.inventory.get(&event.into()).copied()
+    }
+
+    pub fn editor(&self, event: &KeyEvent) -> Option<Action> {
+        self.editor.get(&event.into()).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyEventState;
+
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_keys_and_modifiers() {
+        let parse = |s| KeyBinding::parse(s).unwrap();
+        assert_eq!(
+            parse("ctrl-s"),
+            KeyBinding::new(KeyCode::Char('s'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            parse("Ctrl-Alt-Left"),
+            KeyBinding::new(KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::ALT)
+        );
+        assert_eq!(
+            parse("f5"),
+            KeyBinding::new(KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse("space"),
+            KeyBinding::new(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse("-"),
+            KeyBinding::new(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse("ctrl--"),
+            KeyBinding::new(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(KeyBinding::parse("fx"), None);
+        assert_eq!(KeyBinding::parse("ab"), None);
+        assert_eq!(KeyBinding::parse(""), None);
+    }
+
+    #[test]
+    fn shifted_letters_match_their_capitals() {
+        let shift_a = KeyBinding::parse("shift-a").unwrap();
+        assert_eq!(shift_a, KeyBinding::parse("A").unwrap());
+        // Whether or not the terminal reports shift along with the capital
+        assert_eq!(
+            KeyBinding::from(&key(KeyCode::Char('A'), KeyModifiers::SHIFT)),
+            shift_a
+        );
+        assert_eq!(
+            KeyBinding::from(&key(KeyCode::Char('A'), KeyModifiers::NONE)),
+            shift_a
+        );
+        assert_ne!(
+            KeyBinding::from(&key(KeyCode::Char('a'), KeyModifiers::NONE)),
+            shift_a
+        );
+    }
+
+    #[test]
+    fn digits_move_from_the_number_row_and_the_numpad() {
+        let keymap = Keymap::default();
+        let digit = key(KeyCode::Char('8'), KeyModifiers::NONE);
+        assert_eq!(keymap.normal(&digit), Some(Action::MoveNorth));
+        let numpad = KeyEvent::new_with_kind_and_state(
+            KeyCode::Char('8'),
+            KeyModifiers::NONE,
+            crossterm::event::KeyEventKind::Press,
+            KeyEventState::KEYPAD,
+        );
+        assert_eq!(keymap.normal(&numpad), Some(Action::MoveNorth));
+        assert_eq!(keymap.inventory(&numpad), Some(Action::SelectPrevious));
+    }
+
+    #[test]
+    fn looks_up_each_mode_separately() {
+        let keymap = Keymap::default();
+        let j = key(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(keymap.normal(&j), Some(Action::MoveSouth));
+        assert_eq!(keymap.inventory(&j), Some(Action::SelectNext));
+        assert_eq!(keymap.global(&j), None);
+        let ctrl_c = key(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(keymap.global(&ctrl_c), Some(Action::Quit));
+        assert_eq!(keymap.normal(&ctrl_c), None);
+    }
+
+    #[test]
+    fn files_override_and_unbind_defaults() {
+        let path = std::env::temp_dir().join(format!("keymap-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[normal]\n\"shift-q\" = \"quit\"\nh = \"none\"\nx = \"move_west\"\n",
+        )
+        .unwrap();
+        let keymap = Keymap::load(Some(path.clone())).ok().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let press = |c| key(KeyCode::Char(c), KeyModifiers::NONE);
+        assert_eq!(keymap.normal(&press('Q')), Some(Action::Quit));
+        assert_eq!(keymap.normal(&press('h')), None);
+        assert_eq!(keymap.normal(&press('x')), Some(Action::MoveWest));
+        assert_eq!(keymap.normal(&press('j')), Some(Action::MoveSouth));
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        let path = std::env::temp_dir().join(format!("keymap-bad-{}.toml", std::process::id()));
+        std::fs::write(&path, "[normal]\nfoo = \"quit\"\n").unwrap();
+        let loaded = Keymap::load(Some(path.clone()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(KeymapError::InvalidKey(_, key)) if key == "foo"));
+    }
+}
@@ -0,0 +1,11 @@
+pub mod bot;
+pub mod chat;
+pub mod client;
+pub mod dungeon;
+pub mod engine;
+pub mod networking;
+pub mod recording;
+pub mod rng;
+pub mod schema;
+pub mod state;
+pub mod template;
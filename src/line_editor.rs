@@ -0,0 +1,319 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::UnicodeWidthChar;
+
+const HISTORY_LIMIT: usize = 500;
+
+pub enum LineEvent {
+    Submit(String),
+    Cancel,
+}
+
+// A single line of input with a cursor and a history that is kept across sessions
+pub struct LineEditor {
+    line: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    // Position while browsing history with up/down, None when editing a fresh line
+    history_index: Option<usize>,
+    // The fresh line, put back when browsing runs off the newest end of the history
+    draft: Vec<char>,
+    history_path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    // Starts an empty line with the history saved by previous sessions
+    pub fn load() -> Self {
+        let history_path = dirs::data_dir().map(|d| d.join("mpdungeon2").join("history"));
+        let mut history = history_path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .map(|h| h.lines().map(str::to_owned).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if history.len() > HISTORY_LIMIT {
+            history.drain(..history.len() - HISTORY_LIMIT);
+            // The file is only ever appended to while playing, trim it back down now and then
+            if let Some(path) = &history_path {
+                let _ = std::fs::write(path, history.join("\n") + "\n");
+            }
+        }
+        Self {
+            line: vec![],
+            cursor: 0,
+            history,
+            history_index: None,
+            draft: vec![],
+            history_path,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    // How many terminal columns the text before the cursor takes up, wide characters taking two
+    pub fn cursor_column(&self) -> usize {
+        self.line[..self.cursor]
+            .iter()
+            .map(|c| c.width().unwrap_or(0))
+            .sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.history_index = None;
+        self.draft.clear();
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<LineEvent> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Enter => {
+                let text = self.text();
+                self.push_history(&text);
+                self.clear();
+                return Some(LineEvent::Submit(text));
+            }
+            KeyCode::Esc => {
+                self.clear();
+                return Some(LineEvent::Cancel);
+            }
+            KeyCode::Left if ctrl => self.cursor = self.word_start(),
+            KeyCode::Right if ctrl => self.cursor = self.word_end(),
+            KeyCode::Char('b') if alt => self.cursor = self.word_start(),
+            KeyCode::Char('f') if alt => self.cursor = self.word_end(),
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Char('b') if ctrl => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+            KeyCode::Char('f') if ctrl => self.cursor = (self.cursor + 1).min(self.line.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::End => self.cursor = self.line.len(),
+            KeyCode::Char('e') if ctrl => self.cursor = self.line.len(),
+            KeyCode::Backspace if ctrl || alt => self.delete_word(),
+            KeyCode::Char('w') if ctrl => self.delete_word(),
+            KeyCode::Char('h') if ctrl => self.backspace(),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            KeyCode::Char('u') if ctrl => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            KeyCode::Char('k') if ctrl => self.line.truncate(self.cursor),
+            KeyCode::Up => self.history_previous(),
+            KeyCode::Char('p') if ctrl => self.history_previous(),
+            KeyCode::Down => self.history_next(),
+            KeyCode::Char('n') if ctrl => self.history_next(),
+            KeyCode::Char(c) if !ctrl && !alt => {
+                self.line.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn delete_word(&mut self) {
+        let start = self.word_start();
+        self.line.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    // Start of the word before the cursor, skipping any whitespace in between
+    fn word_start(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && self.line[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        while i > 0 && !self.line[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        i
+    }
+
+    fn word_end(&self) -> usize {
+        let mut i = self.cursor;
+        while i < self.line.len() && self.line[i].is_whitespace() {
+            i += 1;
+        }
+        while i < self.line.len() && !self.line[i].is_whitespace() {
+            i += 1;
+        }
+        i
+    }
+
+    fn history_previous(&mut self) {
+        let index = match self.history_index {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = std::mem::take(&mut self.line);
+                self.history.len() - 1
+            }
+            Some(0) => return,
+            Some(i) => i - 1,
+        };
+        self.history_index = Some(index);
+        self.line = self.history[index].chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.line = self.history[index + 1].chars().collect();
+        } else {
+            self.history_index = None;
+            self.line = std::mem::take(&mut self.draft);
+        }
+        self.cursor = self.line.len();
+    }
+
+    fn push_history(&mut self, text: &str) {
+        if text.trim().is_empty() || self.history.last().map(String::as_str) == Some(text) {
+            return;
+        }
+        self.history.push(text.to_owned());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        // History is a nicety, failing to save it shouldn't interrupt the game
+        if let Some(path) = &self.history_path {
+            if let Some(dir) = path.parent() {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", text);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(history: &[&str]) -> LineEditor {
+        LineEditor {
+            line: vec![],
+            cursor: 0,
+            history: history.iter().map(|h| h.to_string()).collect(),
+            history_index: None,
+            draft: vec![],
+            history_path: None,
+        }
+    }
+
+    fn press(editor: &mut LineEditor, code: KeyCode) -> Option<LineEvent> {
+        editor.handle_key(&KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn ctrl(editor: &mut LineEditor, c: char) {
+        editor.handle_key(&KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+    }
+
+    fn type_in(editor: &mut LineEditor, text: &str) {
+        for c in text.chars() {
+            press(editor, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn the_cursor_stops_at_the_ends_of_the_line() {
+        let mut e = editor(&[]);
+        press(&mut e, KeyCode::Left);
+        press(&mut e, KeyCode::Backspace);
+        assert_eq!((e.text().as_str(), e.cursor), ("", 0));
+
+        type_in(&mut e, "ab");
+        press(&mut e, KeyCode::Right);
+        press(&mut e, KeyCode::Delete);
+        assert_eq!((e.text().as_str(), e.cursor), ("ab", 2));
+
+        press(&mut e, KeyCode::Home);
+        press(&mut e, KeyCode::Left);
+        type_in(&mut e, "x");
+        assert_eq!((e.text().as_str(), e.cursor), ("xab", 1));
+        ctrl(&mut e, 'e');
+        assert_eq!(e.cursor, 3);
+    }
+
+    #[test]
+    fn words_are_skipped_and_deleted_whole() {
+        let mut e = editor(&[]);
+        type_in(&mut e, "tell bob  hi");
+        ctrl(&mut e, 'w');
+        assert_eq!(e.text(), "tell bob  ");
+        ctrl(&mut e, 'w');
+        assert_eq!(e.text(), "tell ");
+        e.handle_key(&KeyEvent::new(KeyCode::Left, KeyModifiers::CONTROL));
+        assert_eq!(e.cursor, 0);
+        e.handle_key(&KeyEvent::new(KeyCode::Right, KeyModifiers::CONTROL));
+        assert_eq!(e.cursor, 4);
+        ctrl(&mut e, 'k');
+        assert_eq!(e.text(), "tell");
+        ctrl(&mut e, 'u');
+        assert_eq!((e.text().as_str(), e.cursor), ("", 0));
+    }
+
+    #[test]
+    fn history_is_browsed_and_the_draft_kept() {
+        let mut e = editor(&["first", "second"]);
+        type_in(&mut e, "draft");
+        press(&mut e, KeyCode::Up);
+        assert_eq!(e.text(), "second");
+        press(&mut e, KeyCode::Up);
+        press(&mut e, KeyCode::Up);
+        assert_eq!((e.text().as_str(), e.cursor), ("first", 5));
+        press(&mut e, KeyCode::Down);
+        press(&mut e, KeyCode::Down);
+        assert_eq!(e.text(), "draft");
+        press(&mut e, KeyCode::Down);
+        assert_eq!(e.text(), "draft");
+    }
+
+    #[test]
+    fn submitting_adds_to_history_once() {
+        let mut e = editor(&["old"]);
+        type_in(&mut e, "new");
+        assert!(matches!(press(&mut e, KeyCode::Enter), Some(LineEvent::Submit(t)) if t == "new"));
+        type_in(&mut e, "new");
+        press(&mut e, KeyCode::Enter);
+        press(&mut e, KeyCode::Enter);
+        assert_eq!(e.history, ["old", "new"]);
+        assert_eq!(e.text(), "");
+
+        type_in(&mut e, "never mind");
+        assert!(matches!(
+            press(&mut e, KeyCode::Esc),
+            Some(LineEvent::Cancel)
+        ));
+        assert_eq!(e.history, ["old", "new"]);
+    }
+
+    #[test]
+    fn wide_characters_move_the_cursor_two_columns() {
+        let mut e = editor(&[]);
+        type_in(&mut e, "a宿屋b");
+        assert_eq!((e.cursor, e.cursor_column()), (4, 6));
+        press(&mut e, KeyCode::Left);
+        press(&mut e, KeyCode::Left);
+        assert_eq!((e.cursor, e.cursor_column()), (2, 3));
+        press(&mut e, KeyCode::Backspace);
+        assert_eq!((e.text().as_str(), e.cursor_column()), ("a屋b", 1));
+    }
+}
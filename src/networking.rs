@@ -1,10 +1,31 @@
-use std::thread::JoinHandle;
+use std::{
+    fmt,
+    sync::{Arc, mpsc},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
 use tokio::sync::watch;
 
-use crate::state::{Message, State, WorldEntity};
+use crate::{
+    engine::World,
+    recording::{RecordedEvent, Recorder},
+    rng::Rng,
+    schema::{self, SchemaError},
+    state::{self, Message, State, Terrain, WorldEntity},
+};
 
+const CHAT_PAGE_SIZE: i64 = 50;
+// How far below the newest line's message_id new chat is fetched from. message_ids are handed out
+// when a line is sent but only seen once it commits, so a line can turn up after higher ones have.
+const CHAT_OVERLAP: i64 = 50;
+// Well inside the server's session_timeout(), so a slow tick doesn't let someone else take over
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// How long quitting waits for the server to end the session
+const LOGOUT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PlayerCommand {
     pub entity_id: i32,
     pub command_type: String,
@@ -13,86 +34,336 @@ pub struct PlayerCommand {
     pub command_target: Option<i32>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Channel {
+    Room,
+    World,
+    Direct(String),
+}
+
+impl Channel {
+    // The channel's name and recipient, as api.say takes them
+    fn to_api(&self) -> (&'static str, Option<&str>) {
+        match self {
+            Channel::Room => ("room", None),
+            Channel::World => ("world", None),
+            Channel::Direct(recipient) => ("direct", Some(recipient)),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PlayerMessage {
     pub speaker: i32,
-    pub recipient_species: String,
+    pub channel: Channel,
     pub message: String,
 }
 
 pub struct ServerConnection {
-    pub last_state: &'static ArcSwap<State>,
-    pub command_tx: tokio::sync::watch::Sender<Option<PlayerCommand>>,
-    pub message_tx: tokio::sync::watch::Sender<Option<PlayerMessage>>,
-    pub join_handle: JoinHandle<ExitResult>,
+    pub state_rx: watch::Receiver<Arc<State>>,
+    pub command_tx: watch::Sender<Option<PlayerCommand>>,
+    pub message_tx: watch::Sender<Option<PlayerMessage>>,
+    pub history_tx: watch::Sender<()>,
+    // Tells the thread to log out and stop, which dropping the connection does
+    quit_tx: watch::Sender<()>,
+    // Feedback from the server about things the player asked for, such as an unknown tell recipient
+    pub notice_rx: mpsc::Receiver<String>,
+    pub join_handle: Option<JoinHandle<ExitResult>>,
 }
 
 pub enum ExitResult {
+    // Most often mpd_player without a password, or the wrong one
+    ConnectFailed(sqlx::Error),
     LoginFailed,
+    // Someone is already playing the character and takeover wasn't asked for
+    AlreadyLoggedIn,
+    // Someone else took over the character
+    LoggedInElsewhere,
+    ConnectionLost,
+    IncompatibleSchema(SchemaError),
+    // The client quit and the session was ended
+    LoggedOut,
+}
+
+impl fmt::Display for ExitResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitResult::ConnectFailed(e) => write!(
+                f,
+                "Could not connect to the server: {}. Clients log in as mpd_player, which needs a \
+                 password set by `mpdungeon2 migrate up --player-password`",
+                e
+            ),
+            ExitResult::LoginFailed => write!(f, "Login failed"),
+            ExitResult::AlreadyLoggedIn => write!(f, "Already logged in elsewhere"),
+            ExitResult::LoggedInElsewhere => {
+                write!(
+                    f,
+                    "Logged out, the character was taken over from somewhere else"
+                )
+            }
+            ExitResult::ConnectionLost => write!(f, "Lost connection to the server"),
+            ExitResult::IncompatibleSchema(e) => write!(f, "{}", e),
+            ExitResult::LoggedOut => write!(f, "Logged out"),
+        }
+    }
+}
+
+// Writes to the recorder until it fails, then tells the player and stops
+fn record_to(
+    mut recorder: Option<Recorder>,
+    notice_tx: &mpsc::Sender<String>,
+) -> impl FnMut(RecordedEvent) + '_ {
+    move |event| {
+        if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(event)) {
+            notice_tx.send(format!("Stopped recording: {}", e)).unwrap();
+            recorder = None;
+        }
+    }
+}
+
+// Tells the player why a tell went nowhere, given what api.say or the offline world made of it
+fn notice_undelivered(
+    notice_tx: &mpsc::Sender<String>,
+    channel: &Channel,
+    delivered: Result<bool, String>,
+) {
+    match (delivered, channel) {
+        (Ok(false), Channel::Direct(recipient)) => notice_tx
+            .send(format!("There is no one called {}", recipient))
+            .unwrap(),
+        (Err(e), _) => notice_tx.send(e).unwrap(),
+        _ => {}
+    }
 }
 
 impl ServerConnection {
     #[tokio::main]
+    #[allow(clippy::too_many_arguments)]
     pub async fn start_thread(
-        last_state: &ArcSwap<State>,
+        state_tx: watch::Sender<Arc<State>>,
         conn_addr: String,
         username: String,
         password: String,
+        takeover: bool,
         mut command_rx: watch::Receiver<Option<PlayerCommand>>,
         mut message_rx: watch::Receiver<Option<PlayerMessage>>,
+        mut history_rx: watch::Receiver<()>,
+        mut quit_rx: watch::Receiver<()>,
+        notice_tx: mpsc::Sender<String>,
+        recorder: Option<Recorder>,
     ) -> ExitResult {
-        let db_pool = sqlx::PgPool::connect(&conn_addr).await.unwrap();
-        let user_ids = sqlx::query_file!("sql/login.sql", username, password)
-            .fetch_all(&db_pool)
+        let mut record = record_to(recorder, &notice_tx);
+        let db_pool = match sqlx::PgPool::connect(&conn_addr).await {
+            Ok(db_pool) => db_pool,
+            Err(e) => return ExitResult::ConnectFailed(e),
+        };
+        // Better to refuse now than to fail on the first query that no longer fits
+        if let Err(e) = schema::check(&db_pool).await {
+            return ExitResult::IncompatibleSchema(e);
+        }
+        let login = sqlx::query_file!("sql/login.sql", username, password, takeover)
+            .fetch_optional(&db_pool)
             .await
-            .unwrap();
-        let user_id = if user_ids.is_empty() {
-            sqlx::query_file!("sql/create_player.sql", username, password)
-                .fetch_one(&db_pool)
+            .unwrap()
+            .map(|l| (l.entity_id, l.token));
+        // A name no one has yet is registered to whoever asks for it first
+        let login = match login {
+            Some(login) => Some(login),
+            None => sqlx::query_file!("sql/register.sql", username, password)
+                .fetch_optional(&db_pool)
                 .await
                 .unwrap()
-                .entity_id
-        } else {
-            if user_ids[0].logged_in {
-                user_ids[0].entity_id
-            } else {
-                return ExitResult::LoginFailed;
-            }
+                .map(|r| (r.entity_id, Some(r.token))),
+        };
+        let Some((user_id, token)) = login else {
+            return ExitResult::LoginFailed;
         };
+        let Some(token) = token else {
+            return ExitResult::AlreadyLoggedIn;
+        };
+        let mut last_heartbeat = Instant::now();
 
+        let mut chat: Vec<Message> = vec![];
+        let mut chat_history_complete = false;
+        let mut commands_submitted = 0;
+        let mut terrain: Option<Terrain> = None;
         loop {
             tokio::select! {
-                _ = tokio::time::sleep(std::time::Duration::from_millis(200))  => {
+                _ = tokio::time::sleep(Duration::from_millis(200))  => {
                 },
+                _ = quit_rx.changed()  => {
+                    // Frees the character to log in again now rather than once the session goes
+                    // stale, which is still what happens if this fails
+                    let _ = sqlx::query_file!("sql/logout.sql", token).fetch_one(&db_pool).await;
+                    return ExitResult::LoggedOut;
+                }
                 _ = message_rx.changed()  => {
                     if let Some(m) = message_rx.borrow_and_update().as_ref() {
-                        sqlx::query_file!("sql/say.sql", m.speaker, m.recipient_species, m.message).execute(&db_pool).await.unwrap();
+                        let (channel, recipient) = m.channel.to_api();
+                        let said = sqlx::query_file!("sql/say.sql", token, channel, recipient, m.message).fetch_one(&db_pool).await;
+                        let delivered = match said {
+                            Ok(row) => Ok(row.delivered),
+                            // Ambiguous tells are raised by api.say for the player to read
+                            Err(sqlx::Error::Database(e)) => Err(e.message().to_owned()),
+                            Err(e) => panic!("{}", e),
+                        };
+                        notice_undelivered(&notice_tx, &m.channel, delivered);
+                        record(RecordedEvent::Message(m.clone()));
                     }
 
                 }
+                _ = history_rx.changed()  => {
+                    history_rx.borrow_and_update();
+                    if let (false, Some(oldest)) = (chat_history_complete, chat.first()) {
+                        let mut older = sqlx::query_file_as!(
+                            Message,
+                            "sql/get_chat.sql",
+                            token,
+                            Some(oldest.message_id),
+                            None::<i64>,
+                            Some(CHAT_PAGE_SIZE)
+                        )
+                        .fetch_all(&db_pool)
+                        .await
+                        .unwrap();
+                        chat_history_complete = (older.len() as i64) < CHAT_PAGE_SIZE;
+                        older.reverse();
+                        chat.splice(0..0, older);
+                    }
+                }
                 _ = command_rx.changed()  => {
                     if let Some(c) = command_rx.borrow_and_update().as_ref() {
-                        sqlx::query_file!("sql/insert_command.sql", c.entity_id, c.command_type, c.x, c.y, c.command_target).execute(&db_pool).await.unwrap();
+                        let accepted = sqlx::query_file!("sql/insert_command.sql", token, c.command_type, c.x, c.y, c.command_target).fetch_one(&db_pool).await.unwrap().accepted;
+                        if !accepted {
+                            return ExitResult::LoggedInElsewhere;
+                        }
+                        commands_submitted += 1;
+                        record(RecordedEvent::Command(c.clone()));
                     }
 
                 }
 
             };
-            let chat = sqlx::query_file_as!(Message, "sql/get_chat.sql", user_id)
-                .fetch_all(&db_pool)
-                .await
-                .unwrap();
-            let entities = sqlx::query_file_as!(WorldEntity, "sql/get_world_entities.sql", user_id)
+            if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+                let alive = sqlx::query_file!("sql/heartbeat.sql", token)
+                    .fetch_one(&db_pool)
+                    .await
+                    .unwrap()
+                    .alive;
+                if !alive {
+                    return ExitResult::LoggedInElsewhere;
+                }
+                last_heartbeat = Instant::now();
+            }
+            // Only fetch what arrived since just below the newest line we have, older pages come
+            // in on request
+            let newer = sqlx::query_file_as!(
+                Message,
+                "sql/get_chat.sql",
+                token,
+                None::<i64>,
+                chat.last().map(|m| m.message_id - CHAT_OVERLAP),
+                chat.is_empty().then_some(CHAT_PAGE_SIZE)
+            )
+            .fetch_all(&db_pool)
+            .await
+            .unwrap();
+            if chat.is_empty() {
+                chat_history_complete = (newer.len() as i64) < CHAT_PAGE_SIZE;
+            }
+            state::merge_chat(&mut chat, newer);
+            let entities = sqlx::query_file_as!(WorldEntity, "sql/get_world_entities.sql", token)
                 .fetch_all(&db_pool)
                 .await
                 .unwrap();
-            last_state.store(
-                State {
-                    entities,
-                    chat,
-                    self_entity_id: Some(user_id),
+            // Only comes back when the player is in another room or the room's terrain has changed
+            let in_room = entities.iter().any(|e| e.entity_id == user_id);
+            let changed = sqlx::query_file_as!(
+                Terrain,
+                "sql/get_terrain.sql",
+                token,
+                terrain.as_ref().map(|t| t.room_id),
+                terrain.as_ref().map(|t| t.revision)
+            )
+            .fetch_optional(&db_pool)
+            .await
+            .unwrap();
+            if changed.is_some() || !in_room {
+                terrain = changed;
+            }
+            let state = State {
+                entities,
+                terrain: terrain.clone(),
+                chat: chat.clone(),
+                chat_history_complete,
+                commands_submitted,
+                self_entity_id: Some(user_id),
+            };
+            record(RecordedEvent::State(state.clone()));
+            state_tx.send_replace(state.into());
+        }
+    }
+
+    // Plays against a world of its own instead of a server, with the same rules as the database
+    #[tokio::main]
+    async fn start_offline_thread(
+        state_tx: watch::Sender<Arc<State>>,
+        username: String,
+        mut command_rx: watch::Receiver<Option<PlayerCommand>>,
+        mut message_rx: watch::Receiver<Option<PlayerMessage>>,
+        mut quit_rx: watch::Receiver<()>,
+        notice_tx: mpsc::Sender<String>,
+        recorder: Option<Recorder>,
+    ) -> ExitResult {
+        let mut record = record_to(recorder, &notice_tx);
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        let mut world = World::seeded(&mut Rng::new(seed));
+        // No one else can log in, so any password will do
+        let Some(user_id) = world.login(&username, "") else {
+            return ExitResult::LoginFailed;
+        };
+
+        let mut commands_submitted = 0;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(200))  => {
+                },
+                _ = quit_rx.changed()  => {
+                    return ExitResult::LoggedOut;
+                }
+                changed = message_rx.changed()  => {
+                    if changed.is_err() {
+                        return ExitResult::ConnectionLost;
+                    }
+                    if let Some(m) = message_rx.borrow_and_update().as_ref() {
+                        notice_undelivered(&notice_tx, &m.channel, world.say(m));
+                        record(RecordedEvent::Message(m.clone()));
+                    }
+                }
+                changed = command_rx.changed()  => {
+                    if changed.is_err() {
+                        return ExitResult::ConnectionLost;
+                    }
+                    if let Some(c) = command_rx.borrow_and_update().as_ref() {
+                        world.insert_command(c.clone());
+                        commands_submitted += 1;
+                        record(RecordedEvent::Command(c.clone()));
+                    }
                 }
-                .into(),
-            );
+            };
+            let state = State {
+                entities: world.entities(user_id),
+                terrain: world.terrain(user_id),
+                chat: world.chat(user_id),
+                chat_history_complete: true,
+                commands_submitted,
+                self_entity_id: Some(user_id),
+            };
+            record(RecordedEvent::State(state.clone()));
+            state_tx.send_replace(state.into());
         }
     }
 
@@ -102,34 +373,100 @@ impl ServerConnection {
     pub fn say(&self, msg: PlayerMessage) {
         self.message_tx.send(Some(msg)).unwrap();
     }
+    pub fn fetch_older_chat(&self) {
+        self.history_tx.send(()).unwrap();
+    }
 
-    pub fn new(server_addr: String, username: String, password: String) -> Self {
-        let (command_tx, command_rx) = tokio::sync::watch::channel(None);
-        let (message_tx, message_rx) = tokio::sync::watch::channel(None);
-        let last_state: &_ = Box::leak(Box::new(ArcSwap::new(
-            State {
-                entities: vec![],
-                chat: vec![],
-                self_entity_id: None,
-            }
-            .into(),
-        )));
+    pub fn new(
+        server_addr: String,
+        username: String,
+        password: String,
+        takeover: bool,
+        recorder: Option<Recorder>,
+    ) -> Self {
+        Self::spawn(
+            move |state_tx, command_rx, message_rx, history_rx, quit_rx, notice_tx| {
+                Self::start_thread(
+                    state_tx,
+                    server_addr,
+                    username,
+                    password,
+                    takeover,
+                    command_rx,
+                    message_rx,
+                    history_rx,
+                    quit_rx,
+                    notice_tx,
+                    recorder,
+                )
+            },
+        )
+    }
+
+    pub fn offline(username: String, recorder: Option<Recorder>) -> Self {
+        Self::spawn(
+            move |state_tx, command_rx, message_rx, _history_rx, quit_rx, notice_tx| {
+                Self::start_offline_thread(
+                    state_tx, username, command_rx, message_rx, quit_rx, notice_tx, recorder,
+                )
+            },
+        )
+    }
+
+    // Sets up the channels to a connection thread and starts it
+    fn spawn(
+        start: impl FnOnce(
+            watch::Sender<Arc<State>>,
+            watch::Receiver<Option<PlayerCommand>>,
+            watch::Receiver<Option<PlayerMessage>>,
+            watch::Receiver<()>,
+            watch::Receiver<()>,
+            mpsc::Sender<String>,
+        ) -> ExitResult
+        + Send
+        + 'static,
+    ) -> Self {
+        let (command_tx, command_rx) = watch::channel(None);
+        let (message_tx, message_rx) = watch::channel(None);
+        let (history_tx, history_rx) = watch::channel(());
+        let (quit_tx, quit_rx) = watch::channel(());
+        let (notice_tx, notice_rx) = mpsc::channel();
+        let (state_tx, state_rx) = watch::channel(Arc::new(State {
+            entities: vec![],
+            terrain: None,
+            chat: vec![],
+            chat_history_complete: false,
+            commands_submitted: 0,
+            self_entity_id: None,
+        }));
         let join_handle = std::thread::spawn(move || {
-            Self::start_thread(
-                last_state,
-                server_addr,
-                username,
-                password,
-                command_rx,
-                message_rx,
+            start(
+                state_tx, command_rx, message_rx, history_rx, quit_rx, notice_tx,
             )
         });
 
         Self {
-            last_state,
+            state_rx,
             command_tx,
-            join_handle,
+            join_handle: Some(join_handle),
             message_tx,
+            history_tx,
+            quit_tx,
+            notice_rx,
+        }
+    }
+}
+
+impl Drop for ServerConnection {
+    // Waits a moment for the thread to log out, a connection that hangs is left to the process exit
+    fn drop(&mut self) {
+        self.quit_tx.send_replace(());
+        let Some(handle) = self.join_handle.take() else {
+            return;
+        };
+        let started = Instant::now();
+        while !handle.is_finished() && started.elapsed() < LOGOUT_TIMEOUT {
+            std::thread::sleep(Duration::from_millis(10));
         }
     }
 }
@@ -0,0 +1,139 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    networking::{PlayerCommand, PlayerMessage},
+    state::{self, Message, State},
+};
+
+#[derive(Serialize, Deserialize)]
+pub enum RecordedEvent {
+    // Recorded without its chat, which comes in Chat events as it changes
+    State(State),
+    Command(PlayerCommand),
+    Message(PlayerMessage),
+    // Lines fetched since the last Chat event, from paging back and from new messages. Newer lines
+    // aren't always after every line recorded so far, see state::merge_chat.
+    Chat {
+        older: Vec<Message>,
+        newer: Vec<Message>,
+    },
+}
+
+// One line of a recording, timed from when recording started
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    pub at_ms: u64,
+    pub event: RecordedEvent,
+}
+
+impl Record {
+    pub fn at(&self) -> Duration {
+        Duration::from_millis(self.at_ms)
+    }
+}
+
+// Writes a session out as JSON lines as it is played
+pub struct Recorder {
+    file: BufWriter<File>,
+    started: Instant,
+    last_state: Option<State>,
+    // Every line recorded so far, which a state's chat is compared against
+    chat: Vec<Message>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            started: Instant::now(),
+            last_state: None,
+            chat: vec![],
+        })
+    }
+
+    pub fn record(&mut self, mut event: RecordedEvent) -> io::Result<()> {
+        // Writing the whole chat into every state would make recordings grow with the square of
+        // their length, so only the lines that weren't there last time are kept
+        if let RecordedEvent::State(state) = &mut event {
+            let chat = std::mem::take(&mut state.chat);
+            let first = self.chat.first().map(|m| m.message_id);
+            let (older, newer): (Vec<_>, Vec<_>) = chat
+                .iter()
+                .filter(|m| {
+                    self.chat
+                        .binary_search_by_key(&m.message_id, |r| r.message_id)
+                        .is_err()
+                })
+                .cloned()
+                .partition(|m| first.is_some_and(|first| m.message_id < first));
+            if !older.is_empty() || !newer.is_empty() {
+                self.chat = chat;
+                self.write(RecordedEvent::Chat { older, newer })?;
+            }
+        }
+        // The state is polled far more often than it changes, only keep the snapshots that differ
+        if let RecordedEvent::State(state) = &event
+            && self.last_state.as_ref() == Some(state)
+        {
+            return Ok(());
+        }
+        self.write(event)
+    }
+
+    fn write(&mut self, event: RecordedEvent) -> io::Result<()> {
+        let record = Record {
+            at_ms: self.started.elapsed().as_millis() as u64,
+            event,
+        };
+        serde_json::to_writer(&mut self.file, &record)?;
+        self.file.write_all(b"\n")?;
+        // Flushed every record so the file is still useful after a crash, which is when it's wanted most
+        self.file.flush()?;
+        if let RecordedEvent::State(state) = record.event {
+            self.last_state = Some(state);
+        }
+        Ok(())
+    }
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Record>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+// Puts the states of a recording back together with their chat as it is played
+#[derive(Default)]
+pub struct Playback {
+    chat: Vec<Message>,
+}
+
+impl Playback {
+    // Applies State and Chat events to what is shown, and hands back the player's own commands and
+    // messages for the caller to show however it likes
+    pub fn apply(&mut self, shown: &mut State, event: RecordedEvent) -> Option<RecordedEvent> {
+        match event {
+            RecordedEvent::State(mut state) => {
+                state.chat = self.chat.clone();
+                *shown = state;
+                None
+            }
+            RecordedEvent::Chat { older, newer } => {
+                self.chat.splice(0..0, older);
+                state::merge_chat(&mut self.chat, newer);
+                shown.chat = self.chat.clone();
+                None
+            }
+            event => Some(event),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::{borrow::Cow, fmt::Display, sync::LazyLock};
+
+use sqlx::{PgPool, migrate::Migrator};
+
+// Every migration in migrations/, which is also the schema the client's queries were checked
+// against, with the pg_cron statements below swapped for ones that work without it
+pub static MIGRATOR: LazyLock<Migrator> = LazyLock::new(|| {
+    let mut migrator = sqlx::migrate!();
+    migrator.migrations = migrator
+        .migrations
+        .iter()
+        .cloned()
+        .map(|mut migration| {
+            let up = migration.migration_type.is_up_migration();
+            for (version, _, original, replacement) in WITHOUT_PG_CRON
+                .iter()
+                .filter(|(version, is_up, _, _)| *version == migration.version && *is_up == up)
+            {
+                assert!(
+                    migration.sql.contains(original),
+                    "migration {} no longer has `{}`",
+                    version,
+                    original
+                );
+                migration.sql = Cow::Owned(migration.sql.replace(original, replacement));
+            }
+            migration
+        })
+        .collect::<Vec<_>>()
+        .into();
+    migrator
+});
+
+// Statements from migrations written when the game needed pg_cron, which fail on a Postgres
+// without it, and what runs in their place: (version, up, original, replacement). The migrations
+// keep the checksums of their files, so databases that ran the originals don't see them as changed.
+// pg_cron can only be created in the database it is configured for, so it is left out anywhere
+// else, such as the databases tests run in.
+const WITHOUT_PG_CRON: &[(i64, bool, &str, &str)] = &[
+    (
+        20250424233112,
+        true,
+        "CREATE EXTENSION pg_cron;",
+        "DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'pg_cron')
+    AND current_database() = COALESCE(current_setting('cron.database_name', true), 'postgres') THEN
+    CREATE EXTENSION pg_cron;
+  END IF;
+END;
+$$;",
+    ),
+    (
+        20250424233112,
+        true,
+        "SELECT cron.schedule('respond_messages', '1 seconds', 'SELECT respond_messages()');",
+        "DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.schedule('respond_messages', '1 seconds', 'SELECT respond_messages()');
+  END IF;
+END;
+$$;",
+    ),
+    (
+        20250424233112,
+        false,
+        "DROP EXTENSION pg_cron;",
+        "DROP EXTENSION IF EXISTS pg_cron;",
+    ),
+    (
+        20250514201500,
+        true,
+        "SELECT cron.schedule('prune_messages', '0 4 * * *', 'SELECT prune_messages()');",
+        "DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.schedule('prune_messages', '0 4 * * *', 'SELECT prune_messages()');
+  END IF;
+END;
+$$;",
+    ),
+    (
+        20250514201500,
+        false,
+        "SELECT cron.unschedule('prune_messages');",
+        "DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') THEN
+    PERFORM cron.unschedule('prune_messages');
+  END IF;
+END;
+$$;",
+    ),
+];
+
+// What api.version() returns in the schema this client was built against. Servers may run newer
+// migrations than the client knows about so long as they leave the api at this version.
+pub const API_VERSION: i32 = 5;
+
+pub enum SchemaError {
+    Database(sqlx::Error),
+    // Migrations the client expects that the database hasn't run
+    Behind(Vec<String>),
+    // Migrations the database has run that this client doesn't know about, and that changed the api
+    Ahead(Vec<i64>),
+    // Migrations whose contents changed after the database ran them
+    Modified(Vec<String>),
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::Database(e) => write!(f, "Could not read the schema version: {}", e),
+            SchemaError::Behind(missing) => write!(
+                f,
+                "The database is missing migrations ({}), run `mpdungeon2 migrate up` against it",
+                missing.join(", ")
+            ),
+            SchemaError::Ahead(unknown) => write!(
+                f,
+                "The database has migrations this client doesn't know about ({}), update the client",
+                unknown
+                    .iter()
+                    .map(i64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            SchemaError::Modified(modified) => write!(
+                f,
+                "Migrations were changed after the database ran them ({})",
+                modified.join(", ")
+            ),
+        }
+    }
+}
+
+impl From<sqlx::Error> for SchemaError {
+    fn from(e: sqlx::Error) -> Self {
+        SchemaError::Database(e)
+    }
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    // Applied, but from a different version of the file
+    pub modified: bool,
+}
+
+// Successfully applied migrations and their checksums, without creating the table sqlx keeps
+// them in, as players shouldn't be changing the schema just by logging in
+async fn applied_migrations(db: &PgPool) -> Result<Vec<(i64, Vec<u8>)>, sqlx::Error> {
+    // The game role can't read sqlx's table, only this function, which older schemas don't have
+    let (table, function): (bool, bool) = sqlx::query_as(
+        "SELECT to_regclass('_sqlx_migrations') IS NOT NULL, to_regprocedure('schema_migrations()') IS NOT NULL",
+    )
+    .fetch_one(db)
+    .await?;
+    let query = match (table, function) {
+        (_, true) => "SELECT version, checksum FROM schema_migrations()",
+        (true, false) => {
+            "SELECT version, checksum FROM _sqlx_migrations WHERE success ORDER BY version"
+        }
+        (false, false) => return Ok(vec![]),
+    };
+    sqlx::query_as(query).fetch_all(db).await
+}
+
+// None for schemas from before the api was versioned
+async fn api_version(db: &PgPool) -> Result<Option<i32>, sqlx::Error> {
+    let exists: bool = sqlx::query_scalar("SELECT to_regprocedure('api.version()') IS NOT NULL")
+        .fetch_one(db)
+        .await?;
+    if !exists {
+        return Ok(None);
+    }
+    sqlx::query_scalar("SELECT api.version()")
+        .fetch_one(db)
+        .await
+        .map(Some)
+}
+
+pub async fn status(db: &PgPool) -> Result<Vec<MigrationStatus>, sqlx::Error> {
+    let applied = applied_migrations(db).await?;
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|m| {
+            let checksum = applied
+                .iter()
+                .find(|(version, _)| *version == m.version)
+                .map(|(_, checksum)| checksum);
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: checksum.is_some(),
+                modified: checksum.is_some_and(|c| **c != *m.checksum),
+            }
+        })
+        .collect())
+}
+
+// Whether the database has the migrations this client was built with, and no later ones that
+// changed the api
+pub async fn check(db: &PgPool) -> Result<(), SchemaError> {
+    let statuses = status(db).await?;
+    let name = |s: &MigrationStatus| format!("{}_{}", s.version, s.description.replace(' ', "_"));
+    let missing = statuses
+        .iter()
+        .filter(|s| !s.applied)
+        .map(name)
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(SchemaError::Behind(missing));
+    }
+    let unknown = applied_migrations(db)
+        .await?
+        .into_iter()
+        .map(|(version, _)| version)
+        .filter(|version| !MIGRATOR.version_exists(*version))
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() && api_version(db).await? != Some(API_VERSION) {
+        return Err(SchemaError::Ahead(unknown));
+    }
+    let modified = statuses
+        .iter()
+        .filter(|s| s.modified)
+        .map(name)
+        .collect::<Vec<_>>();
+    if !modified.is_empty() {
+        return Err(SchemaError::Modified(modified));
+    }
+    Ok(())
+}
@@ -1,9 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct WorldEntity {
     pub entity_id: i32,
     pub x: i16,
     pub y: i16,
     pub room_id: i32,
     pub species: Option<String>,
+    pub name: Option<String>,
     pub command_type: Option<String>,
     pub command_x: Option<i16>,
     pub command_y: Option<i16>,
@@ -11,15 +16,69 @@ pub struct WorldEntity {
     pub maxhp: Option<i32>,
     pub ends: Option<Vec<i32>>,
     pub weight: Option<i32>,
+    pub open: Option<bool>,
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     pub sender: String,
-    pub receiver: String,
+    pub receiver: Option<String>,
+    pub channel: String,
     pub message: String,
+    pub sent_at: DateTime<Utc>,
+    // What chat is paged by, as lines can share a sent_at
+    pub message_id: i64,
+}
+
+// Adds the lines that aren't in `chat` yet where their message_id puts them, which is not always
+// the end: a line can be committed after lines with higher message_ids were already fetched
+pub fn merge_chat(chat: &mut Vec<Message>, lines: impl IntoIterator<Item = Message>) {
+    for line in lines {
+        if let Err(i) = chat.binary_search_by_key(&line.message_id, |m| m.message_id) {
+            chat.insert(i, line);
+        }
+    }
+}
+// The walls and floors of a room, one string per row as in rooms.terrain: '#' for a wall,
+// '+' for a floor and ' ' where there is nothing
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Terrain {
+    pub room_id: i32,
+    // How many times the room's terrain has changed, rooms.terrain_revision
+    pub revision: i32,
+    pub rows: Vec<String>,
 }
+
+impl Terrain {
+    pub fn get(&self, x: i16, y: i16) -> Option<char> {
+        let row = self.rows.get(usize::try_from(y).ok()?)?;
+        let c = *row.as_bytes().get(usize::try_from(x).ok()?)? as char;
+        (c != ' ').then_some(c)
+    }
+
+    pub fn is_wall(&self, x: i16, y: i16) -> bool {
+        self.get(x, y) == Some('#')
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (i16, i16, char)> + '_ {
+        self.rows.iter().enumerate().flat_map(|(y, row)| {
+            row.bytes()
+                .enumerate()
+                .filter(|(_, c)| *c != b' ')
+                .map(move |(x, c)| (x as i16, y as i16, c as char))
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct State {
     pub entities: Vec<WorldEntity>,
+    // The room the player is in, none until it has been fetched
+    pub terrain: Option<Terrain>,
     pub self_entity_id: Option<i32>,
     pub chat: Vec<Message>,
+    // Whether `chat` reaches back to the first message the player can see
+    pub chat_history_complete: bool,
+    // How many commands had reached the server when this state was fetched
+    pub commands_submitted: u64,
 }
@@ -0,0 +1,342 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::state::{Terrain, WorldEntity};
+
+// Everything paint_room_template can put down, one symbol each
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Wall,
+    Floor,
+    Door,
+    Upstair,
+    Downstair,
+    Spawn,
+}
+
+impl Tile {
+    pub const ALL: [Tile; 6] = [
+        Tile::Wall,
+        Tile::Floor,
+        Tile::Door,
+        Tile::Upstair,
+        Tile::Downstair,
+        Tile::Spawn,
+    ];
+
+    pub fn symbol(self) -> char {
+        match self {
+            Tile::Wall => '#',
+            Tile::Floor => '+',
+            Tile::Door => 'D',
+            Tile::Upstair => '<',
+            Tile::Downstair => '>',
+            Tile::Spawn => 'S',
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.symbol() == symbol)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tile::Wall => "wall",
+            Tile::Floor => "floor",
+            Tile::Door => "door",
+            Tile::Upstair => "upstair",
+            Tile::Downstair => "downstair",
+            Tile::Spawn => "spawn point",
+        }
+    }
+
+    // The legend layers paint_room_template puts down for the tile, see template_layer
+    pub fn layers(self) -> &'static [&'static str] {
+        match self {
+            Tile::Wall => &["wall impassible"],
+            Tile::Floor => &["floor"],
+            Tile::Door => &["floor", "door closed"],
+            Tile::Upstair => &["floor", "upstair"],
+            Tile::Downstair => &["floor", "downstair"],
+            Tile::Spawn => &["floor", "spawn=snake"],
+        }
+    }
+
+    // The species of what stands on the tile's floor, which is what the game draws
+    fn feature(self) -> Option<&'static str> {
+        match self {
+            Tile::Wall | Tile::Floor => None,
+            Tile::Door => Some("door"),
+            Tile::Upstair => Some("upstair"),
+            Tile::Downstair => Some("downstair"),
+            Tile::Spawn => Some("snake"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownSymbol {
+    pub symbol: char,
+    pub x: usize,
+    pub y: usize,
+}
+
+impl fmt::Display for UnknownSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown symbol {:?} at {}, {}",
+            self.symbol, self.x, self.y
+        )
+    }
+}
+
+// A room as the ASCII template paint_room_template takes, one tile per cell. Whatever else is on
+// a cell, such as a locked door or a portal, is kept as legend layers on top of its tile.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct RoomTemplate {
+    tiles: BTreeMap<(i16, i16), Tile>,
+    layers: BTreeMap<(i16, i16), Vec<String>>,
+}
+
+// Legend symbols for cells with layers, leaving out the editor's own
+const LEGEND_SYMBOLS: &str = "abcdefghijklmnopqrstuvwxyzABCEFGHIJKLMNOPQRTUVWXYZ0123456789";
+
+// The species a legend layer puts down, if it says one rather than taking a spawn template's
+fn layer_species(layer: &str) -> Option<&str> {
+    layer.split_whitespace().find(|word| {
+        !word.contains('=') && !["impassible", "monster", "open", "closed", "locked"].contains(word)
+    })
+}
+
+impl RoomTemplate {
+    // Reads a template the way paint_room_template does, skipping blank lines and spaces. Legend
+    // entries become the tile they start with and layers for the rest.
+    pub fn parse(template: &str) -> Result<Self, UnknownSymbol> {
+        let lines = template.split('\n').collect::<Vec<_>>();
+        let mut legend = BTreeMap::new();
+        let map = match lines.iter().position(|l| l.trim() == "---") {
+            Some(end) => {
+                for entry in &lines[..end] {
+                    if let Some((symbol, layers)) = entry.split_once('=') {
+                        let mut symbol = symbol.trim().chars();
+                        if let (Some(symbol), None) = (symbol.next(), symbol.next()) {
+                            let layers = layers.split(',').map(str::trim).collect::<Vec<_>>();
+                            legend.insert(symbol, layers);
+                        }
+                    }
+                }
+                &lines[end + 1..]
+            }
+            None => &lines[..],
+        };
+        let mut template = Self::default();
+        let lines = map.iter().filter(|l| !l.trim().is_empty());
+        for (y, line) in lines.enumerate() {
+            for (x, symbol) in line.chars().enumerate().filter(|(_, c)| *c != ' ') {
+                let (tile, layers) = match legend.get(&symbol) {
+                    // The tile with the most layers the entry starts with
+                    Some(layers) => match Tile::ALL
+                        .into_iter()
+                        .filter(|t| layers.starts_with(t.layers()))
+                        .max_by_key(|t| t.layers().len())
+                    {
+                        Some(tile) => (Some(tile), &layers[tile.layers().len()..]),
+                        None => (None, &layers[..]),
+                    },
+                    None => (
+                        Some(Tile::from_symbol(symbol).ok_or(UnknownSymbol { symbol, x, y })?),
+                        &[][..],
+                    ),
+                };
+                let at = (x as i16, y as i16);
+                if let Some(tile) = tile {
+                    template.tiles.insert(at, tile);
+                }
+                if !layers.is_empty() {
+                    template
+                        .layers
+                        .insert(at, layers.iter().map(|l| l.to_string()).collect());
+                }
+            }
+        }
+        Ok(template)
+    }
+
+    // Rebuilds the template of a room from the legend layers at each position, see admin.room_tiles
+    pub fn from_layers<'a>(entities: impl IntoIterator<Item = (i16, i16, &'a str)>) -> Self {
+        let mut template = Self::default();
+        for (x, y, layer) in entities {
+            let Some(tile) = Tile::ALL
+                .into_iter()
+                .find(|t| t.layers().last() == Some(&layer))
+            else {
+                template
+                    .layers
+                    .entry((x, y))
+                    .or_default()
+                    .push(layer.to_owned());
+                continue;
+            };
+            // Anything standing on the floor is what the tile is remembered as
+            template
+                .tiles
+                .entry((x, y))
+                .and_modify(|t| {
+                    if *t == Tile::Floor {
+                        *t = tile
+                    } else if tile != Tile::Floor {
+                        // A second feature on the same cell
+                        template
+                            .layers
+                            .entry((x, y))
+                            .or_default()
+                            .push(layer.to_owned());
+                    }
+                })
+                .or_insert(tile);
+        }
+        template
+    }
+
+    pub fn get(&self, x: i16, y: i16) -> Option<Tile> {
+        self.tiles.get(&(x, y)).copied()
+    }
+
+    // What else is on the cell besides its tile
+    pub fn layers(&self, x: i16, y: i16) -> &[String] {
+        self.layers.get(&(x, y)).map_or(&[], Vec::as_slice)
+    }
+
+    // Paints a tile, or clears the cell if None, along with any layers on it
+    pub fn set(&mut self, x: i16, y: i16, tile: Option<Tile>) {
+        self.layers.remove(&(x, y));
+        match tile {
+            Some(tile) => self.tiles.insert((x, y), tile),
+            None => self.tiles.remove(&(x, y)),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty() && self.layers.is_empty()
+    }
+
+    fn cells(&self) -> impl Iterator<Item = &(i16, i16)> {
+        self.tiles.keys().chain(self.layers.keys())
+    }
+
+    // The first row with nothing in it above the last row that has something. Blank lines are
+    // skipped when a template is read, so saving one would move everything below it up a row.
+    pub fn blank_row(&self) -> Option<i16> {
+        let height = self.cells().map(|(_, y)| y + 1).max()?;
+        (0..height).find(|y| !self.cells().any(|(_, ty)| ty == y))
+    }
+
+    // The walls and floors the room's terrain would be, every other tile being floor underneath
+    pub fn terrain(&self) -> Terrain {
+        let rows = self.rows(|x, y| match self.get(x, y) {
+            Some(Tile::Wall) => '#',
+            Some(_) => '+',
+            None => ' ',
+        });
+        Terrain {
+            room_id: 0,
+            revision: 0,
+            rows,
+        }
+    }
+
+    // Each row from the top, as far as its last cell
+    fn rows(&self, symbol: impl Fn(i16, i16) -> char) -> Vec<String> {
+        let height = self.cells().map(|(_, y)| y + 1).max().unwrap_or(0);
+        (0..height)
+            .map(|y| {
+                let width = self
+                    .cells()
+                    .filter(|(_, ty)| *ty == y)
+                    .map(|(x, _)| x + 1)
+                    .max()
+                    .unwrap_or(0);
+                (0..width).map(|x| symbol(x, y)).collect()
+            })
+            .collect()
+    }
+
+    // What stands on the room's floor, as entities of room 0 with made up ids
+    pub fn entities(&self) -> Vec<WorldEntity> {
+        let entity = |entity_id, x, y, species: &str| WorldEntity {
+            entity_id,
+            x,
+            y,
+            room_id: 0,
+            species: Some(species.to_owned()),
+            name: None,
+            command_type: None,
+            command_x: None,
+            command_y: None,
+            hp: None,
+            maxhp: None,
+            ends: None,
+            weight: None,
+            open: None,
+        };
+        let mut entities = vec![];
+        for (&(x, y), tile) in &self.tiles {
+            if let Some(feature) = tile.feature() {
+                entities.push(entity(entities.len() as i32, x, y, feature));
+            }
+        }
+        for (&(x, y), layers) in &self.layers {
+            for layer in layers {
+                if let Some(species) = layer_species(layer) {
+                    let mut e = entity(entities.len() as i32, x, y, species);
+                    e.open = match layer.split_whitespace().any(|w| w == "open") {
+                        true => Some(true),
+                        false => layer
+                            .split_whitespace()
+                            .any(|w| w == "closed" || w == "locked")
+                            .then_some(false),
+                    };
+                    entities.push(e);
+                }
+            }
+        }
+        entities
+    }
+}
+
+impl fmt::Display for RoomTemplate {
+    // Lines are taken to start at x = 0, the same as paint_room_template reads them. Cells with
+    // layers get a legend entry, shared by cells with the same tile and layers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut legend: Vec<Vec<String>> = vec![];
+        let mut symbols = BTreeMap::new();
+        for (&at, layers) in &self.layers {
+            let entry = self
+                .tiles
+                .get(&at)
+                .map_or(&[][..], |t| t.layers())
+                .iter()
+                .map(|l| l.to_string())
+                .chain(layers.iter().cloned())
+                .collect::<Vec<_>>();
+            let index = legend.iter().position(|e| *e == entry).unwrap_or_else(|| {
+                legend.push(entry);
+                legend.len() - 1
+            });
+            symbols.insert(at, LEGEND_SYMBOLS.chars().nth(index).ok_or(fmt::Error)?);
+        }
+        for (entry, symbol) in legend.iter().zip(LEGEND_SYMBOLS.chars()) {
+            writeln!(f, "{} = {}", symbol, entry.join(", "))?;
+        }
+        if !legend.is_empty() {
+            writeln!(f, "---")?;
+        }
+        for row in self.rows(|x, y| match symbols.get(&(x, y)) {
+            Some(&symbol) => symbol,
+            None => self.get(x, y).map_or(' ', Tile::symbol),
+        }) {
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::*;
+use sqlx::PgPool;
+
+const ROOM: &str = "
+#####
+#+++#
+#####
+";
+
+async fn audited(db: &PgPool) -> Vec<String> {
+    sqlx::query_scalar("SELECT action FROM admin.audit_log ORDER BY id")
+        .fetch_all(db)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn teleports_and_forgets_the_command(db: PgPool) {
+    let here = room(&db, Some(2), ROOM).await;
+    let there = room(&db, Some(2), ROOM).await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+    command(&db, alice, "move", Some((1, 0)), None).await;
+
+    sqlx::query("SELECT admin.teleport($1, $2, 3::smallint, 1::smallint)")
+        .bind(alice)
+        .bind(there)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    assert_eq!(position(&db, alice).await, (3, 1, there));
+    assert!(!has_command(&db, alice).await);
+    assert_eq!(audited(&db).await, ["teleport"]);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn spawns_from_templates(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+
+    let snake: i32 =
+        sqlx::query_scalar("SELECT admin.spawn('snake', $1, 2::smallint, 1::smallint)")
+            .bind(room)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+    assert_eq!(position(&db, snake).await, (2, 1, room));
+    assert_eq!(hp(&db, snake).await, 5);
+    let monster: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM monsters WHERE entity_id=$1)")
+            .bind(snake)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert!(monster);
+    assert!(
+        sqlx::query("SELECT admin.spawn('dragon', $1, 2::smallint, 1::smallint)")
+            .bind(room)
+            .execute(&db)
+            .await
+            .is_err()
+    );
+    assert_eq!(audited(&db).await, ["spawn"]);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn sets_hp_and_kicks(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+
+    sqlx::query("SELECT admin.set_hp($1, 3)")
+        .bind(alice)
+        .execute(&db)
+        .await
+        .unwrap();
+    let kicked: bool = sqlx::query_scalar("SELECT admin.kick($1)")
+        .bind(alice)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    let kicked_again: bool = sqlx::query_scalar("SELECT admin.kick($1)")
+        .bind(alice)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    assert_eq!(hp(&db, alice).await, 3);
+    assert!(kicked);
+    assert!(!kicked_again);
+    assert_eq!(audited(&db).await, ["set_hp", "kick"]);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn players_cannot_use_the_console(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let mut tx = db.begin().await.unwrap();
+    sqlx::query("SET LOCAL ROLE mpd_player")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+    let healed = sqlx::query("SELECT admin.set_hp($1, 1000)")
+        .bind(alice)
+        .execute(&mut *tx)
+        .await;
+
+    assert!(healed.is_err());
+}
@@ -0,0 +1,78 @@
+use mpdungeon2::chat::{ChatCommand, ChatError, parse};
+
+#[test]
+fn blank_lines_are_nothing() {
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("   "), None);
+}
+
+#[test]
+fn plain_text_is_said_to_the_room() {
+    assert_eq!(
+        parse("  hello there "),
+        Some(Ok(ChatCommand::Say("hello there".to_owned())))
+    );
+}
+
+#[test]
+fn commands_and_their_short_forms() {
+    let say = Some(Ok(ChatCommand::Say("hi".to_owned())));
+    assert_eq!(parse("/say hi"), say);
+    assert_eq!(parse("/s   hi"), say);
+    assert_eq!(
+        parse("/shout over here"),
+        Some(Ok(ChatCommand::Shout("over here".to_owned())))
+    );
+    assert_eq!(parse("/who"), Some(Ok(ChatCommand::Who)));
+    assert_eq!(parse("/help"), Some(Ok(ChatCommand::Help)));
+    assert_eq!(parse("/?"), Some(Ok(ChatCommand::Help)));
+    assert_eq!(parse("/quit"), Some(Ok(ChatCommand::Quit)));
+    assert_eq!(parse("/q"), Some(Ok(ChatCommand::Quit)));
+}
+
+#[test]
+fn tells_take_a_name_then_the_message() {
+    let tell = Some(Ok(ChatCommand::Tell {
+        recipient: "bob".to_owned(),
+        message: "meet me  downstairs".to_owned(),
+    }));
+    assert_eq!(parse("/tell bob meet me  downstairs"), tell);
+    assert_eq!(parse("/t bob   meet me  downstairs"), tell);
+}
+
+#[test]
+fn malformed_tells_say_how_to_use_them() {
+    let usage = Some(Err(ChatError::MissingArgument("tell", "<name> <message>")));
+    assert_eq!(parse("/tell"), usage);
+    assert_eq!(parse("/tell bob"), usage);
+    assert_eq!(parse("/tell bob   "), usage);
+    assert_eq!(
+        parse("/tell").unwrap().unwrap_err().to_string(),
+        "Usage: /tell <name> <message>"
+    );
+}
+
+#[test]
+fn commands_missing_their_message_are_errors() {
+    assert_eq!(
+        parse("/say"),
+        Some(Err(ChatError::MissingArgument("say", "<message>")))
+    );
+    assert_eq!(
+        parse("/shout "),
+        Some(Err(ChatError::MissingArgument("shout", "<message>")))
+    );
+}
+
+#[test]
+fn unknown_commands_are_errors() {
+    assert_eq!(
+        parse("/dance wildly"),
+        Some(Err(ChatError::UnknownCommand("dance".to_owned())))
+    );
+    // Commands are case sensitive, like the rest of the keys
+    assert_eq!(
+        parse("/SAY hi"),
+        Some(Err(ChatError::UnknownCommand("SAY".to_owned())))
+    );
+}
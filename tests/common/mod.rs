@@ -0,0 +1,162 @@
+// Builds small worlds inside the database a test was given. Every room is created fresh, so the
+// seed data the migrations add never gets in the way.
+//
+// sqlx::test makes a new database per test from DATABASE_URL and runs schema::MIGRATOR in it, so
+// any Postgres the URL's user can create databases on will do. No extensions are needed.
+#![allow(dead_code)]
+
+use sqlx::PgPool;
+
+pub async fn room(db: &PgPool, min_commands: Option<i32>, template: &str) -> i32 {
+    let room_id: i32 = sqlx::query_scalar(
+        "INSERT INTO rooms (min_commands, landing_zone) VALUES ($1, false) RETURNING entity_id",
+    )
+    .bind(min_commands)
+    .fetch_one(db)
+    .await
+    .unwrap();
+    sqlx::query("SELECT create_room_template($1, $2)")
+        .bind(room_id)
+        .bind(template)
+        .execute(db)
+        .await
+        .unwrap();
+    room_id
+}
+
+// Registers and logs in a player the way the client does and puts them at x, y in the room
+pub async fn player(db: &PgPool, name: &str, room_id: i32, x: i16, y: i16) -> i32 {
+    let entity_id = sqlx::query_file!("sql/register.sql", name, "password")
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .entity_id;
+    place(db, entity_id, room_id, x, y).await;
+    entity_id
+}
+
+pub async fn monster(db: &PgPool, species: &str, hp: i32, room_id: i32, x: i16, y: i16) -> i32 {
+    let entity_id = thing(db, species, room_id, x, y).await;
+    sqlx::query("INSERT INTO hps (entity_id, hp, maxhp) VALUES ($1, $2, $2)")
+        .bind(entity_id)
+        .bind(hp)
+        .execute(db)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO monsters (entity_id) VALUES ($1)")
+        .bind(entity_id)
+        .execute(db)
+        .await
+        .unwrap();
+    entity_id
+}
+
+pub async fn thing(db: &PgPool, species: &str, room_id: i32, x: i16, y: i16) -> i32 {
+    let entity_id: i32 =
+        sqlx::query_scalar("INSERT INTO species (species) VALUES ($1) RETURNING entity_id")
+            .bind(species)
+            .fetch_one(db)
+            .await
+            .unwrap();
+    sqlx::query("INSERT INTO positions (entity_id, x, y, room_id) VALUES ($1, $2, $3, $4)")
+        .bind(entity_id)
+        .bind(x)
+        .bind(y)
+        .bind(room_id)
+        .execute(db)
+        .await
+        .unwrap();
+    entity_id
+}
+
+pub async fn portal(db: &PgPool, start: i32, end: i32) {
+    sqlx::query("INSERT INTO portals (start_entity_id, end_entity_id) VALUES ($1, $2)")
+        .bind(start)
+        .bind(end)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+pub async fn place(db: &PgPool, entity_id: i32, room_id: i32, x: i16, y: i16) {
+    sqlx::query("UPDATE positions SET x=$2, y=$3, room_id=$4 WHERE entity_id=$1")
+        .bind(entity_id)
+        .bind(x)
+        .bind(y)
+        .bind(room_id)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+// Submits a command through the same query as the client, which sets off room_tick
+pub async fn command(
+    db: &PgPool,
+    entity_id: i32,
+    command_type: &str,
+    delta: Option<(i16, i16)>,
+    target: Option<i32>,
+) {
+    let token = token(db, entity_id).await;
+    sqlx::query_file!(
+        "sql/insert_command.sql",
+        token,
+        command_type,
+        delta.map(|d| d.0),
+        delta.map(|d| d.1),
+        target
+    )
+    .fetch_one(db)
+    .await
+    .unwrap();
+}
+
+// Says something through the same query as the client, false if no one heard it
+pub async fn say(
+    db: &PgPool,
+    speaker: i32,
+    channel: &str,
+    recipient: Option<&str>,
+    message: &str,
+) -> bool {
+    let token = token(db, speaker).await;
+    sqlx::query_file!("sql/say.sql", token, channel, recipient, message)
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .delivered
+}
+
+// The current session's token, which everything the client does goes through
+pub async fn token(db: &PgPool, entity_id: i32) -> String {
+    sqlx::query_scalar("SELECT token FROM sessions WHERE entity_id=$1")
+        .bind(entity_id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+// x, y and room_id
+pub async fn position(db: &PgPool, entity_id: i32) -> (i16, i16, i32) {
+    sqlx::query_as("SELECT x, y, room_id FROM positions WHERE entity_id=$1")
+        .bind(entity_id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+pub async fn hp(db: &PgPool, entity_id: i32) -> i32 {
+    sqlx::query_scalar("SELECT hp FROM hps WHERE entity_id=$1")
+        .bind(entity_id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+pub async fn has_command(db: &PgPool, entity_id: i32) -> bool {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM commands WHERE entity_id=$1)")
+        .bind(entity_id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
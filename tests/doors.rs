@@ -0,0 +1,109 @@
+mod common;
+
+use common::*;
+use sqlx::PgPool;
+
+const HALL: &str = "
+D = floor, door closed
+L = floor, door lock=cellar
+k = floor, key key=cellar weight=1
+---
+#####
+#+k+#
+#+++#
+##D##
+#+++#
+##L##
+#+++#
+#####
+";
+
+async fn door_at(db: &PgPool, room: i32, x: i16, y: i16) -> i32 {
+    sqlx::query_scalar(
+        "SELECT d.entity_id FROM doors d
+        INNER JOIN positions p ON p.entity_id=d.entity_id
+        WHERE p.room_id=$1 AND p.x=$2 AND p.y=$3",
+    )
+    .bind(room)
+    .bind(x)
+    .bind(y)
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+// Whether the door is open and whether it is locked
+async fn state(db: &PgPool, door: i32) -> (bool, bool) {
+    sqlx::query_as("SELECT open, locked FROM doors WHERE entity_id=$1")
+        .bind(door)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn closed_doors_block_until_opened(db: PgPool) {
+    let hall = room(&db, Some(1), HALL).await;
+    let door = door_at(&db, hall, 2, 3).await;
+    let alice = player(&db, "alice", hall, 2, 2).await;
+
+    command(&db, alice, "move", Some((0, 1)), None).await;
+    assert_eq!(position(&db, alice).await, (2, 2, hall));
+
+    command(&db, alice, "open", None, Some(door)).await;
+    assert_eq!(state(&db, door).await, (true, false));
+    let seen: Option<bool> =
+        sqlx::query_scalar("SELECT open FROM api.snapshot($1) WHERE entity_id=$2")
+            .bind(token(&db, alice).await)
+            .bind(door)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(seen, Some(true));
+
+    command(&db, alice, "move", Some((0, 1)), None).await;
+    assert_eq!(position(&db, alice).await, (2, 3, hall));
+
+    // Nothing closes on someone standing in the doorway
+    let bob = player(&db, "bob", hall, 2, 2).await;
+    command(&db, bob, "close", None, Some(door)).await;
+    assert_eq!(state(&db, door).await, (true, false));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn doors_are_opened_from_next_to_them(db: PgPool) {
+    let hall = room(&db, Some(1), HALL).await;
+    let door = door_at(&db, hall, 2, 3).await;
+    let alice = player(&db, "alice", hall, 2, 1).await;
+
+    command(&db, alice, "open", None, Some(door)).await;
+
+    assert_eq!(state(&db, door).await, (false, false));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn locked_doors_open_for_their_key(db: PgPool) {
+    let hall = room(&db, Some(1), HALL).await;
+    let door = door_at(&db, hall, 2, 5).await;
+    let alice = player(&db, "alice", hall, 2, 4).await;
+
+    command(&db, alice, "open", None, Some(door)).await;
+    assert_eq!(state(&db, door).await, (false, true));
+
+    let key: i32 = sqlx::query_scalar("SELECT entity_id FROM keys WHERE lock='cellar'")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    place(&db, key, alice, 0, 0).await;
+    command(&db, alice, "open", None, Some(door)).await;
+    assert_eq!(state(&db, door).await, (true, false));
+    command(&db, alice, "move", Some((0, 1)), None).await;
+    assert_eq!(position(&db, alice).await, (2, 5, hall));
+
+    // Closing it with the key locks it behind you
+    command(&db, alice, "move", Some((0, 1)), None).await;
+    command(&db, alice, "close", None, Some(door)).await;
+    assert_eq!(state(&db, door).await, (false, true));
+    command(&db, alice, "move", Some((0, -1)), None).await;
+    assert_eq!(position(&db, alice).await, (2, 6, hall));
+}
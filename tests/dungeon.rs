@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use mpdungeon2::dungeon::{self, Cell, Grid, LevelType, Options};
+use sqlx::PgPool;
+
+const LEVEL_TYPES: [LevelType; 3] = [LevelType::Bsp, LevelType::Cave, LevelType::Maze];
+
+// No floor on the outermost tiles, and nothing but wall or floor around any floor
+fn walled_in(grid: &Grid) -> bool {
+    grid.tiles()
+        .filter(|(_, _, cell)| *cell == Cell::Floor)
+        .all(|(x, y, _)| {
+            let (x, y) = (x as i32, y as i32);
+            x > 0
+                && y > 0
+                && x < grid.width() - 1
+                && y < grid.height() - 1
+                && (-1..=1).all(|dy| (-1..=1).all(|dx| grid.get(x + dx, y + dy) != Cell::Empty))
+        })
+}
+
+#[test]
+fn generated_levels_are_connected_and_walled_in() {
+    for level_type in LEVEL_TYPES {
+        for seed in 1..=20 {
+            let grid =
+                dungeon::generate(level_type, 61, 31, seed, &Options::default(), &[]).unwrap();
+            assert_eq!(
+                grid.regions().len(),
+                1,
+                "{:?} seed {}:\n{}",
+                level_type,
+                seed,
+                grid
+            );
+            assert!(
+                walled_in(&grid),
+                "{:?} seed {}:\n{}",
+                level_type,
+                seed,
+                grid
+            );
+        }
+    }
+}
+
+#[test]
+fn loops_do_not_disconnect_rooms() {
+    for seed in 1..=20 {
+        let options = Options {
+            room_count: Some(8),
+            max_room_size: Some(10),
+            loop_fraction: Some(1.0),
+            ..Options::default()
+        };
+        let grid = dungeon::generate(LevelType::Bsp, 60, 30, seed, &options, &[]).unwrap();
+        assert_eq!(grid.regions().len(), 1, "seed {}:\n{}", seed, grid);
+    }
+}
+
+#[test]
+fn generated_levels_can_be_made_again_from_their_seed() {
+    for level_type in LEVEL_TYPES {
+        let first = dungeon::generate(level_type, 41, 21, 7, &Options::default(), &[]).unwrap();
+        let again = dungeon::generate(level_type, 41, 21, 7, &Options::default(), &[]).unwrap();
+        let other = dungeon::generate(level_type, 41, 21, 8, &Options::default(), &[]).unwrap();
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+    }
+}
+
+#[test]
+fn mazes_too_small_to_have_passages_are_empty() {
+    let grid = dungeon::generate(LevelType::Maze, 2, 10, 1, &Options::default(), &[]).unwrap();
+    assert_eq!(grid.tiles().count(), 0);
+    assert_eq!(grid.regions().len(), 0);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn levels_are_inserted_as_terrain(db: PgPool) {
+    let options = Options {
+        fill: Some(0.4),
+        ..Options::default()
+    };
+    let grid = dungeon::generate(LevelType::Cave, 40, 20, 3, &options, &[]).unwrap();
+    let room_id = dungeon::insert(&db, LevelType::Cave, 3, &options, &grid)
+        .await
+        .unwrap();
+
+    let terrain: Vec<String> = sqlx::query_scalar("SELECT terrain FROM rooms WHERE entity_id=$1")
+        .bind(room_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(terrain.join("\n"), grid.to_string());
+    let entities: i64 = sqlx::query_scalar("SELECT count(*) FROM positions WHERE room_id=$1")
+        .bind(room_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(entities, 0);
+
+    // Read back tile by tile the way the game sees them
+    let tiles: Vec<(i16, i16, String)> = sqlx::query_as(
+        "SELECT t.x, t.y, t.species FROM rooms r, terrain_tiles(r.terrain) t WHERE r.entity_id=$1",
+    )
+    .bind(room_id)
+    .fetch_all(&db)
+    .await
+    .unwrap();
+    let tiles = tiles
+        .into_iter()
+        .map(|(x, y, species)| ((x, y), species))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(tiles.len(), grid.tiles().count());
+    for (x, y, cell) in grid.tiles() {
+        assert_eq!(Some(tiles[&(x, y)].as_str()), cell.species());
+        let blocked: bool = sqlx::query_scalar("SELECT blocked($1, $2, $3)")
+            .bind(room_id)
+            .bind(x as i32)
+            .bind(y as i32)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(blocked, cell == Cell::Wall);
+    }
+
+    let (seed, level_type, generator): (i64, String, serde_json::Value) =
+        sqlx::query_as("SELECT seed, level_type, generator FROM rooms WHERE entity_id=$1")
+            .bind(room_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!((seed, level_type.as_str()), (3, "cave"));
+    let stored: Options = serde_json::from_value(generator).unwrap();
+    assert_eq!(stored, options);
+    assert_eq!(
+        dungeon::generate(LevelType::Cave, 40, 20, seed, &stored, &[]).unwrap(),
+        grid
+    );
+
+    // The database's generators would make something else of the seed
+    let regenerated = sqlx::query("SELECT template FROM regenerate_dungeon($1)")
+        .bind(room_id)
+        .fetch_optional(&db)
+        .await;
+    assert!(regenerated.is_err());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn vaults_are_stamped_into_open_space(db: PgPool) {
+    let vaults = dungeon::load_vaults(&db).await.unwrap();
+    let shrine = vaults
+        .into_iter()
+        .filter(|v| v.name == "shrine")
+        .collect::<Vec<_>>();
+    let options = Options {
+        vaults: Some(1),
+        ..Options::default()
+    };
+    let grid = dungeon::generate(LevelType::Cave, 60, 30, 2, &options, &shrine).unwrap();
+
+    assert_eq!(grid.regions().len(), 1, "{}", grid);
+    assert!(
+        grid.to_string()
+            .starts_with("$ = floor, gold weight=1\n---\n")
+    );
+    let room_id = dungeon::insert(&db, LevelType::Cave, 2, &options, &grid)
+        .await
+        .unwrap();
+    let gold: Vec<(i16, i16)> = sqlx::query_as(
+        "SELECT p.x, p.y FROM positions p
+        INNER JOIN species s ON s.entity_id=p.entity_id
+        WHERE p.room_id=$1 AND s.species='gold'",
+    )
+    .bind(room_id)
+    .fetch_all(&db)
+    .await
+    .unwrap();
+    assert_eq!(gold.len(), 1);
+    let (x, y) = gold[0];
+    assert_eq!(grid.feature(x as i32, y as i32), Some('$'));
+
+    // No room for a vault in a maze
+    let maze = dungeon::generate(LevelType::Maze, 21, 11, 2, &options, &shrine).unwrap();
+    assert_eq!(maze.vault_template(), None);
+}
+
+#[test]
+fn vaults_have_to_agree_on_their_legends() {
+    let a = dungeon::Vault::parse("a", "x = floor, gold\n---\nx").unwrap();
+    let b = dungeon::Vault::parse("b", "x = floor, rat\n---\nx").unwrap();
+    let options = Options {
+        vaults: Some(10),
+        ..Options::default()
+    };
+    let stamped = dungeon::generate(LevelType::Cave, 60, 30, 1, &options, &[a, b]);
+    assert!(stamped.is_err());
+    assert!(dungeon::Vault::parse("c", "#?#").is_err());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn generated_rooms_are_made_again_by_the_generator_that_made_them(db: PgPool) {
+    let options = Options {
+        room_count: Some(4),
+        vaults: Some(2),
+        ..Options::default()
+    };
+    let vaults = dungeon::load_vaults(&db).await.unwrap();
+    let grid = dungeon::generate(LevelType::Bsp, 60, 30, 11, &options, &vaults).unwrap();
+    let rust_room = dungeon::insert(&db, LevelType::Bsp, 11, &options, &grid)
+        .await
+        .unwrap();
+    assert_eq!(
+        dungeon::regenerate(&db, rust_room).await.unwrap(),
+        Some(grid.to_string())
+    );
+
+    let (sql_room, template): (i32, String) =
+        sqlx::query_as("SELECT room_id, template FROM generate_level('cave', 50, 25, 3)")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(
+        dungeon::regenerate(&db, sql_room).await.unwrap(),
+        Some(template)
+    );
+
+    let tavern: i32 = sqlx::query_scalar("SELECT entity_id FROM rooms WHERE landing_zone")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(dungeon::regenerate(&db, tavern).await.unwrap(), None);
+}
@@ -0,0 +1,172 @@
+use mpdungeon2::{
+    engine::World,
+    networking::{Channel, PlayerCommand, PlayerMessage},
+    rng::Rng,
+    state::WorldEntity,
+};
+
+fn world() -> World {
+    World::seeded(&mut Rng::new(1))
+}
+
+fn command(world: &mut World, entity_id: i32, command_type: &str, x: i16, y: i16, target: i32) {
+    world.insert_command(PlayerCommand {
+        entity_id,
+        command_type: command_type.to_owned(),
+        x: Some(x),
+        y: Some(y),
+        command_target: Some(target),
+    });
+}
+
+fn step(world: &mut World, entity_id: i32, x: i16, y: i16) {
+    command(world, entity_id, "move", x, y, 0);
+}
+
+fn me(world: &World, entity_id: i32) -> WorldEntity {
+    world
+        .entities(entity_id)
+        .into_iter()
+        .find(|e| e.entity_id == entity_id)
+        .unwrap()
+}
+
+fn position(world: &World, entity_id: i32) -> (i16, i16, i32) {
+    let e = me(world, entity_id);
+    (e.x, e.y, e.room_id)
+}
+
+fn find(world: &World, seen_by: i32, species: &str, x: i16, y: i16) -> WorldEntity {
+    world
+        .entities(seen_by)
+        .into_iter()
+        .find(|e| e.species.as_deref() == Some(species) && (e.x, e.y) == (x, y))
+        .unwrap()
+}
+
+// Takes the player down the tavern's doors to the dungeon, which waits for everyone in it
+fn go_down(world: &mut World, entity_id: i32) {
+    let door = find(world, entity_id, "door", 4, 6).entity_id;
+    command(world, entity_id, "travel", 0, 0, door);
+}
+
+#[test]
+fn players_start_in_the_landing_zone() {
+    let mut world = world();
+    let alice = world.login("alice", "secret").unwrap();
+    let tavern = position(&world, alice).2;
+    assert_eq!(position(&world, alice), (1, 1, tavern));
+    assert_eq!(world.login("alice", "wrong"), None);
+    assert_eq!(world.login("alice", "secret"), Some(alice));
+    assert_eq!(world.terrain(alice).unwrap().room_id, tavern);
+}
+
+#[test]
+fn walls_stop_moves() {
+    let mut world = world();
+    let alice = world.login("alice", "").unwrap();
+    let tavern = position(&world, alice).2;
+
+    step(&mut world, alice, -1, 0);
+    assert_eq!(position(&world, alice), (1, 1, tavern));
+    step(&mut world, alice, -1, -1);
+    assert_eq!(position(&world, alice), (1, 1, tavern));
+    step(&mut world, alice, 1, 1);
+    assert_eq!(position(&world, alice), (2, 2, tavern));
+}
+
+#[test]
+fn players_can_share_a_tile() {
+    let mut world = world();
+    let alice = world.login("alice", "").unwrap();
+    let bob = world.login("bob", "").unwrap();
+
+    step(&mut world, alice, 1, 0);
+    step(&mut world, bob, 1, 0);
+    assert_eq!(position(&world, alice), position(&world, bob));
+}
+
+#[test]
+fn rooms_without_min_commands_wait_for_everyone() {
+    let mut world = world();
+    let alice = world.login("alice", "").unwrap();
+    let bob = world.login("bob", "").unwrap();
+    go_down(&mut world, alice);
+    go_down(&mut world, bob);
+    let dungeon = position(&world, alice).2;
+    assert_eq!(position(&world, bob), (1, 1, dungeon));
+
+    step(&mut world, alice, 1, 0);
+    assert_eq!(position(&world, alice), (1, 1, dungeon));
+    assert_eq!(me(&world, alice).command_type.as_deref(), Some("move"));
+
+    // A second command replaces the first until the room ticks
+    step(&mut world, alice, 0, 1);
+    step(&mut world, bob, 1, 0);
+    assert_eq!(position(&world, alice), (1, 2, dungeon));
+    assert_eq!(position(&world, bob), (2, 1, dungeon));
+    assert_eq!(me(&world, alice).command_type, None);
+}
+
+#[test]
+fn attackers_in_the_same_tick_take_one_hp() {
+    let mut world = world();
+    let alice = world.login("alice", "").unwrap();
+    let bob = world.login("bob", "").unwrap();
+    go_down(&mut world, alice);
+    go_down(&mut world, bob);
+    let snake = find(&world, alice, "snake", 3, 5).entity_id;
+
+    command(&mut world, alice, "attack", 0, 0, snake);
+    command(&mut world, bob, "attack", 0, 0, snake);
+
+    let snake = world
+        .entities(alice)
+        .into_iter()
+        .find(|e| e.entity_id == snake)
+        .unwrap();
+    assert_eq!(snake.hp, Some(4));
+    // The snake moved towards the nearest of them while it was being hit
+    assert_ne!((snake.x, snake.y), (3, 5));
+}
+
+#[test]
+fn each_attack_takes_an_hp() {
+    let mut world = world();
+    let alice = world.login("alice", "").unwrap();
+    let innkeeper = find(&world, alice, "innkeeper", 4, 1).entity_id;
+    step(&mut world, alice, 1, 0);
+    for _ in 0..10 {
+        command(&mut world, alice, "attack", 0, 0, innkeeper);
+    }
+    let innkeeper = world
+        .entities(alice)
+        .into_iter()
+        .find(|e| e.entity_id == innkeeper)
+        .unwrap();
+    assert_eq!(innkeeper.hp, Some(0));
+}
+
+#[test]
+fn tells_prefer_players_to_what_shares_their_name() {
+    let mut world = world();
+    let alice = world.login("alice", "").unwrap();
+    let innkeeper = world.login("innkeeper", "").unwrap();
+    let tell = |world: &mut World, recipient: &str| {
+        world.say(&PlayerMessage {
+            speaker: alice,
+            channel: Channel::Direct(recipient.to_owned()),
+            message: "hello".to_owned(),
+        })
+    };
+
+    assert_eq!(tell(&mut world, "Innkeeper"), Ok(true));
+    assert_eq!(tell(&mut world, "nobody"), Ok(false));
+    assert_eq!(tell(&mut world, "tavern"), Ok(false));
+    let told = world
+        .chat(innkeeper)
+        .into_iter()
+        .filter(|m| m.channel == "direct")
+        .count();
+    assert_eq!(told, 1);
+}
@@ -0,0 +1,111 @@
+use sqlx::PgPool;
+
+async fn runs(db: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM job_runs")
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+async fn run_due_jobs(db: &PgPool) -> i32 {
+    sqlx::query_scalar("SELECT run_due_jobs()")
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+async fn schedule(db: &PgPool, name: &str, every: &str, command: &str) {
+    sqlx::query("SELECT schedule_job($1, '* * * * *', $2::interval, $3)")
+        .bind(name)
+        .bind(every)
+        .bind(command)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn runs_jobs_when_they_are_due(db: PgPool) {
+    sqlx::query("CREATE TABLE job_runs (ran_at TIMESTAMPTZ DEFAULT NOW())")
+        .execute(&db)
+        .await
+        .unwrap();
+    // Only what the test schedules, not the game's own jobs
+    sqlx::query("DELETE FROM jobs").execute(&db).await.unwrap();
+    schedule(
+        &db,
+        "count",
+        "1 hour",
+        "INSERT INTO job_runs DEFAULT VALUES",
+    )
+    .await;
+
+    assert_eq!(run_due_jobs(&db).await, 1);
+    assert_eq!(run_due_jobs(&db).await, 0);
+    assert_eq!(runs(&db).await, 1);
+
+    sqlx::query("UPDATE jobs SET last_run = NOW() - INTERVAL '2 hours'")
+        .execute(&db)
+        .await
+        .unwrap();
+    assert_eq!(run_due_jobs(&db).await, 1);
+    assert_eq!(runs(&db).await, 2);
+
+    sqlx::query("SELECT unschedule_job('count')")
+        .execute(&db)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE jobs SET last_run = NULL")
+        .execute(&db)
+        .await
+        .unwrap();
+    assert_eq!(run_due_jobs(&db).await, 0);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn failing_jobs_dont_stop_the_others(db: PgPool) {
+    sqlx::query("CREATE TABLE job_runs (ran_at TIMESTAMPTZ DEFAULT NOW())")
+        .execute(&db)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM jobs").execute(&db).await.unwrap();
+    schedule(&db, "broken", "1 hour", "SELECT 1/0").await;
+    schedule(
+        &db,
+        "count",
+        "1 hour",
+        "INSERT INTO job_runs DEFAULT VALUES",
+    )
+    .await;
+
+    assert_eq!(run_due_jobs(&db).await, 2);
+    assert_eq!(runs(&db).await, 1);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn only_one_runner_runs_jobs_at_a_time(db: PgPool) {
+    // Another runner part way through its jobs
+    let mut runner = db.acquire().await.unwrap();
+    sqlx::query("SELECT pg_advisory_lock(hashtext('run_due_jobs'))")
+        .execute(&mut *runner)
+        .await
+        .unwrap();
+    assert_eq!(run_due_jobs(&db).await, 0);
+
+    sqlx::query("SELECT pg_advisory_unlock(hashtext('run_due_jobs'))")
+        .execute(&mut *runner)
+        .await
+        .unwrap();
+    // The game's own jobs, which haven't run yet
+    assert!(run_due_jobs(&db).await > 0);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn prunes_messages_without_pg_cron(db: PgPool) {
+    let command: String =
+        sqlx::query_scalar("SELECT command FROM jobs WHERE job_name='prune_messages'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(command, "SELECT prune_messages()");
+}
@@ -0,0 +1,320 @@
+mod common;
+
+use common::*;
+use sqlx::PgPool;
+
+const ROOM: &str = "
+#####
+#+++#
+#####
+";
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn registers_and_logs_in(db: PgPool) {
+    let unknown = sqlx::query_file!("sql/login.sql", "alice", "secret", false)
+        .fetch_optional(&db)
+        .await
+        .unwrap();
+    assert!(unknown.is_none());
+
+    let alice = sqlx::query_file!("sql/register.sql", "alice", "secret")
+        .fetch_one(&db)
+        .await
+        .unwrap()
+        .entity_id;
+
+    let tavern: i32 = sqlx::query_scalar("SELECT entity_id FROM rooms WHERE landing_zone")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(position(&db, alice).await, (1, 1, tavern));
+    assert_eq!(hp(&db, alice).await, 10);
+
+    let login = sqlx::query_file!("sql/login.sql", "alice", "secret", true)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(login.entity_id, alice);
+    assert!(login.token.is_some());
+
+    let wrong = sqlx::query_file!("sql/login.sql", "alice", "guess", true)
+        .fetch_optional(&db)
+        .await
+        .unwrap();
+    assert!(wrong.is_none());
+
+    let taken = sqlx::query_file!("sql/register.sql", "alice", "guess")
+        .fetch_optional(&db)
+        .await
+        .unwrap();
+    assert!(taken.is_none());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn sees_own_room_and_inventory(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+    let bob = player(&db, "bob", there, 1, 1).await;
+    let gold = thing(&db, "gold", here, 1, 1).await;
+    place(&db, gold, alice, 0, 0).await;
+    command(&db, bob, "move", Some((1, 0)), None).await;
+
+    let entities = sqlx::query_file!("sql/get_world_entities.sql", token(&db, alice).await)
+        .fetch_all(&db)
+        .await
+        .unwrap();
+
+    let ids = entities.iter().map(|e| e.entity_id).collect::<Vec<_>>();
+    assert!(ids.contains(&alice));
+    assert!(ids.contains(&gold));
+    assert!(!ids.contains(&bob));
+    // Just alice and her gold, the room's walls and floor are its terrain
+    assert_eq!(entities.len(), 2);
+    let me = entities.iter().find(|e| e.entity_id == alice).unwrap();
+    assert_eq!(me.name.as_deref(), Some("alice"));
+    assert_eq!(me.species.as_deref(), Some("human"));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn fetches_the_terrain_of_own_room(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, Some(1), "\n###\n#+#\n###\n").await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+
+    let (room_id, revision, rows) = terrain(&db, alice, None).await.unwrap();
+    assert_eq!(room_id, here);
+    assert_eq!(rows, ["#####", "#+++#", "#####"]);
+    assert_eq!(terrain(&db, alice, Some((here, revision))).await, None);
+
+    place(&db, alice, there, 1, 1).await;
+    let (room_id, _, rows) = terrain(&db, alice, Some((here, revision))).await.unwrap();
+    assert_eq!(room_id, there);
+    assert_eq!(rows, ["###", "#+#", "###"]);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn terrain_comes_again_when_the_room_is_rebuilt(db: PgPool) {
+    sqlx::query("SELECT admin.save_room('Cellar', $1)")
+        .bind(ROOM)
+        .execute(&db)
+        .await
+        .unwrap();
+    let cellar: i32 = sqlx::query_scalar("SELECT entity_id FROM names WHERE name='Cellar'")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    let alice = player(&db, "alice", cellar, 1, 1).await;
+    let (_, revision, _) = terrain(&db, alice, None).await.unwrap();
+
+    sqlx::query("SELECT admin.save_room('Cellar', '###\n#+#\n###')")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let (room_id, rebuilt, rows) = terrain(&db, alice, Some((cellar, revision))).await.unwrap();
+    assert_eq!(room_id, cellar);
+    assert!(rebuilt > revision);
+    assert_eq!(rows, ["###", "#+#", "###"]);
+    assert_eq!(terrain(&db, alice, Some((cellar, rebuilt))).await, None);
+}
+
+// The room, revision and rows of the player's terrain, if they aren't the room and revision given
+async fn terrain(
+    db: &PgPool,
+    entity_id: i32,
+    known: Option<(i32, i32)>,
+) -> Option<(i32, i32, Vec<String>)> {
+    sqlx::query_file!(
+        "sql/get_terrain.sql",
+        token(db, entity_id).await,
+        known.map(|k| k.0),
+        known.map(|k| k.1)
+    )
+    .fetch_optional(db)
+    .await
+    .unwrap()
+    .map(|t| (t.room_id, t.revision, t.rows))
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn tells_are_addressed_by_name(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let bob = player(&db, "Bob", room, 3, 1).await;
+
+    let delivered = tell(&db, alice, "bob", "hello").await;
+    assert!(delivered);
+    let recipients: Vec<Option<i32>> = sqlx::query_scalar("SELECT recipient FROM messages")
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(recipients, [Some(bob)]);
+
+    // Rooms have names, but can't be spoken to
+    assert!(!tell(&db, alice, "Tavern", "hello").await);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn tells_go_to_one_entity(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+    let bob = player(&db, "bob", there, 1, 1).await;
+    let name = |entity_id: i32, name: &'static str| {
+        sqlx::query("INSERT INTO names (entity_id, name) VALUES ($1, $2)")
+            .bind(entity_id)
+            .bind(name)
+            .execute(&db)
+    };
+    let impostor = thing(&db, "snake", here, 2, 1).await;
+    name(impostor, "Bob").await.unwrap();
+    let far_rat = thing(&db, "rat", there, 2, 1).await;
+    name(far_rat, "Rat").await.unwrap();
+    let near_rat = thing(&db, "rat", here, 3, 1).await;
+    name(near_rat, "Rat").await.unwrap();
+
+    // Players before anything else, then whoever is in the speaker's room
+    assert!(tell(&db, alice, "bob", "hello").await);
+    assert!(tell(&db, alice, "rat", "hello").await);
+    let recipients: Vec<Option<i32>> =
+        sqlx::query_scalar("SELECT recipient FROM messages ORDER BY message_id")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+    assert_eq!(recipients, [Some(bob), Some(near_rat)]);
+
+    let other_rat = thing(&db, "rat", here, 1, 1).await;
+    name(other_rat, "Rat").await.unwrap();
+    let token = token(&db, alice).await;
+    let ambiguous = sqlx::query_file!("sql/say.sql", token, "direct", "rat", "hello")
+        .fetch_one(&db)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        ambiguous.as_database_error().unwrap().message(),
+        "There is more than one called rat"
+    );
+}
+
+async fn tell(db: &PgPool, speaker: i32, recipient: &str, message: &str) -> bool {
+    say(db, speaker, "direct", Some(recipient), message).await
+}
+
+async fn chat(
+    db: &PgPool,
+    entity_id: i32,
+    before: Option<i64>,
+    after: Option<i64>,
+    limit: Option<i64>,
+) -> Vec<(String, String, String)> {
+    sqlx::query_file!(
+        "sql/get_chat.sql",
+        token(db, entity_id).await,
+        before,
+        after,
+        limit
+    )
+    .fetch_all(db)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|m| (m.sender, m.channel, m.message))
+    .collect()
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn chat_is_seen_by_who_it_was_meant_for(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+    let bob = player(&db, "bob", here, 2, 1).await;
+    let carol = player(&db, "carol", there, 1, 1).await;
+
+    say(&db, alice, "room", None, "in here").await;
+    say(&db, carol, "world", None, "everyone").await;
+    tell(&db, alice, "carol", "psst").await;
+
+    let to = |name: &str, channel: &str, message: &str| {
+        (name.to_owned(), channel.to_owned(), message.to_owned())
+    };
+    assert_eq!(
+        chat(&db, bob, None, None, None).await,
+        vec![
+            to("carol", "world", "everyone"),
+            to("alice", "room", "in here")
+        ]
+    );
+    assert_eq!(
+        chat(&db, carol, None, None, None).await,
+        vec![
+            to("alice", "direct", "psst"),
+            to("carol", "world", "everyone")
+        ]
+    );
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn chat_pages_back_from_the_oldest_line(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    for i in 0..5 {
+        sqlx::query(
+            "INSERT INTO messages (speaker, message, channel, sent_at)
+            VALUES ($1, $2, 'world', NOW() + $3 * INTERVAL '1 second')",
+        )
+        .bind(alice)
+        .bind(i.to_string())
+        .bind(i)
+        .execute(&db)
+        .await
+        .unwrap();
+    }
+
+    let newest = chat(&db, alice, None, None, Some(2)).await;
+    assert_eq!(
+        newest.iter().map(|m| m.2.as_str()).collect::<Vec<_>>(),
+        ["4", "3"]
+    );
+
+    let oldest_seen = message_id(&db, "3").await;
+    let older = chat(&db, alice, Some(oldest_seen), None, Some(2)).await;
+    assert_eq!(
+        older.iter().map(|m| m.2.as_str()).collect::<Vec<_>>(),
+        ["2", "1"]
+    );
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn chat_sent_together_is_not_skipped(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    // One statement, so every line has the same sent_at
+    sqlx::query(
+        "INSERT INTO messages (speaker, message, channel)
+        SELECT $1, m, 'world' FROM unnest(ARRAY['a', 'b', 'c']) m",
+    )
+    .bind(alice)
+    .execute(&db)
+    .await
+    .unwrap();
+
+    let newer = chat(&db, alice, None, Some(message_id(&db, "a").await), None).await;
+    assert_eq!(
+        newer.iter().map(|m| m.2.as_str()).collect::<Vec<_>>(),
+        ["c", "b"]
+    );
+    let older = chat(&db, alice, Some(message_id(&db, "c").await), None, Some(1)).await;
+    assert_eq!(
+        older.iter().map(|m| m.2.as_str()).collect::<Vec<_>>(),
+        ["b"]
+    );
+}
+
+async fn message_id(db: &PgPool, message: &str) -> i64 {
+    sqlx::query_scalar("SELECT message_id FROM messages WHERE message=$1")
+        .bind(message)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
@@ -0,0 +1,88 @@
+use chrono::DateTime;
+use mpdungeon2::{
+    recording::{self, Playback, RecordedEvent, Recorder},
+    state::{Message, State},
+};
+
+fn line(message_id: i64) -> Message {
+    Message {
+        sender: "alice".to_owned(),
+        receiver: None,
+        channel: "world".to_owned(),
+        message: message_id.to_string(),
+        sent_at: DateTime::from_timestamp(message_id, 0).unwrap(),
+        message_id,
+    }
+}
+
+fn state(chat: &[i64]) -> State {
+    State {
+        entities: vec![],
+        terrain: None,
+        self_entity_id: Some(1),
+        chat: chat.iter().copied().map(line).collect(),
+        chat_history_complete: false,
+        commands_submitted: 0,
+    }
+}
+
+// Records the states to a file named for the test, then plays them back and returns what was shown after each event along with
+// how many lines of chat the file held
+fn record_and_play(name: &str, states: &[State]) -> (Vec<State>, usize) {
+    let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+    let mut recorder = Recorder::create(&path).unwrap();
+    for state in states {
+        recorder
+            .record(RecordedEvent::State(state.clone()))
+            .unwrap();
+    }
+    let records = recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut recorded_lines = 0;
+    let mut playback = Playback::default();
+    let mut shown = state(&[]);
+    let mut frames = vec![];
+    for record in records {
+        match &record.event {
+            RecordedEvent::State(s) => recorded_lines += s.chat.len(),
+            RecordedEvent::Chat { older, newer } => recorded_lines += older.len() + newer.len(),
+            _ => {}
+        }
+        assert!(playback.apply(&mut shown, record.event).is_none());
+        frames.push(shown.clone());
+    }
+    (frames, recorded_lines)
+}
+
+#[test]
+fn chat_is_recorded_once_per_line() {
+    let states = [
+        state(&[3, 4]),
+        state(&[3, 4]),
+        state(&[3, 4, 5]),
+        // Paging back to older lines
+        state(&[1, 2, 3, 4, 5]),
+        state(&[1, 2, 3, 4, 5, 6]),
+    ];
+    let (frames, recorded_lines) = record_and_play("chat-once", &states);
+    assert_eq!(recorded_lines, 6);
+    assert!(frames.last() == states.last());
+    for state in &states {
+        assert!(frames.contains(state));
+    }
+}
+
+#[test]
+fn lines_committed_late_are_recorded_in_place() {
+    let states = [state(&[3, 4]), state(&[3, 4, 6]), state(&[3, 4, 5, 6])];
+    let (frames, recorded_lines) = record_and_play("chat-late", &states);
+    assert_eq!(recorded_lines, 4);
+    assert!(frames.last() == states.last());
+}
+
+#[test]
+fn unchanged_states_are_left_out() {
+    let (frames, _) = record_and_play("unchanged", &[state(&[]), state(&[]), state(&[])]);
+    assert_eq!(frames.len(), 1);
+}
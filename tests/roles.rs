@@ -0,0 +1,116 @@
+mod common;
+
+use common::*;
+use mpdungeon2::schema;
+use sqlx::{Executor, PgPool, postgres::PgPoolOptions};
+
+const ROOM: &str = "
+#####
+#+++#
+#####
+";
+
+// The test's database, as the role clients connect with. mpd_player has no password unless one
+// was given when it was created, so the connection switches to it instead of logging in as it.
+async fn as_player(db: &PgPool) -> PgPool {
+    PgPoolOptions::new()
+        .max_connections(1)
+        .after_connect(|conn, _| {
+            Box::pin(async move {
+                conn.execute("SET ROLE mpd_player").await?;
+                Ok(())
+            })
+        })
+        .connect_with((*db.connect_options()).clone())
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn players_can_only_call_the_api(db: PgPool) {
+    let player_db = as_player(&db).await;
+
+    let login = sqlx::query_file!("sql/register.sql", "alice", "secret")
+        .fetch_one(&player_db)
+        .await
+        .unwrap();
+    let entities = sqlx::query_file!("sql/get_world_entities.sql", login.token)
+        .fetch_all(&player_db)
+        .await
+        .unwrap();
+    assert!(entities.iter().any(|e| e.entity_id == login.entity_id));
+    assert!(schema::check(&player_db).await.is_ok());
+
+    assert!(
+        sqlx::query("SELECT hp FROM hps")
+            .execute(&player_db)
+            .await
+            .is_err()
+    );
+    assert!(
+        sqlx::query("UPDATE hps SET hp=1000 WHERE entity_id=$1")
+            .bind(login.entity_id)
+            .execute(&player_db)
+            .await
+            .is_err()
+    );
+    assert!(
+        sqlx::query("SELECT room_tick()")
+            .execute(&player_db)
+            .await
+            .is_err()
+    );
+    // Jobs are run by `mpdungeon2 jobs`, not whoever happens to be playing
+    assert!(
+        sqlx::query("SELECT run_due_jobs()")
+            .execute(&player_db)
+            .await
+            .is_err()
+    );
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn sessions_only_see_their_own_room_and_chat(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+    let bob = player(&db, "bob", there, 1, 1).await;
+    let carol = player(&db, "carol", there, 2, 1).await;
+    say(&db, bob, "room", None, "over here").await;
+    sqlx::query("INSERT INTO messages (speaker, recipient, message, channel) VALUES ($1, $2, 'psst', 'direct')")
+        .bind(bob)
+        .bind(carol)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    // What the API functions run as, acting for alice
+    let mut tx = db.begin().await.unwrap();
+    sqlx::query("SELECT set_config('mpd.session', $1, true)")
+        .bind(token(&db, alice).await)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    sqlx::query("SET LOCAL ROLE mpd_game")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+    let rooms: Vec<i32> = sqlx::query_scalar("SELECT DISTINCT room_id FROM positions")
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap();
+    assert_eq!(rooms, [here]);
+    let messages: i64 = sqlx::query_scalar("SELECT count(*) FROM messages")
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+    assert_eq!(messages, 0);
+    let impersonated = sqlx::query(
+        "INSERT INTO messages (speaker, message, channel) VALUES ($1, 'from bob', 'world')",
+    )
+    .bind(bob)
+    .execute(&mut *tx)
+    .await;
+    assert!(impersonated.is_err());
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::*;
+use sqlx::PgPool;
+
+const ROOM: &str = "
+#####
+#+++#
+#+++#
+#+++#
+#####
+";
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn moves_onto_floor(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+
+    command(&db, alice, "move", Some((1, 1)), None).await;
+
+    assert_eq!(position(&db, alice).await, (2, 2, room));
+    assert!(!has_command(&db, alice).await);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn walls_block_movement(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+
+    command(&db, alice, "move", Some((-1, 0)), None).await;
+
+    assert_eq!(position(&db, alice).await, (1, 1, room));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn waits_for_every_player_in_the_room(db: PgPool) {
+    let room = room(&db, None, ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let bob = player(&db, "bob", room, 3, 3).await;
+
+    command(&db, alice, "move", Some((1, 0)), None).await;
+    assert_eq!(position(&db, alice).await, (1, 1, room));
+    assert!(has_command(&db, alice).await);
+
+    command(&db, bob, "move", Some((-1, 0)), None).await;
+    assert_eq!(position(&db, alice).await, (2, 1, room));
+    assert_eq!(position(&db, bob).await, (2, 3, room));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn min_commands_ticks_without_everyone(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let bob = player(&db, "bob", room, 3, 3).await;
+
+    command(&db, alice, "move", Some((1, 0)), None).await;
+
+    assert_eq!(position(&db, alice).await, (2, 1, room));
+    assert_eq!(position(&db, bob).await, (3, 3, room));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn only_ticks_the_commanding_room(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, None, ROOM).await;
+    let alice = player(&db, "alice", here, 1, 1).await;
+    let bob = player(&db, "bob", there, 1, 1).await;
+    let carol = player(&db, "carol", there, 3, 3).await;
+
+    command(&db, bob, "move", Some((1, 0)), None).await;
+    command(&db, alice, "move", Some((1, 0)), None).await;
+
+    assert_eq!(position(&db, bob).await, (1, 1, there));
+    assert!(has_command(&db, bob).await);
+    assert!(!has_command(&db, carol).await);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn attacks_cost_a_hit_point(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let snake = monster(&db, "snake", 5, room, 2, 2).await;
+
+    command(&db, alice, "attack", None, Some(snake)).await;
+
+    assert_eq!(hp(&db, snake).await, 4);
+    // The snake was already next to her, so it bit back on the same tick
+    assert_eq!(hp(&db, alice).await, 9);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn monsters_close_in(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let snake = monster(&db, "snake", 5, room, 3, 3).await;
+
+    command(&db, alice, "move", Some((0, 0)), None).await;
+
+    assert_eq!(position(&db, snake).await, (2, 2, room));
+    assert_eq!(hp(&db, alice).await, 10);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn dead_monsters_stay_put(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let snake = monster(&db, "snake", 0, room, 2, 2).await;
+
+    command(&db, alice, "move", Some((0, 0)), None).await;
+
+    assert_eq!(position(&db, snake).await, (2, 2, room));
+    assert_eq!(hp(&db, alice).await, 10);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn the_dead_cannot_act(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    sqlx::query("UPDATE hps SET hp=0 WHERE entity_id=$1")
+        .bind(alice)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    command(&db, alice, "move", Some((1, 0)), None).await;
+
+    assert_eq!(position(&db, alice).await, (1, 1, room));
+    assert!(!has_command(&db, alice).await);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn picks_up_and_drops(db: PgPool) {
+    let room = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let gold = thing(&db, "gold", room, 1, 1).await;
+
+    command(&db, alice, "pickup", None, Some(gold)).await;
+    assert_eq!(position(&db, gold).await, (0, 0, alice));
+
+    command(&db, alice, "move", Some((1, 1)), None).await;
+    assert_eq!(position(&db, gold).await, (0, 0, alice));
+
+    command(&db, alice, "drop", None, Some(gold)).await;
+    assert_eq!(position(&db, gold).await, (2, 2, room));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn travels_through_portals(db: PgPool) {
+    let here = room(&db, Some(1), ROOM).await;
+    let there = room(&db, Some(1), ROOM).await;
+    let alice = player(&db, "alice", here, 2, 2).await;
+    let door = thing(&db, "door", here, 2, 2).await;
+    let stair = thing(&db, "upstair", there, 3, 1).await;
+    portal(&db, door, stair).await;
+
+    command(&db, alice, "travel", None, Some(door)).await;
+
+    assert_eq!(position(&db, alice).await, (3, 1, there));
+}
@@ -0,0 +1,300 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+
+use common::*;
+use sqlx::PgPool;
+
+// The room's terrain and then its entities, so anything standing on the floor is what's kept
+async fn tiles(db: &PgPool, room_id: i32) -> HashMap<(i16, i16), String> {
+    let tiles: Vec<(i16, i16, String)> = sqlx::query_as(
+        "SELECT t.x, t.y, t.species FROM rooms r, terrain_tiles(r.terrain) t WHERE r.entity_id=$1
+        UNION ALL
+        SELECT p.x, p.y, s.species FROM positions p
+        INNER JOIN species s ON s.entity_id=p.entity_id
+        WHERE p.room_id=$1",
+    )
+    .bind(room_id)
+    .fetch_all(db)
+    .await
+    .unwrap();
+    tiles.into_iter().map(|(x, y, s)| ((x, y), s)).collect()
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn templates_become_walls_and_floors(db: PgPool) {
+    let room = room(
+        &db,
+        Some(1),
+        "
+
+###
+#+#
+ ##
+",
+    )
+    .await;
+
+    let tiles = tiles(&db, room).await;
+    assert_eq!(tiles.len(), 8);
+    // Blank lines don't count towards y, spaces count towards x but make no tile
+    assert_eq!(tiles[&(0, 0)], "wall");
+    assert_eq!(tiles[&(1, 1)], "floor");
+    assert_eq!(tiles[&(1, 2)], "wall");
+    assert!(!tiles.contains_key(&(0, 2)));
+
+    // All of it is terrain, none of it entities
+    let terrain: Vec<String> = sqlx::query_scalar("SELECT terrain FROM rooms WHERE entity_id=$1")
+        .bind(room)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(terrain, ["###", "#+#", " ##"]);
+    let entities: i64 = sqlx::query_scalar("SELECT count(*) FROM positions WHERE room_id=$1")
+        .bind(room)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(entities, 0);
+
+    let blocked: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM rooms r, terrain_tiles(r.terrain) t
+        WHERE r.entity_id=$1 AND blocked(r.entity_id, t.x, t.y)",
+    )
+    .bind(room)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    assert_eq!(blocked, 7);
+}
+
+// The seed data's rooms were made of wall and floor entities before there was terrain
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn seeded_walls_and_floors_became_terrain(db: PgPool) {
+    let (tavern, terrain): (i32, Vec<String>) =
+        sqlx::query_as("SELECT entity_id, terrain FROM rooms WHERE landing_zone")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(terrain.len(), 7);
+    assert_eq!(terrain[0], "##########");
+    assert_eq!(terrain[1], "#++++++++#");
+    let tiles = tiles(&db, tavern).await;
+    assert_eq!(tiles[&(4, 6)], "door");
+
+    let left: i64 =
+        sqlx::query_scalar("SELECT count(*) FROM species WHERE species IN ('wall', 'floor')")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(left, 0);
+}
+
+// Whether every tile that isn't wall or void can be walked to from every other. Doors count as
+// walkable, they can be opened.
+fn connected(template: &str) -> bool {
+    let map = template
+        .split_once("\n---\n")
+        .map_or(template, |(_, map)| map);
+    let open = map
+        .lines()
+        .enumerate()
+        .flat_map(|(y, l)| {
+            l.chars()
+                .enumerate()
+                .filter(|(_, c)| *c != '#' && *c != ' ')
+                .map(move |(x, _)| (x as i32, y as i32))
+        })
+        .collect::<HashSet<_>>();
+    let mut reached = HashSet::new();
+    let mut stack = open.iter().take(1).copied().collect::<Vec<_>>();
+    while let Some((x, y)) = stack.pop() {
+        if !open.contains(&(x, y)) || !reached.insert((x, y)) {
+            continue;
+        }
+        stack.extend([(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+    }
+    !open.is_empty() && reached.len() == open.len()
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn generated_dungeons_are_walled_in(db: PgPool) {
+    let (room_id, template): (i32, String) =
+        sqlx::query_as("SELECT room_id, template FROM generate_dungeon(40, 20)")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+    assert_eq!(template.lines().count(), 20);
+    assert!(template.lines().all(|l| l.chars().count() == 40));
+
+    let tiles = tiles(&db, room_id).await;
+    let floors = tiles
+        .iter()
+        .filter(|(_, s)| *s == "floor")
+        .map(|(p, _)| *p)
+        .collect::<Vec<_>>();
+    assert!(!floors.is_empty());
+    for (x, y) in floors {
+        assert!(x > 0 && x < 39 && y > 0 && y < 19);
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            assert!(
+                tiles.contains_key(&(x + dx, y + dy)),
+                "floor at {},{} is open to the void",
+                x,
+                y
+            );
+        }
+    }
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn generated_dungeons_can_be_made_again_from_their_seed(db: PgPool) {
+    let generate = "SELECT room_id, template FROM generate_dungeon(40, 20, 4, 3, 8, $1)";
+    let (first, template): (i32, String) = sqlx::query_as(generate)
+        .bind(7_i64)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    let (second, again): (i32, String) = sqlx::query_as(generate)
+        .bind(7_i64)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_ne!(first, second);
+    assert_eq!(template, again);
+
+    let regenerated: String = sqlx::query_scalar("SELECT template FROM regenerate_dungeon($1)")
+        .bind(first)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(regenerated, template);
+
+    let (_, other): (i32, String) = sqlx::query_as(generate)
+        .bind(8_i64)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_ne!(other, template);
+}
+
+// Every floor tile of a generated dungeon can be walked to from every other
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn generated_dungeons_are_connected(db: PgPool) {
+    for seed in 1..=10_i64 {
+        let (template, corridors, rooms): (String, i32, i32) = sqlx::query_as(
+            "SELECT
+                template,
+                jsonb_array_length(debug_output->'corridors'),
+                jsonb_array_length(debug_output->'rooms')
+            FROM dungeon_layout(60, 30, 8, 3, 10, $1, 0.25)",
+        )
+        .bind(seed)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        assert!(connected(&template), "seed {}:\n{}", seed, template);
+
+        // A tree of rooms - 1 corridors and a quarter as many again for loops
+        let tree = rooms - 1;
+        assert_eq!(corridors, tree + (tree as f64 * 0.25).round() as i32);
+    }
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn caves_and_mazes_are_connected(db: PgPool) {
+    for layout in [
+        "cave_layout(60, 30, $1, 0.45, 4)",
+        "maze_layout(41, 21, $1)",
+    ] {
+        for seed in 1..=10_i64 {
+            let query = format!("SELECT template FROM {}", layout);
+            let template: String = sqlx::query_scalar(&query)
+                .bind(seed)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            assert!(
+                connected(&template),
+                "{} seed {}:\n{}",
+                layout,
+                seed,
+                template
+            );
+
+            let again: String = sqlx::query_scalar(&query)
+                .bind(seed)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            assert_eq!(template, again);
+        }
+    }
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn generated_levels_keep_their_type(db: PgPool) {
+    let (room_id, template): (i32, String) =
+        sqlx::query_as("SELECT room_id, template FROM generate_level('cave', 50, 25, 3)")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+    let level_type: Option<String> =
+        sqlx::query_scalar("SELECT level_type FROM rooms WHERE entity_id=$1")
+            .bind(room_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(level_type.as_deref(), Some("cave"));
+
+    let regenerated: String = sqlx::query_scalar("SELECT template FROM regenerate_dungeon($1)")
+        .bind(room_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(regenerated, template);
+
+    let unknown = sqlx::query("SELECT * FROM generate_level('swamp', 50, 25)")
+        .execute(&db)
+        .await;
+    assert!(unknown.is_err());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn vaults_are_stamped_into_open_space(db: PgPool) {
+    sqlx::query("DELETE FROM admin.vaults WHERE name != 'shrine'")
+        .execute(&db)
+        .await
+        .unwrap();
+    let (room_id, template): (i32, String) = sqlx::query_as(
+        "SELECT room_id, template FROM generate_level('cave', 60, 30, 2, '{\"vaults\": 1}')",
+    )
+    .fetch_one(&db)
+    .await
+    .unwrap();
+
+    assert!(template.contains('$'), "{}", template);
+    assert!(connected(&template), "{}", template);
+    let gold: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM positions p
+        INNER JOIN species s ON s.entity_id=p.entity_id
+        WHERE p.room_id=$1 AND s.species='gold'",
+    )
+    .bind(room_id)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    assert_eq!(gold, 1);
+
+    // No room for a vault in a maze, and no legend for one either
+    let maze: String = sqlx::query_scalar(
+        "SELECT template FROM level_layout('maze', 21, 11, 2, '{\"vaults\": 1}')",
+    )
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    assert!(!maze.contains("---"));
+}
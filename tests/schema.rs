@@ -0,0 +1,95 @@
+use mpdungeon2::schema::{self, SchemaError};
+use sqlx::PgPool;
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn accepts_a_migrated_database(db: PgPool) {
+    assert!(schema::check(&db).await.is_ok());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn refuses_missing_migrations(db: PgPool) {
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations)",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+
+    assert!(matches!(schema::check(&db).await, Err(SchemaError::Behind(m)) if m.len() == 1));
+}
+
+async fn run_migration_from_the_future(db: &PgPool) {
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99990101000000, 'from the future', true, '\\x00', 0)",
+    )
+    .execute(db)
+    .await
+    .unwrap();
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn accepts_newer_migrations_that_keep_the_api(db: PgPool) {
+    run_migration_from_the_future(&db).await;
+
+    assert!(schema::check(&db).await.is_ok());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn refuses_newer_migrations_that_change_the_api(db: PgPool) {
+    run_migration_from_the_future(&db).await;
+    sqlx::query(&format!(
+        "CREATE OR REPLACE FUNCTION api.version() RETURNS INTEGER AS 'SELECT {}' LANGUAGE SQL",
+        schema::API_VERSION + 1
+    ))
+    .execute(&db)
+    .await
+    .unwrap();
+
+    assert!(
+        matches!(schema::check(&db).await, Err(SchemaError::Ahead(v)) if v == [99990101000000])
+    );
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn refuses_modified_migrations(db: PgPool) {
+    sqlx::query("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = 20250424233112")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        schema::check(&db).await,
+        Err(SchemaError::Modified(_))
+    ));
+}
+
+#[sqlx::test(migrations = false)]
+async fn refuses_an_empty_database(db: PgPool) {
+    assert!(matches!(
+        schema::check(&db).await,
+        Err(SchemaError::Behind(_))
+    ));
+}
+
+#[test]
+fn keeps_the_checksums_of_the_migration_files() {
+    let files = sqlx::migrate!();
+    assert_eq!(files.iter().count(), schema::MIGRATOR.iter().count());
+    for (file, migration) in files.iter().zip(schema::MIGRATOR.iter()) {
+        assert_eq!(file.checksum, migration.checksum);
+    }
+    // While the statements that need pg_cron are swapped for ones that check for it
+    let init = schema::MIGRATOR.iter().next().unwrap();
+    assert!(!file_sql(&files, init.version).contains("pg_available_extensions"));
+    assert!(init.sql.contains("pg_available_extensions"));
+}
+
+fn file_sql(files: &sqlx::migrate::Migrator, version: i64) -> String {
+    files
+        .iter()
+        .find(|m| m.version == version)
+        .unwrap()
+        .sql
+        .to_string()
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::*;
+use sqlx::PgPool;
+
+const ROOM: &str = "
+#####
+#+++#
+#####
+";
+
+async fn login(db: &PgPool, name: &str, takeover: bool) -> Option<String> {
+    sqlx::query_file!("sql/login.sql", name, "password", takeover)
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .token
+}
+
+async fn heartbeat(db: &PgPool, token: &str) -> bool {
+    sqlx::query_file!("sql/heartbeat.sql", token)
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .alive
+}
+
+async fn logout(db: &PgPool, token: &str) -> bool {
+    sqlx::query_file!("sql/logout.sql", token)
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .ended
+}
+
+async fn go_quiet(db: &PgPool, entity_id: i32) {
+    sqlx::query("UPDATE sessions SET last_seen = NOW() - INTERVAL '1 minute' WHERE entity_id=$1")
+        .bind(entity_id)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn one_session_per_character(db: PgPool) {
+    let room = room(&db, None, ROOM).await;
+    player(&db, "alice", room, 1, 1).await;
+
+    assert_eq!(login(&db, "alice", false).await, None);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn takeover_ends_the_other_session(db: PgPool) {
+    let room = room(&db, None, ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let first = token(&db, alice).await;
+
+    let second = login(&db, "alice", true).await.unwrap();
+
+    assert_ne!(first, second);
+    assert!(!heartbeat(&db, &first).await);
+    assert!(heartbeat(&db, &second).await);
+    let accepted = sqlx::query_file!(
+        "sql/insert_command.sql",
+        first,
+        "move",
+        Some(1i16),
+        Some(0i16),
+        None::<i32>
+    )
+    .fetch_one(&db)
+    .await
+    .unwrap()
+    .accepted;
+    assert!(!accepted);
+    assert!(!has_command(&db, alice).await);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn quiet_sessions_can_be_replaced(db: PgPool) {
+    let room = room(&db, None, ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    go_quiet(&db, alice).await;
+
+    assert!(login(&db, "alice", false).await.is_some());
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn quiet_sessions_are_cleaned_up(db: PgPool) {
+    let room = room(&db, None, ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let bob = player(&db, "bob", room, 2, 1).await;
+    go_quiet(&db, alice).await;
+
+    let ended: i32 = sqlx::query_scalar("SELECT end_stale_sessions()")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    assert_eq!(ended, 1);
+    let left: Vec<i32> = sqlx::query_scalar("SELECT entity_id FROM sessions")
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(left, [bob]);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn logging_out_frees_the_character(db: PgPool) {
+    let room = room(&db, None, ROOM).await;
+    let alice = player(&db, "alice", room, 1, 1).await;
+    let first = token(&db, alice).await;
+
+    assert!(logout(&db, &first).await);
+    assert!(!logout(&db, &first).await);
+
+    assert!(!heartbeat(&db, &first).await);
+    assert!(login(&db, "alice", false).await.is_some());
+}
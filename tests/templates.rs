@@ -0,0 +1,310 @@
+mod common;
+
+use common::*;
+use mpdungeon2::template::{RoomTemplate, Tile};
+use sqlx::PgPool;
+
+const CELLAR: &str = "\
+#######
+#+S+<+#
+#+++++#
+###D###
+";
+
+async fn save(db: &PgPool, name: &str, template: &str) -> i32 {
+    sqlx::query_file_scalar!("sql/admin/save_room.sql", name, template)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+// The room as the editor loads it
+async fn load(db: &PgPool, name: &str) -> RoomTemplate {
+    let tiles = sqlx::query_file!("sql/admin/room_tiles.sql", name)
+        .fetch_all(db)
+        .await
+        .unwrap();
+    RoomTemplate::from_layers(tiles.iter().map(|t| (t.x, t.y, t.layer.as_str())))
+}
+
+// Where each portal out of the room leads, by room
+async fn portals_from(db: &PgPool, room: i32) -> Vec<(i16, i16, i32)> {
+    sqlx::query_as(
+        "SELECT start_p.x, start_p.y, end_p.room_id FROM portals
+        INNER JOIN positions start_p ON start_p.entity_id=start_entity_id
+        INNER JOIN positions end_p ON end_p.entity_id=end_entity_id
+        WHERE start_p.room_id=$1
+        ORDER BY 1, 2, 3",
+    )
+    .bind(room)
+    .fetch_all(db)
+    .await
+    .unwrap()
+}
+
+// The wall or floor of the room's terrain counts along with the entities there
+async fn species_at(db: &PgPool, room: i32, x: i16, y: i16) -> Vec<String> {
+    sqlx::query_scalar(
+        "SELECT t.species FROM rooms r, terrain_tiles(r.terrain) t
+        WHERE r.entity_id=$1 AND t.x=$2 AND t.y=$3
+        UNION ALL
+        SELECT s.species FROM positions p
+        INNER JOIN species s ON s.entity_id=p.entity_id
+        WHERE p.room_id=$1 AND p.x=$2 AND p.y=$3
+        ORDER BY 1",
+    )
+    .bind(room)
+    .bind(x)
+    .bind(y)
+    .fetch_all(db)
+    .await
+    .unwrap()
+}
+
+#[test]
+fn templates_print_as_they_were_read() {
+    let mut cellar = RoomTemplate::parse(CELLAR).unwrap();
+    assert_eq!(cellar.to_string(), CELLAR);
+    assert_eq!(cellar.get(3, 3), Some(Tile::Door));
+
+    cellar.set(1, 2, None);
+    cellar.set(5, 2, Some(Tile::Downstair));
+    assert_eq!(cellar.to_string(), "#######\n#+S+<+#\n# +++>#\n###D###\n");
+
+    assert!(RoomTemplate::parse("#?#").is_err());
+
+    let locked = "a = floor, door locked lock=cellar\n---\n#a#\n";
+    let mut cellar = RoomTemplate::parse(locked).unwrap();
+    assert_eq!(cellar.get(1, 0), Some(Tile::Floor));
+    assert_eq!(cellar.layers(1, 0), ["door locked lock=cellar"]);
+    assert_eq!(cellar.to_string(), locked);
+    cellar.set(1, 0, Some(Tile::Door));
+    assert_eq!(cellar.to_string(), "#D#\n");
+}
+
+#[test]
+fn rows_left_empty_would_be_lost() {
+    let mut cellar = RoomTemplate::parse(CELLAR).unwrap();
+    assert_eq!(cellar.blank_row(), None);
+    for x in 0..7 {
+        cellar.set(x, 1, None);
+    }
+    assert_eq!(cellar.blank_row(), Some(1));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn saves_new_rooms(db: PgPool) {
+    let cellar = save(&db, "Cellar", CELLAR).await;
+
+    assert_eq!(species_at(&db, cellar, 0, 0).await, ["wall"]);
+    assert_eq!(species_at(&db, cellar, 3, 3).await, ["door", "floor"]);
+    assert_eq!(species_at(&db, cellar, 4, 1).await, ["floor", "upstair"]);
+    let snake: i32 = sqlx::query_scalar(
+        "SELECT m.entity_id FROM monsters m
+        INNER JOIN positions p ON p.entity_id=m.entity_id
+        WHERE p.room_id=$1 AND p.x=2 AND p.y=1",
+    )
+    .bind(cellar)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    assert_eq!(hp(&db, snake).await, 5);
+    let walls: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM generate_series(0, 6) x, generate_series(0, 3) y
+        WHERE blocked($1, x, y)",
+    )
+    .bind(cellar)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    // The door starts closed
+    assert_eq!(walls, 18);
+    assert_eq!(load(&db, "Cellar").await.to_string(), CELLAR);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn saving_again_replaces_the_tiles_around_players(db: PgPool) {
+    let cellar = save(&db, "Cellar", CELLAR).await;
+    let alice = player(&db, "alice", cellar, 1, 2).await;
+    let smaller = "\
+#####
+#+>+#
+#####
+";
+
+    assert_eq!(save(&db, "Cellar", smaller).await, cellar);
+
+    assert_eq!(load(&db, "Cellar").await.to_string(), smaller);
+    assert_eq!(position(&db, alice).await, (1, 2, cellar));
+    let monsters: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM monsters")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    // Only the seed data's snakes are left
+    assert_eq!(monsters, 2);
+    let audited: Vec<String> = sqlx::query_scalar("SELECT action FROM admin.audit_log")
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(audited, ["save_room", "save_room"]);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn legends_place_anything_and_link_portals_by_label(db: PgPool) {
+    let cellar = save(
+        &db,
+        "Cellar",
+        "\
+< = floor, upstair portal=trapdoor
+$ = floor, gold weight=3
+s = floor, rat monster hp=2
+---
+#####
+#<$s#
+#####
+",
+    )
+    .await;
+    assert_eq!(species_at(&db, cellar, 2, 1).await, ["floor", "gold"]);
+    assert_eq!(species_at(&db, cellar, 3, 1).await, ["floor", "rat"]);
+
+    // Nothing to lead to until the other end has been made
+    let portals: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM portals")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    let kitchen = save(
+        &db,
+        "Kitchen",
+        "\
+T = floor, door portal=trapdoor
+---
+#T#
+",
+    )
+    .await;
+    let linked: Vec<(i32, i32)> = sqlx::query_as(
+        "SELECT start_p.room_id, end_p.room_id FROM portals
+        INNER JOIN positions start_p ON start_p.entity_id=start_entity_id
+        INNER JOIN positions end_p ON end_p.entity_id=end_entity_id
+        WHERE start_p.room_id IN ($1, $2)
+        ORDER BY start_p.room_id",
+    )
+    .bind(cellar)
+    .bind(kitchen)
+    .fetch_all(&db)
+    .await
+    .unwrap();
+    assert_eq!(linked, [(cellar, kitchen), (kitchen, cellar)]);
+    let added: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM portals")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(added - portals, 2);
+
+    let rat: (i32, i32) = sqlx::query_as(
+        "SELECT h.hp, h.maxhp FROM hps h
+        INNER JOIN positions p ON p.entity_id=h.entity_id
+        INNER JOIN monsters m ON m.entity_id=h.entity_id
+        WHERE p.room_id=$1 AND p.x=3 AND p.y=1",
+    )
+    .bind(cellar)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    assert_eq!(rat, (2, 2));
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn templates_refuse_what_they_cant_place(db: PgPool) {
+    for template in [
+        "#?#",
+        "x = floor, dragon=1\n---\n#x#",
+        "x = floor, spawn=dragon\n---\n#x#",
+    ] {
+        let saved = sqlx::query("SELECT admin.save_room('Cellar', $1)")
+            .bind(template)
+            .execute(&db)
+            .await;
+        assert!(saved.is_err(), "{}", template);
+    }
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn editing_keeps_what_the_editor_has_no_tile_for(db: PgPool) {
+    let kitchen = save(
+        &db,
+        "Kitchen",
+        "T = floor, door portal=trapdoor\n---\n#T#\n",
+    )
+    .await;
+    let cellar = save(
+        &db,
+        "Cellar",
+        "\
+< = floor, upstair portal=trapdoor
+L = floor, door locked lock=cellar
+r = floor, rat monster hp=2
+---
+#####
+#<+r#
+##L##
+",
+    )
+    .await;
+    sqlx::query(
+        "UPDATE hps SET hp=1 FROM positions p WHERE p.entity_id=hps.entity_id AND p.room_id=$1",
+    )
+    .bind(cellar)
+    .execute(&db)
+    .await
+    .unwrap();
+
+    let mut template = load(&db, "Cellar").await;
+    assert_eq!(template.layers(2, 2), ["door locked lock=cellar"]);
+    template.set(2, 1, Some(Tile::Downstair));
+    save(&db, "Cellar", &template.to_string()).await;
+
+    assert_eq!(species_at(&db, cellar, 2, 1).await, ["downstair", "floor"]);
+    assert_eq!(portals_from(&db, cellar).await, [(1, 1, kitchen)]);
+    assert_eq!(portals_from(&db, kitchen).await, [(1, 0, cellar)]);
+    let door: (bool, Option<String>) = sqlx::query_as(
+        "SELECT d.locked, d.lock FROM doors d
+        INNER JOIN positions p ON p.entity_id=d.entity_id
+        WHERE p.room_id=$1 AND p.x=2 AND p.y=2",
+    )
+    .bind(cellar)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    assert_eq!(door, (true, Some("cellar".to_owned())));
+    let rat: (String, i32) = sqlx::query_as(
+        "SELECT s.species, h.hp FROM monsters m
+        INNER JOIN species s ON s.entity_id=m.entity_id
+        INNER JOIN hps h ON h.entity_id=m.entity_id
+        INNER JOIN positions p ON p.entity_id=m.entity_id
+        WHERE p.room_id=$1 AND p.x=3 AND p.y=1",
+    )
+    .bind(cellar)
+    .fetch_one(&db)
+    .await
+    .unwrap();
+    // Left as it was rather than painted again
+    assert_eq!(rat, ("rat".to_owned(), 1));
+    assert_eq!(load(&db, "Cellar").await, template);
+}
+
+#[sqlx::test(migrator = "mpdungeon2::schema::MIGRATOR")]
+async fn saving_unchanged_keeps_portals_without_labels(db: PgPool) {
+    let tavern: i32 = sqlx::query_scalar("SELECT entity_id FROM rooms WHERE landing_zone")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    let portals = portals_from(&db, tavern).await;
+    assert!(!portals.is_empty());
+
+    let template = load(&db, "Tavern").await;
+    save(&db, "Tavern", &template.to_string()).await;
+
+    assert_eq!(portals_from(&db, tavern).await, portals);
+}